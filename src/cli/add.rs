@@ -0,0 +1,32 @@
+use super::RESERVED_COMMANDS;
+use crate::config::{self as kylefile_config, Run, Step, Task};
+use anyhow::{Result, anyhow, bail};
+
+pub fn run(name: &str, runs: &[String], desc: Option<&str>, deps: &[String]) -> Result<()> {
+    if name.is_empty() || name.contains([':', '.']) || name.contains(char::is_whitespace) {
+        bail!("'{name}' isn't a valid task name; ':' and '.' separate namespaces");
+    }
+    if RESERVED_COMMANDS.contains(&name) {
+        bail!("'{name}' is a kyle command, so `kyle {name}` couldn't run the task");
+    }
+    if runs.is_empty() && deps.is_empty() {
+        bail!("give the task a command with --run or a dependency with --dep");
+    }
+
+    let path = super::local_kylefile("add")?;
+    let task = Task {
+        desc: desc.unwrap_or_default().to_string(),
+        run: match runs {
+            [] => Run::default(),
+            [cmd] => cmd.as_str().into(),
+            cmds => Run::Steps(cmds.iter().cloned().map(Step::Command).collect()),
+        },
+        deps: deps.to_vec(),
+        ..Default::default()
+    };
+    kylefile_config::add_task(&path, name, &task)
+        .map_err(|e| anyhow!("Failed to add '{name}' to {}: {e}", path.display()))?;
+
+    println!("Added '{name}' to {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use crate::config::{self as kylefile_config, Diagnostic};
+use crate::output;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub fn run(file: Option<&str>) -> Result<()> {
+    let path = match file {
+        Some(file) => PathBuf::from(file),
+        None => kylefile_config::find_file(Path::new("")).context(super::NO_KYLEFILE)?,
+    };
+
+    let diagnostics = kylefile_config::check(&path)
+        .with_context(|| format!("Failed to check {}", path.display()))?;
+
+    if diagnostics.is_empty() {
+        println!("✓ {}: no problems found", path.display());
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(&path)?;
+    for diagnostic in &diagnostics {
+        print_diagnostic(&path, &content, diagnostic);
+    }
+
+    let count = diagnostics.len();
+    let noun = if count == 1 { "problem" } else { "problems" };
+    anyhow::bail!("{count} {noun} found in {}", path.display())
+}
+
+/// Print a diagnostic with its location and the offending source line
+fn print_diagnostic(path: &Path, content: &str, diagnostic: &Diagnostic) {
+    output::error(&diagnostic.message);
+
+    let Some(span) = &diagnostic.span else {
+        eprintln!("  --> {}\n", path.display());
+        return;
+    };
+
+    let start = span.start.min(content.len());
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[start..]
+        .find('\n')
+        .map_or(content.len(), |i| start + i);
+    let line_no = content[..start].matches('\n').count() + 1;
+    let column = content[line_start..start].chars().count() + 1;
+    let source_line = &content[line_start..line_end];
+
+    let width = content[start..span.end.clamp(start, line_end)]
+        .chars()
+        .count()
+        .max(1);
+    let gutter = " ".repeat(line_no.to_string().len());
+    let indent: String = content[line_start..start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    eprintln!("{gutter}--> {}:{line_no}:{column}", path.display());
+    eprintln!("{gutter} |");
+    eprintln!("{line_no} | {source_line}");
+    eprintln!("{gutter} | {indent}{}\n", "^".repeat(width));
+}
@@ -18,7 +18,7 @@ const BASH_COMPLETION: &str = r#"_kyle() {
     cur="${COMP_WORDS[COMP_CWORD]}"
     prev="${COMP_WORDS[COMP_CWORD-1]}"
 
-    local commands="init config version upgrade mcp completions help"
+    local commands="init import export check fmt add rm config version upgrade mcp completions schema help"
     local global_flags="-v --version -h --help"
 
     case "${prev}" in
@@ -49,10 +49,17 @@ _kyle() {
 
     commands=(
         'init:Create a new Kylefile'
+        'import:Convert another task runner file into a Kylefile'
+        'export:Generate another task runner file from the Kylefile'
+        'check:Validate the Kylefile and report problems'
+        'fmt:Rewrite the Kylefile in the canonical layout'
+        'add:Add a task to the Kylefile'
+        'rm:Remove a task from the Kylefile'
         'config:Configure kyle settings'
         'version:Print version'
         'upgrade:Upgrade kyle to the latest version'
         'completions:Generate shell completions'
+        'schema:Print the JSON Schema for Kylefiles'
         'help:Print help'
     )
 
@@ -97,10 +104,17 @@ end
 
 complete -c kyle -n __kyle_needs_command -a '(__kyle_tasks)' -d 'task'
 complete -c kyle -n __kyle_needs_command -a init -d 'Create a new Kylefile'
+complete -c kyle -n __kyle_needs_command -a import -d 'Convert another task runner file into a Kylefile'
+complete -c kyle -n __kyle_needs_command -a export -d 'Generate another task runner file from the Kylefile'
+complete -c kyle -n __kyle_needs_command -a check -d 'Validate the Kylefile and report problems'
+complete -c kyle -n __kyle_needs_command -a fmt -d 'Rewrite the Kylefile in the canonical layout'
+complete -c kyle -n __kyle_needs_command -a add -d 'Add a task to the Kylefile'
+complete -c kyle -n __kyle_needs_command -a rm -d 'Remove a task from the Kylefile'
 complete -c kyle -n __kyle_needs_command -a config -d 'Configure kyle settings'
 complete -c kyle -n __kyle_needs_command -a version -d 'Print version'
 complete -c kyle -n __kyle_needs_command -a upgrade -d 'Upgrade kyle to the latest version'
 complete -c kyle -n __kyle_needs_command -a completions -d 'Generate shell completions'
+complete -c kyle -n __kyle_needs_command -a schema -d 'Print the JSON Schema for Kylefiles'
 complete -c kyle -n __kyle_needs_command -a help -d 'Print help'
 
 complete -c kyle -n '__kyle_using_command config' -a 'list get set path'
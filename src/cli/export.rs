@@ -0,0 +1,60 @@
+use crate::config::{self as kylefile_config, ExportTarget, GENERATED, PackageManager, Source};
+use crate::output;
+use anyhow::{Context, Result, anyhow, bail};
+use std::fs;
+use std::path::Path;
+
+pub fn run(to: &str, inline: bool, output: Option<&str>) -> Result<()> {
+    let Some(target) = ExportTarget::from_name(to) else {
+        bail!(
+            "unknown export target '{to}' (expected {})",
+            ExportTarget::NAMES.join(", ")
+        );
+    };
+
+    let path = kylefile_config::find_file(Path::new("")).context(super::NO_KYLEFILE)?;
+    let (mut kylefile, source) = kylefile_config::load_for_conversion(&path)
+        .map_err(|e| anyhow!("Failed to load {}: {e}", path.display()))?;
+    if source != Source::Kylefile {
+        bail!(
+            "kyle export needs a Kylefile, found {}; run `kyle import` first",
+            path.display()
+        );
+    }
+
+    // Inlined scripts run their deps with the project's package manager
+    if target == ExportTarget::PackageJson {
+        let package_json = Path::new(output.unwrap_or("package.json"));
+        let pkg = fs::read_to_string(package_json)
+            .ok()
+            .and_then(|content| kylefile_config::parse_jsonc(&content).ok())
+            .unwrap_or_default();
+        let dir = package_json.parent().unwrap_or(Path::new(""));
+        kylefile.package_manager = Some(PackageManager::detect(&pkg, dir));
+    }
+
+    let exported = kylefile_config::export(&kylefile, target, inline)?;
+    for note in &exported.notes {
+        output::warn(note);
+    }
+
+    let Some(output) = output else {
+        print!("{}", exported.content);
+        return Ok(());
+    };
+
+    let content = match fs::read_to_string(output) {
+        Ok(existing) if target == ExportTarget::PackageJson => {
+            kylefile_config::merge_scripts(&existing, &exported.content)
+                .map_err(|e| anyhow!("Failed to update {output}: {e}"))?
+        }
+        Ok(existing) if !existing.contains(GENERATED) => {
+            bail!("{output} already exists and wasn't generated by kyle export")
+        }
+        _ => exported.content,
+    };
+    fs::write(output, content)?;
+
+    println!("\n  Exported {} tasks to {output}\n", kylefile.tasks.len());
+    Ok(())
+}
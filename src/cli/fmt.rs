@@ -0,0 +1,29 @@
+use crate::config as kylefile_config;
+use crate::settings;
+use anyhow::{Context, Result, anyhow, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub fn run(file: Option<&str>, check: bool, sort: bool) -> Result<()> {
+    let path = match file {
+        Some(file) => PathBuf::from(file),
+        None => kylefile_config::find_file(Path::new("")).context(super::NO_KYLEFILE)?,
+    };
+    if !kylefile_config::is_kylefile(&path) {
+        bail!("kyle fmt only formats Kylefiles, not {}", path.display());
+    }
+
+    let sort = sort || settings::get().sort_tasks;
+    let formatted = kylefile_config::reformat(&path, sort)
+        .map_err(|e| anyhow!("Failed to format {}: {e}", path.display()))?;
+
+    match formatted {
+        None => println!("✓ {}: already formatted", path.display()),
+        Some(_) if check => bail!("{} is not formatted; run `kyle fmt`", path.display()),
+        Some(formatted) => {
+            fs::write(&path, formatted)?;
+            println!("Formatted {}", path.display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use crate::config::{self as kylefile_config, Format, Source};
+use crate::output;
+use crate::settings;
+use anyhow::{Context, Result, anyhow, bail};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const KYLEFILE: &str = "Kylefile";
+
+pub fn run(from: Option<&str>, format: Option<&str>, force: bool) -> Result<()> {
+    let format_name = format
+        .map(String::from)
+        .unwrap_or_else(|| settings::get().default_format);
+    let Some(format) = Format::from_name(&format_name) else {
+        bail!("unknown format '{format_name}' (expected toml, yaml or json)");
+    };
+
+    // Any Kylefile counts, since a new extensionless one would take its place
+    let existing =
+        kylefile_config::find_file(Path::new("")).filter(|path| kylefile_config::is_kylefile(path));
+    if let Some(existing) = &existing
+        && !force
+    {
+        bail!(
+            "{} already exists (use --force to write {KYLEFILE} anyway)",
+            existing.display()
+        );
+    }
+
+    let path = match from {
+        Some(from) => PathBuf::from(from),
+        None => kylefile_config::find_file(Path::new(""))
+            .context("No task file found in current directory to import")?,
+    };
+
+    let (mut kylefile, source) = kylefile_config::load_for_conversion(&path)
+        .map_err(|e| anyhow!("Failed to load {}: {e}", path.display()))?;
+    if source == Source::Kylefile {
+        bail!("{} is already a Kylefile", path.display());
+    }
+
+    kylefile.version = Some(env!("CARGO_PKG_VERSION").to_string());
+    if kylefile.name.is_empty() {
+        kylefile.name = project_name();
+    }
+
+    let content = format.render(&kylefile)?;
+    fs::write(KYLEFILE, content)?;
+    if let Some(existing) = existing
+        && existing != Path::new(KYLEFILE)
+    {
+        output::warn(&format!(
+            "{KYLEFILE} now takes priority over {}",
+            existing.display()
+        ));
+    }
+
+    for warning in &kylefile.warnings {
+        output::warn(warning);
+    }
+    for (name, task) in &kylefile.tasks {
+        for note in &task.notes {
+            output::warn(&format!("{name}: {note}"));
+        }
+    }
+
+    let count = kylefile.tasks.len();
+    let noun = if count == 1 { "task" } else { "tasks" };
+    println!(
+        "\n  Imported {count} {noun} from {} into {KYLEFILE}\n",
+        path.display()
+    );
+    Ok(())
+}
+
+fn project_name() -> String {
+    env::current_dir()
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "project".to_string())
+}
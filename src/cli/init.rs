@@ -1,11 +1,17 @@
+use super::RESERVED_COMMANDS;
+use crate::config::{self as kylefile_config, Format, Includes, Kylefile, Task};
+use crate::namespace::discovery::{FileType, discover_namespaces, project_files};
 use crate::settings;
-use anyhow::Result;
+use anyhow::{Result, bail};
+use indexmap::IndexMap;
 use std::env;
 use std::fs;
 use std::io::{self, BufRead, Write};
+use std::path::Path;
 
 const KYLEFILE: &str = "Kylefile";
 const DEFAULT_PROJECT_NAME: &str = "project";
+const LISTED_TASKS: usize = 5;
 
 struct TaskDef {
     name: String,
@@ -13,9 +19,22 @@ struct TaskDef {
     run: String,
 }
 
-pub fn run(name: Option<&str>, format: Option<&str>) -> Result<()> {
+/// Tasks offered from one task file in the project root
+struct Suggestion {
+    file: String,
+    tasks: IndexMap<String, Task>,
+}
+
+pub fn run(name: Option<&str>, format: Option<&str>, yes: bool) -> Result<()> {
+    if let Some(path) = kylefile_config::find_file(Path::new(""))
+        && kylefile_config::is_kylefile(&path)
+    {
+        bail!("{} already exists", path.display());
+    }
+
     let name = match name {
         Some(n) => n.to_string(),
+        None if yes => default_name(),
         None => prompt_name()?,
     };
 
@@ -23,28 +42,131 @@ pub fn run(name: Option<&str>, format: Option<&str>) -> Result<()> {
         .map(String::from)
         .unwrap_or_else(|| settings::get().default_format);
 
-    let mut tasks = Vec::new();
-    if prompt_yn("Add a task?", true)? {
+    let cwd = env::current_dir()?;
+    let mut kylefile = Kylefile {
+        name,
+        version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        ..Default::default()
+    };
+
+    for suggestion in suggest(&cwd) {
+        let new: IndexMap<String, Task> = suggestion
+            .tasks
+            .into_iter()
+            .filter(|(name, _)| !kylefile.tasks.contains_key(name))
+            .collect();
+        if new.is_empty() {
+            continue;
+        }
+        let question = format!(
+            "Add {} task(s) from {} ({})?",
+            new.len(),
+            suggestion.file,
+            listed(new.keys())
+        );
+        if yes || prompt_yn(&question, true)? {
+            kylefile.tasks.extend(new);
+        }
+    }
+
+    let subprojects: Vec<(String, String)> = discover_namespaces(&cwd)
+        .into_iter()
+        .map(|ns| {
+            let dir = ns.path.strip_prefix(&cwd).unwrap_or(&ns.path);
+            (ns.alias, dir.to_string_lossy().replace('\\', "/"))
+        })
+        .collect();
+    if !subprojects.is_empty() {
+        let question = format!(
+            "Include {} subproject(s) ({})?",
+            subprojects.len(),
+            listed(subprojects.iter().map(|(alias, _)| alias))
+        );
+        if yes || prompt_yn(&question, true)? {
+            // Aliases are directories, or package names for JS workspace
+            // packages; both resolve as namespaces
+            kylefile.includes = Includes::Map(subprojects.into_iter().collect());
+        }
+    }
+
+    if !yes && prompt_yn("Add a task?", kylefile.tasks.is_empty())? {
         loop {
-            tasks.push(prompt_task()?);
+            let task = prompt_task()?;
+            if task.name.is_empty() {
+                break;
+            }
+            kylefile.tasks.insert(
+                task.name,
+                Task {
+                    desc: task.desc,
+                    run: task.run.into(),
+                    ..Default::default()
+                },
+            );
             if !prompt_yn("Add another task?", false)? {
                 break;
             }
         }
     }
 
-    let content = generate_kylefile(&format, &name, &tasks);
+    let content = generate_kylefile(&format, &kylefile)?;
     fs::write(KYLEFILE, &content)?;
 
     println!("\n  Created {KYLEFILE}\n");
     Ok(())
 }
 
-fn prompt_name() -> Result<String> {
-    let default_name = env::current_dir()
+/// Tasks from each task file in `dir`, in detection order. Tasks another tool
+/// defines are run through that tool, e.g. `npm run build`, so its file stays
+/// the source of truth.
+fn suggest(dir: &Path) -> Vec<Suggestion> {
+    project_files(dir)
+        .into_iter()
+        .filter(|(_, file_type)| *file_type != FileType::Kylefile)
+        .filter_map(|(path, _)| {
+            let (kylefile, source) = kylefile_config::load(&path.to_string_lossy()).ok()?;
+            let tasks = kylefile
+                .tasks
+                .into_iter()
+                // A task named like a built-in command could never run from the Kylefile
+                .filter(|(name, _)| !RESERVED_COMMANDS.contains(&name.as_str()))
+                .map(|(name, task)| {
+                    let task = match source.delegate(&name, kylefile.package_manager) {
+                        Some(cmd) => Task {
+                            desc: task.desc,
+                            run: cmd.into(),
+                            ..Default::default()
+                        },
+                        None => task,
+                    };
+                    (name, task)
+                })
+                .collect();
+            let file = path.file_name()?.to_string_lossy().into_owned();
+            Some(Suggestion { file, tasks })
+        })
+        .collect()
+}
+
+fn listed<'a>(names: impl ExactSizeIterator<Item = &'a String>) -> String {
+    let count = names.len();
+    let mut list: Vec<&str> = names.take(LISTED_TASKS).map(String::as_str).collect();
+    let more = format!("and {} more", count.saturating_sub(LISTED_TASKS));
+    if count > LISTED_TASKS {
+        list.push(&more);
+    }
+    list.join(", ")
+}
+
+fn default_name() -> String {
+    env::current_dir()
         .ok()
         .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
-        .unwrap_or_else(|| DEFAULT_PROJECT_NAME.to_string());
+        .unwrap_or_else(|| DEFAULT_PROJECT_NAME.to_string())
+}
+
+fn prompt_name() -> Result<String> {
+    let default_name = default_name();
 
     print!("  Project name [{default_name}]: ");
     io::stdout().flush()?;
@@ -103,60 +225,19 @@ fn prompt_task() -> Result<TaskDef> {
     })
 }
 
-fn generate_kylefile(format: &str, name: &str, tasks: &[TaskDef]) -> String {
-    if format == "yaml" {
-        generate_yaml(name, tasks)
-    } else {
-        generate_toml(name, tasks)
-    }
-}
-
-fn generate_yaml(name: &str, tasks: &[TaskDef]) -> String {
-    let mut out = String::new();
-
-    // Format header tells kyle how to parse extensionless Kylefile
-    out.push_str("# kyle: yaml\n");
-    out.push_str(&format!("version: \"{}\"\n", env!("CARGO_PKG_VERSION")));
-    out.push_str(&format!("name: {name}\n\ntasks:\n"));
-
-    if tasks.is_empty() {
-        out.push_str("  # example:\n");
-        out.push_str("  #   desc: An example task\n");
-        out.push_str("  #   run: echo hello\n");
-    }
-
-    for t in tasks {
-        out.push_str(&format!("  {}:\n", t.name));
-        if !t.desc.is_empty() {
-            out.push_str(&format!("    desc: {}\n", t.desc));
-        }
-        out.push_str(&format!("    run: {}\n", t.run));
-    }
-
-    out
-}
-
-fn generate_toml(name: &str, tasks: &[TaskDef]) -> String {
-    let mut out = String::new();
-
-    // Format header tells kyle how to parse extensionless Kylefile
-    out.push_str("# kyle: toml\n");
-    out.push_str(&format!("version = \"{}\"\n", env!("CARGO_PKG_VERSION")));
-    out.push_str(&format!("name = \"{name}\"\n"));
-
-    if tasks.is_empty() {
-        out.push_str("\n# [tasks.example]\n");
-        out.push_str("# desc = \"An example task\"\n");
-        out.push_str("# run = \"echo hello\"\n");
-    }
-
-    for t in tasks {
-        out.push_str(&format!("\n[tasks.{}]\n", t.name));
-        if !t.desc.is_empty() {
-            out.push_str(&format!("desc = \"{}\"\n", t.desc));
-        }
-        out.push_str(&format!("run = \"{}\"\n", t.run));
+fn generate_kylefile(format: &str, kylefile: &Kylefile) -> Result<String> {
+    let format = Format::from_name(format).unwrap_or(Format::Toml);
+    let mut out = format.render(kylefile)?;
+    if kylefile.tasks.is_empty() {
+        out.push_str(match format {
+            Format::Toml => {
+                "\n# [tasks.example]\n# desc = \"An example task\"\n# run = \"echo hello\"\n"
+            }
+            Format::Yaml => {
+                "\n# tasks:\n#   example:\n#     desc: An example task\n#     run: echo hello\n"
+            }
+            Format::Json => "",
+        });
     }
-
-    out
+    Ok(out)
 }
@@ -1,25 +1,43 @@
+mod add;
+mod check;
 mod completions;
 mod config;
+mod export;
+mod fmt;
+mod import;
 mod init;
+mod rm;
 mod upgrade;
 
-use crate::config::{self as kylefile_config, load_from_dir};
-use crate::namespace::discovery::{FileType, discover_namespaces};
+use crate::config::{
+    self as kylefile_config, GLOBAL_NAMESPACE, Source, load_from_dir, load_global,
+};
+use crate::namespace::discovery::{DiscoveredNamespace, FileType, discover_namespaces};
 use crate::namespace::{parse_task_ref, resolve_namespace};
 use crate::runner::Runner;
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 const VERSION: &str = concat!("v", env!("CARGO_PKG_VERSION"));
+const NO_KYLEFILE: &str =
+    "No Kylefile found in current directory.\n\n  Run 'kyle init' to create one.";
 
 pub const RESERVED_COMMANDS: &[&str] = &[
     "init",
+    "import",
+    "export",
+    "fmt",
+    "add",
+    "rm",
+    "check",
     "config",
     "version",
     "upgrade",
     "mcp",
     "completions",
+    "schema",
     "help",
 ];
 
@@ -43,6 +61,14 @@ pub struct Cli {
     #[arg(short = 'v', long = "version", action = clap::ArgAction::Version)]
     version: (),
 
+    /// Run Makefile, justfile, Taskfile, Rakefile and package.json tasks through their own tool
+    #[arg(long, overrides_with = "no_delegate")]
+    delegate: bool,
+
+    /// Run the commands kyle read from those files itself
+    #[arg(long, overrides_with = "delegate")]
+    no_delegate: bool,
+
     /// Print task names (used by completion scripts)
     #[arg(long, hide = true)]
     summary: bool,
@@ -63,6 +89,92 @@ enum Command {
         /// Use TOML format (default)
         #[arg(long)]
         toml: bool,
+
+        /// Use JSON format
+        #[arg(long)]
+        json: bool,
+
+        /// Accept every suggested task and subproject without prompting
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Convert another task runner file into a Kylefile
+    Import {
+        /// File to convert (defaults to the task file in the current directory)
+        #[arg(long, value_name = "FILE")]
+        from: Option<String>,
+
+        /// Output format: toml, yaml or json (defaults to the configured format)
+        #[arg(long, value_name = "FORMAT")]
+        format: Option<String>,
+
+        /// Write the Kylefile even if the project already has one
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Generate a Makefile, justfile, package.json scripts or Taskfile from the Kylefile
+    Export {
+        /// Target format: makefile, justfile, package-json or taskfile
+        #[arg(long, value_name = "TARGET")]
+        to: String,
+
+        /// Copy commands into the file instead of calling `kyle <task>`
+        #[arg(long)]
+        inline: bool,
+
+        /// File to write (defaults to stdout); scripts are merged into an existing package.json
+        #[arg(long, value_name = "FILE")]
+        output: Option<String>,
+    },
+
+    /// Validate the Kylefile and report problems
+    Check {
+        /// File to check (defaults to the task file in the current directory)
+        #[arg(value_name = "FILE")]
+        file: Option<String>,
+    },
+
+    /// Rewrite the Kylefile in the canonical layout, keeping comments
+    Fmt {
+        /// File to format (defaults to the Kylefile in the current directory)
+        #[arg(value_name = "FILE")]
+        file: Option<String>,
+
+        /// Fail if the file isn't formatted instead of rewriting it
+        #[arg(long)]
+        check: bool,
+
+        /// Sort tasks by name (also on with the `sort_tasks` setting)
+        #[arg(long)]
+        sort: bool,
+    },
+
+    /// Add a task to the Kylefile
+    Add {
+        /// Task name
+        #[arg(value_name = "NAME")]
+        name: String,
+
+        /// Command to run; repeat for several steps
+        #[arg(long = "run", value_name = "CMD")]
+        run: Vec<String>,
+
+        /// Description shown in the task list
+        #[arg(long, value_name = "TEXT")]
+        desc: Option<String>,
+
+        /// Task to run first; repeat for several
+        #[arg(long = "dep", value_name = "TASK")]
+        deps: Vec<String>,
+    },
+
+    /// Remove a task from the Kylefile
+    Rm {
+        /// Task name
+        #[arg(value_name = "NAME")]
+        name: String,
     },
 
     /// Configure kyle settings
@@ -89,6 +201,9 @@ enum Command {
         /// Shell type (bash, zsh, fish)
         shell: String,
     },
+
+    /// Print the JSON Schema for Kylefiles
+    Schema,
 }
 
 #[derive(Subcommand)]
@@ -124,16 +239,39 @@ pub fn run() -> Result<()> {
     }
 
     match cli.command {
-        Some(Command::Init { name, yaml, toml }) => {
+        Some(Command::Init {
+            name,
+            yaml,
+            toml,
+            json,
+            yes,
+        }) => {
             let format = if yaml {
                 Some("yaml")
             } else if toml {
                 Some("toml")
+            } else if json {
+                Some("json")
             } else {
                 None
             };
-            init::run(name.as_deref(), format)
+            init::run(name.as_deref(), format, yes)
         }
+        Some(Command::Import {
+            from,
+            format,
+            force,
+        }) => import::run(from.as_deref(), format.as_deref(), force),
+        Some(Command::Export { to, inline, output }) => export::run(&to, inline, output.as_deref()),
+        Some(Command::Check { file }) => check::run(file.as_deref()),
+        Some(Command::Fmt { file, check, sort }) => fmt::run(file.as_deref(), check, sort),
+        Some(Command::Add {
+            name,
+            run,
+            desc,
+            deps,
+        }) => add::run(&name, &run, desc.as_deref(), &deps),
+        Some(Command::Rm { name }) => rm::run(&name),
         Some(Command::Config { action }) => config::run(action),
         Some(Command::Version) => {
             println!("kyle {VERSION}");
@@ -148,14 +286,25 @@ pub fn run() -> Result<()> {
             }
         }
         Some(Command::Completions { shell }) => completions::run(&shell),
-        None => run_tasks(cli.task.as_deref(), &cli.args),
+        Some(Command::Schema) => {
+            println!("{}", kylefile_config::schema());
+            Ok(())
+        }
+        None => {
+            let delegate = if cli.delegate || cli.no_delegate {
+                cli.delegate
+            } else {
+                crate::settings::get().delegate
+            };
+            run_tasks(cli.task.as_deref(), &cli.args, delegate)
+        }
     }
 }
 
 fn print_summary() -> Result<()> {
     if let Ok((kf, _)) = kylefile_config::load("") {
-        for name in kf.tasks.keys() {
-            if !RESERVED_COMMANDS.contains(&name.as_str()) {
+        for (name, task) in &kf.tasks {
+            if !task.private && !RESERVED_COMMANDS.contains(&name.as_str()) {
                 println!("{name}");
             }
         }
@@ -163,35 +312,52 @@ fn print_summary() -> Result<()> {
     Ok(())
 }
 
-fn run_tasks(task: Option<&str>, args: &[String]) -> Result<()> {
+/// The Kylefile in the current directory, for commands that edit it
+fn local_kylefile(command: &str) -> Result<PathBuf> {
+    let path = kylefile_config::find_file(Path::new("")).context(NO_KYLEFILE)?;
+    if !kylefile_config::is_kylefile(&path) {
+        anyhow::bail!(
+            "kyle {command} only edits Kylefiles, not {}",
+            path.display()
+        );
+    }
+    Ok(path)
+}
+
+fn run_tasks(task: Option<&str>, args: &[String], delegate: bool) -> Result<()> {
     let cwd = std::env::current_dir().context("Failed to get current directory")?;
 
     match task {
         Some(task_input) => {
-            if let Ok((kf, _source)) = kylefile_config::load("")
+            if let Ok((kf, source)) = kylefile_config::load("")
                 && kf.tasks.contains_key(task_input)
             {
-                let mut runner = Runner::with_working_dir(kf, cwd.to_path_buf(), cwd.to_path_buf());
+                let mut runner = Runner::with_working_dir(kf, cwd.to_path_buf(), cwd.to_path_buf())
+                    .delegating(source, delegate);
                 return runner.run(task_input, args).map_err(Into::into);
             }
 
             let task_ref = parse_task_ref(task_input);
 
             if let Some(namespace) = &task_ref.namespace {
-                run_namespaced_task(&cwd, namespace, &task_ref.task_name, args)
+                run_namespaced_task(&cwd, namespace, &task_ref.task_name, args, delegate)
             } else {
-                run_local_task(&cwd, &task_ref.task_name, args)
+                run_local_task(&cwd, &task_ref.task_name, args, delegate)
             }
         }
         None => list_all_tasks(&cwd),
     }
 }
 
-fn run_local_task(cwd: &Path, task_name: &str, args: &[String]) -> Result<()> {
-    let (kf, _source) = kylefile_config::load("")
-        .context("No Kylefile found in current directory.\n\n  Run 'kyle init' to create one.")?;
+fn run_local_task(cwd: &Path, task_name: &str, args: &[String], delegate: bool) -> Result<()> {
+    let (kf, source) = match kylefile_config::load("") {
+        Ok(loaded) => loaded,
+        Err(kylefile_config::Error::NotFound(_)) => anyhow::bail!(NO_KYLEFILE),
+        Err(e) => return Err(e.into()),
+    };
 
-    let mut runner = Runner::with_working_dir(kf, cwd.to_path_buf(), cwd.to_path_buf());
+    let mut runner = Runner::with_working_dir(kf, cwd.to_path_buf(), cwd.to_path_buf())
+        .delegating(source, delegate);
     runner.run(task_name, args)?;
     Ok(())
 }
@@ -201,17 +367,34 @@ fn run_namespaced_task(
     namespace: &str,
     task_name: &str,
     args: &[String],
+    delegate: bool,
 ) -> Result<()> {
+    if namespace == GLOBAL_NAMESPACE {
+        let kf = load_global()
+            .context("Failed to load global Kylefile")?
+            .with_context(|| {
+                format!(
+                    "No global Kylefile found in {}",
+                    crate::settings::dir().display()
+                )
+            })?;
+        let mut runner = Runner::with_working_dir(kf, root.to_path_buf(), root.to_path_buf())
+            .delegating(Source::Global, delegate);
+        runner.run(task_name, args)?;
+        return Ok(());
+    }
+
     let ns_dir = resolve_namespace(root, namespace);
 
     if !ns_dir.exists() {
         anyhow::bail!("Namespace directory not found: {}", ns_dir.display());
     }
 
-    let (kf, _source) = load_from_dir(&ns_dir)
+    let (kf, source) = load_from_dir(&ns_dir)
         .with_context(|| format!("Failed to load Kylefile from namespace '{namespace}'"))?;
 
-    let mut runner = Runner::with_working_dir(kf, ns_dir, root.to_path_buf());
+    let mut runner =
+        Runner::with_working_dir(kf, ns_dir, root.to_path_buf()).delegating(source, delegate);
     runner.run(task_name, args)?;
     Ok(())
 }
@@ -222,9 +405,25 @@ fn list_all_tasks(cwd: &Path) -> Result<()> {
 
     match local_result {
         Ok((kf, _source)) => {
-            println!("Available tasks:");
+            let hide_undocumented = crate::settings::get().hide_undocumented;
             let runner = Runner::new(kf.clone());
-            runner.list_tasks();
+            let (global, local): (Vec<_>, Vec<_>) = kf.tasks.values().partition(|t| t.global);
+
+            if !local.is_empty() || global.is_empty() {
+                match kf.package_manager {
+                    Some(manager) => println!("Available tasks ({manager}):"),
+                    None => println!("Available tasks:"),
+                }
+                runner.list_tasks(hide_undocumented);
+            }
+
+            if !global.is_empty() {
+                if !local.is_empty() {
+                    println!();
+                }
+                println!("Global tasks:");
+                runner.list_global_tasks(hide_undocumented);
+            }
 
             // Show namespaces from explicit includes
             if !kf.includes.is_empty() {
@@ -237,35 +436,62 @@ fn list_all_tasks(cwd: &Path) -> Result<()> {
             // Discover additional namespaces
             let discovered = discover_namespaces(cwd);
             if !discovered.is_empty() {
-                println!("\nDiscovered namespaces:");
-                for ns in &discovered {
-                    if ns.file_type == FileType::Kylefile {
-                        println!("  {}:", ns.alias);
-                    } else {
-                        println!("  {}: ({})", ns.alias, ns.file_type);
-                    }
-                }
+                println!();
+                print_namespaces(&discovered);
             }
         }
-        Err(_) => {
+        Err(kylefile_config::Error::NotFound(_)) => {
             // No local Kylefile, just show discovered namespaces
             let discovered = discover_namespaces(cwd);
             if discovered.is_empty() {
-                anyhow::bail!(
-                    "No Kylefile found in current directory.\n\n  Run 'kyle init' to create one."
-                );
+                anyhow::bail!(NO_KYLEFILE);
             }
 
-            println!("Discovered namespaces:");
-            for ns in &discovered {
-                if ns.file_type == FileType::Kylefile {
-                    println!("  {}:", ns.alias);
-                } else {
-                    println!("  {}: ({})", ns.alias, ns.file_type);
-                }
-            }
+            print_namespaces(&discovered);
         }
+        Err(e) => return Err(e.into()),
     }
 
     Ok(())
 }
+
+/// Print discovered namespaces, with JS workspace packages grouped under the
+/// workspace pattern that declares them
+fn print_namespaces(discovered: &[DiscoveredNamespace]) {
+    let (members, others): (Vec<_>, Vec<_>) =
+        discovered.iter().partition(|ns| ns.workspace.is_some());
+
+    let print = |ns: &DiscoveredNamespace, indent: &str| {
+        if ns.file_type == FileType::Kylefile {
+            println!("{indent}{}:", ns.alias);
+        } else {
+            println!("{indent}{}: ({})", ns.alias, ns.file_type);
+        }
+    };
+
+    let grouped = !members.is_empty();
+    if grouped {
+        println!("Workspace packages:");
+        let mut groups: BTreeMap<&str, Vec<&DiscoveredNamespace>> = BTreeMap::new();
+        for ns in members {
+            let pattern = ns.workspace.as_deref().unwrap_or_default();
+            groups.entry(pattern).or_default().push(ns);
+        }
+        for (pattern, packages) in groups {
+            println!("  {pattern}");
+            for ns in packages {
+                print(ns, "    ");
+            }
+        }
+    }
+
+    if !others.is_empty() {
+        if grouped {
+            println!();
+        }
+        println!("Discovered namespaces:");
+        for ns in others {
+            print(ns, "  ");
+        }
+    }
+}
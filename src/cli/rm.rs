@@ -0,0 +1,19 @@
+use crate::config as kylefile_config;
+use crate::output;
+use anyhow::{Result, anyhow};
+
+pub fn run(name: &str) -> Result<()> {
+    let path = super::local_kylefile("rm")?;
+    kylefile_config::remove_task(&path, name)
+        .map_err(|e| anyhow!("Failed to remove '{name}' from {}: {e}", path.display()))?;
+    println!("Removed '{name}' from {}", path.display());
+
+    // Leave the deps for the user to fix, but say where they are
+    let (kylefile, _) = kylefile_config::load(&path.to_string_lossy())?;
+    for (task, def) in &kylefile.tasks {
+        if def.deps.iter().any(|dep| dep == name) {
+            output::warn(&format!("'{task}' still depends on '{name}'"));
+        }
+    }
+    Ok(())
+}
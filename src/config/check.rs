@@ -0,0 +1,698 @@
+use super::Error;
+use super::kylefile::{Kylefile, Step};
+use super::loader::{self, GLOBAL_NAMESPACE, Source};
+use crate::cli::RESERVED_COMMANDS;
+use crate::namespace::{parse_task_ref, resolve_namespace};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+pub(super) const KYLEFILE_KEYS: &[&str] = &[
+    "$schema", "name", "version", "extends", "includes", "vars", "env", "tasks",
+];
+pub(super) const TASK_KEYS: &[&str] = &[
+    "desc",
+    "group",
+    "private",
+    "params",
+    "confirm",
+    "run",
+    "deps",
+    "defer",
+    "finally",
+    "dir",
+    "env",
+    "vars",
+    "platforms",
+];
+pub(super) const STEP_KEYS: &[&str] = &["cmd", "echo", "ignore_error", "task", "args", "defer"];
+pub(super) const VAR_KEYS: &[&str] = &["sh"];
+
+/// Keys people reach for from other task runners, and what kyle calls them
+const KEY_HINTS: &[(&str, &str)] = &[
+    ("depends", "deps"),
+    ("depends_on", "deps"),
+    ("dependencies", "deps"),
+    ("description", "desc"),
+    ("summary", "desc"),
+    ("section", "group"),
+    ("category", "group"),
+    ("cmd", "run"),
+    ("cmds", "run"),
+    ("command", "run"),
+    ("commands", "run"),
+    ("script", "run"),
+    ("environment", "env"),
+    ("cwd", "dir"),
+    ("workdir", "dir"),
+    ("variables", "vars"),
+    ("include", "includes"),
+    ("platform", "platforms"),
+];
+
+/// A problem found by `kyle check`
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    /// Byte range in the checked file, when the problem can be pinned down
+    pub span: Option<Range<usize>>,
+}
+
+/// Validate a task file more strictly than loading it does: unknown keys,
+/// empty commands, references to tasks or namespaces that don't exist,
+/// dependency cycles and tasks shadowed by built-in commands.
+pub fn check(path: &Path) -> Result<Vec<Diagnostic>, Error> {
+    let content = fs::read_to_string(path)?;
+    let mut checker = Checker {
+        content: &content,
+        diagnostics: Vec::new(),
+    };
+
+    let (kylefile, source) = match loader::read_file(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            checker.load_error(e);
+            return Ok(checker.diagnostics);
+        }
+    };
+
+    if source == Source::Kylefile {
+        let value = loader::kylefile_format(path, &content)?.parse_value(&content)?;
+        checker.check_structure(&value);
+        checker.check_reserved(&kylefile);
+    }
+
+    for deprecated in &kylefile.deprecated {
+        let path: Vec<&str> = deprecated.path.iter().map(String::as_str).collect();
+        checker.report(deprecated.message.clone(), &path);
+    }
+
+    for warning in &kylefile.warnings {
+        checker.diagnostics.push(Diagnostic {
+            message: warning.clone(),
+            span: None,
+        });
+    }
+
+    let root = path.parent().unwrap_or(Path::new(""));
+    checker.check_references(&kylefile, root);
+    checker.check_cycles(&kylefile);
+
+    Ok(checker.diagnostics)
+}
+
+struct Checker<'a> {
+    content: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, message: String, path: &[&str]) {
+        let span = locate(self.content, path);
+        self.diagnostics.push(Diagnostic { message, span });
+    }
+
+    fn load_error(&mut self, err: Error) {
+        let span = match &err {
+            Error::Toml(e) => e.span(),
+            Error::Yaml(e) => e.location().map(|l| l.index()..l.index()),
+            Error::Json(e) => offset(self.content, e.line(), e.column()).map(|o| o..o),
+            Error::Extends { path, .. } => locate(self.content, &["extends", path]),
+            Error::VersionTooNew { .. } | Error::InvalidVersion(_) => {
+                locate(self.content, &["version"])
+            }
+            _ => None,
+        };
+        // toml renders its own snippet; keep just the message
+        let message = match &err {
+            Error::Toml(e) => format!("toml parse error: {}", e.message().trim()),
+            _ => err.to_string(),
+        };
+        self.diagnostics.push(Diagnostic { message, span });
+    }
+
+    fn check_structure(&mut self, root: &Value) {
+        let Some(root) = root.as_object() else {
+            self.report("expected a table of Kylefile settings".into(), &[]);
+            return;
+        };
+
+        self.unknown_keys(root, KYLEFILE_KEYS, &[], "the Kylefile");
+
+        if let Some(vars) = root.get("vars").and_then(Value::as_object) {
+            self.check_vars(vars, &["vars"]);
+        }
+
+        if let Some(tasks) = root.get("tasks").and_then(Value::as_object) {
+            for (name, task) in tasks {
+                if let Some(task) = task.as_object() {
+                    self.check_task(name, task);
+                }
+            }
+        }
+    }
+
+    fn check_task(&mut self, name: &str, task: &Map<String, Value>) {
+        let at = ["tasks", name];
+        self.unknown_keys(task, TASK_KEYS, &at, &format!("task '{name}'"));
+
+        let has_deps = task
+            .get("deps")
+            .and_then(Value::as_array)
+            .is_some_and(|deps| !deps.is_empty());
+        match task.get("run") {
+            Some(run) => self.check_run(name, run, &["tasks", name, "run"]),
+            None if !has_deps => {
+                self.report(format!("task '{name}' has no `run` and no `deps`"), &at);
+            }
+            None => {}
+        }
+
+        for key in ["defer", "finally"] {
+            if let Some(steps) = task.get(key).and_then(Value::as_array) {
+                for step in steps {
+                    self.check_step(name, step, &["tasks", name, key]);
+                }
+            }
+        }
+
+        if let Some(vars) = task.get("vars").and_then(Value::as_object) {
+            self.check_vars(vars, &["tasks", name, "vars"]);
+        }
+    }
+
+    fn check_run(&mut self, name: &str, run: &Value, path: &[&str]) {
+        match run {
+            Value::String(cmd) if cmd.trim().is_empty() => {
+                self.report(format!("task '{name}' has an empty `run`"), path);
+            }
+            Value::Array(steps) if steps.is_empty() => {
+                self.report(format!("task '{name}' has an empty `run`"), path);
+            }
+            Value::Array(steps) => {
+                for step in steps {
+                    self.check_step(name, step, path);
+                }
+            }
+            Value::Object(platforms) => {
+                for (spec, run) in platforms {
+                    let mut nested = path.to_vec();
+                    nested.push(spec);
+                    self.check_run(name, run, &nested);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_step(&mut self, name: &str, step: &Value, path: &[&str]) {
+        match step {
+            Value::String(cmd) if cmd.trim().is_empty() => {
+                self.report(format!("task '{name}' has an empty step"), path);
+            }
+            Value::Object(fields) => {
+                self.unknown_keys(fields, STEP_KEYS, path, &format!("a step of task '{name}'"));
+                if fields
+                    .get("cmd")
+                    .and_then(Value::as_str)
+                    .is_some_and(|cmd| cmd.trim().is_empty())
+                {
+                    self.report(format!("task '{name}' has an empty step"), path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_vars(&mut self, vars: &Map<String, Value>, path: &[&str]) {
+        for (name, var) in vars {
+            if let Some(fields) = var.as_object() {
+                let mut at = path.to_vec();
+                at.push(name);
+                self.unknown_keys(fields, VAR_KEYS, &at, &format!("variable '{name}'"));
+            }
+        }
+    }
+
+    fn unknown_keys(
+        &mut self,
+        fields: &Map<String, Value>,
+        allowed: &[&str],
+        path: &[&str],
+        context: &str,
+    ) {
+        for key in fields.keys() {
+            if allowed.contains(&key.as_str()) {
+                continue;
+            }
+            let hint = suggest(key, allowed)
+                .map(|s| format!(" (did you mean '{s}'?)"))
+                .unwrap_or_default();
+            let mut at = path.to_vec();
+            at.push(key);
+            self.report(format!("unknown key '{key}' in {context}{hint}"), &at);
+        }
+    }
+
+    /// Only a Kylefile's own tasks are worth flagging: a Makefile `help` target
+    /// is the usual convention there, and `make help` still runs it
+    fn check_reserved(&mut self, kylefile: &Kylefile) {
+        for (name, task) in &kylefile.tasks {
+            if task.origin.is_none() && RESERVED_COMMANDS.contains(&name.as_str()) {
+                self.report(
+                    format!(
+                        "task '{name}' shadows the built-in `kyle {name}` command and can never run"
+                    ),
+                    &["tasks", name],
+                );
+            }
+        }
+    }
+
+    fn check_references(&mut self, kylefile: &Kylefile, root: &Path) {
+        // Global tasks are merged into every project, so plain names may refer to them
+        let global = loader::load_global().ok().flatten();
+        let known = |name: &str| {
+            kylefile.tasks.contains_key(name)
+                || kylefile.unavailable.contains_key(name)
+                || global.as_ref().is_some_and(|g| g.tasks.contains_key(name))
+        };
+
+        let tasks: BTreeMap<_, _> = kylefile.tasks.iter().collect();
+        for (name, task) in tasks {
+            // Inherited tasks are checked in the file that defines them
+            if task.origin.is_some() {
+                continue;
+            }
+
+            let mut after_rest = false;
+            for param in task.params() {
+                let valid = param
+                    .name
+                    .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && param
+                        .name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    self.report(
+                        format!(
+                            "task '{name}' has an invalid parameter name '{}'",
+                            param.name
+                        ),
+                        &["tasks", name, "params"],
+                    );
+                } else if after_rest {
+                    self.report(
+                        format!(
+                            "task '{name}': parameter '{}' follows one that takes the rest of the arguments",
+                            param.name
+                        ),
+                        &["tasks", name, "params"],
+                    );
+                }
+                after_rest |= param.rest;
+            }
+
+            for dep in &task.deps {
+                let dep_ref = parse_task_ref(dep);
+                let problem = match dep_ref.namespace {
+                    _ if known(dep) => None,
+                    Some(ns) => {
+                        namespace_problem(dep, &ns, &dep_ref.task_name, root, global.as_ref())
+                    }
+                    None => Some(format!("undefined task '{dep}'")),
+                };
+                if let Some(problem) = problem {
+                    self.report(
+                        format!("task '{name}' depends on {problem}"),
+                        &["tasks", name, "deps", dep],
+                    );
+                }
+            }
+
+            let steps = task.run.steps();
+            let deferred = steps.iter().filter_map(|step| match step {
+                Step::Defer { defer } => Some(defer.as_ref()),
+                _ => None,
+            });
+            for step in steps.iter().chain(deferred).chain(&task.defer) {
+                let Step::Task { task: call, .. } = step else {
+                    continue;
+                };
+                if call.contains("{{") || known(call) {
+                    continue;
+                }
+                let call_ref = parse_task_ref(call);
+                let problem = match call_ref.namespace {
+                    Some(ns) => {
+                        namespace_problem(call, &ns, &call_ref.task_name, root, global.as_ref())
+                    }
+                    None => Some(format!("undefined task '{call}'")),
+                };
+                if let Some(problem) = problem {
+                    self.report(
+                        format!("task '{name}' calls {problem}"),
+                        &["tasks", name, call],
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_cycles(&mut self, kylefile: &Kylefile) {
+        let graph: BTreeMap<&str, Vec<&str>> = kylefile
+            .tasks
+            .iter()
+            .map(|(name, task)| {
+                let calls = task.run.steps();
+                let calls = calls.iter().filter_map(|step| match step {
+                    Step::Task { task, .. } => Some(task.as_str()),
+                    _ => None,
+                });
+                let edges = task
+                    .deps
+                    .iter()
+                    .map(String::as_str)
+                    .chain(calls)
+                    // Key the graph by the map's own names so edges outlive `calls`
+                    .filter_map(|next| kylefile.tasks.get_key_value(next))
+                    .map(|(next, _)| next.as_str())
+                    .collect();
+                (name.as_str(), edges)
+            })
+            .collect();
+
+        let mut done = HashSet::new();
+        for name in graph.keys() {
+            self.visit(name, &graph, &mut Vec::new(), &mut done);
+        }
+    }
+
+    fn visit<'g>(
+        &mut self,
+        name: &'g str,
+        graph: &BTreeMap<&'g str, Vec<&'g str>>,
+        stack: &mut Vec<&'g str>,
+        done: &mut HashSet<&'g str>,
+    ) {
+        if done.contains(name) {
+            return;
+        }
+        if let Some(start) = stack.iter().position(|n| *n == name) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(name);
+            self.report(
+                format!("circular dependency: {}", cycle.join(" → ")),
+                &["tasks", stack[start]],
+            );
+            return;
+        }
+
+        stack.push(name);
+        for next in graph.get(name).into_iter().flatten() {
+            self.visit(next, graph, stack, done);
+        }
+        stack.pop();
+        done.insert(name);
+    }
+}
+
+/// Describe why `reference` (`ns:task`) can't be resolved, mirroring how the runner looks it up
+fn namespace_problem(
+    reference: &str,
+    namespace: &str,
+    task: &str,
+    root: &Path,
+    global: Option<&Kylefile>,
+) -> Option<String> {
+    if reference.contains("{{") {
+        return None;
+    }
+
+    if namespace == GLOBAL_NAMESPACE {
+        return match global {
+            Some(global) if global.tasks.contains_key(task) => None,
+            Some(_) => Some(format!("undefined global task '{task}'")),
+            None => Some(format!("'{reference}', but there is no global Kylefile")),
+        };
+    }
+
+    let dir = resolve_namespace(root, namespace);
+    if !dir.exists() {
+        return Some(format!(
+            "'{reference}', but namespace '{namespace}' does not exist"
+        ));
+    }
+
+    match loader::load_from_dir(&dir) {
+        Ok((kf, _)) if kf.tasks.contains_key(task) || kf.unavailable.contains_key(task) => None,
+        Ok(_) => Some(format!(
+            "undefined task '{task}' in namespace '{namespace}'"
+        )),
+        Err(e) => Some(format!(
+            "'{reference}', but namespace '{namespace}' failed to load: {e}"
+        )),
+    }
+}
+
+/// Find where a key path appears in the source by looking for each segment
+/// in turn. Segments that can't be found (e.g. `tasks` in a Makefile) are
+/// skipped, so the result points at the deepest part that was found.
+fn locate(content: &str, path: &[&str]) -> Option<Range<usize>> {
+    let mut from = 0;
+    let mut found = None;
+    for segment in path {
+        if let Some(range) = find_token(content, segment, from) {
+            from = range.end;
+            found = Some(range);
+        }
+    }
+    found
+}
+
+/// Next occurrence of `token` at or after `from` that isn't part of a longer name
+fn find_token(content: &str, token: &str, from: usize) -> Option<Range<usize>> {
+    if token.is_empty() {
+        return None;
+    }
+    let is_name = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-');
+    content[from..]
+        .match_indices(token)
+        .map(|(i, _)| from + i)
+        .find(|&start| {
+            let end = start + token.len();
+            !content[..start].chars().next_back().is_some_and(is_name)
+                && !content[end..].chars().next().is_some_and(is_name)
+        })
+        .map(|start| start..start + token.len())
+}
+
+/// Byte offset of a 1-based line and column
+fn offset(content: &str, line: usize, column: usize) -> Option<usize> {
+    let line_start: usize = content
+        .split_inclusive('\n')
+        .take(line.checked_sub(1)?)
+        .map(str::len)
+        .sum();
+    Some((line_start + column.saturating_sub(1)).min(content.len()))
+}
+
+fn suggest<'a>(key: &str, allowed: &[&'a str]) -> Option<&'a str> {
+    let lower = key.to_ascii_lowercase();
+    if let Some((_, hint)) = KEY_HINTS.iter().find(|(from, _)| *from == lower) {
+        return allowed.iter().find(|k| *k == hint).copied();
+    }
+    allowed
+        .iter()
+        .copied()
+        .filter(|candidate| edit_distance(&lower, candidate) <= 2)
+        .min_by_key(|candidate| edit_distance(&lower, candidate))
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            let next = (row[j + 1] + 1).min(row[j] + 1).min(prev + cost);
+            prev = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use tempfile::TempDir;
+
+    fn check_str(name: &str, content: &str) -> Vec<String> {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join(name);
+        fs::write(&path, content).unwrap();
+        check(&path)
+            .unwrap()
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn known_keys_match_schema() {
+        let schema = serde_json::to_value(schemars::schema_for!(Kylefile)).unwrap();
+        // Property names of an object schema, or of every object variant of an untagged enum
+        let keys = |def: &Value| -> BTreeSet<String> {
+            let variants = match def["anyOf"].as_array() {
+                Some(variants) => variants.iter().collect(),
+                None => vec![def],
+            };
+            variants
+                .into_iter()
+                .filter_map(|v| v["properties"].as_object())
+                .flat_map(|props| props.keys().cloned())
+                .collect()
+        };
+        let listed = |list: &[&str], extra: &[&str]| -> BTreeSet<String> {
+            list.iter()
+                .filter(|key| !extra.contains(key))
+                .map(|key| key.to_string())
+                .collect()
+        };
+        let defs = &schema["$defs"];
+
+        // `$schema` is for editors and `finally` is a serde alias, so neither is in the schema
+        assert_eq!(keys(&schema), listed(KYLEFILE_KEYS, &["$schema"]));
+        assert_eq!(keys(&defs["Task"]), listed(TASK_KEYS, &["finally"]));
+        assert_eq!(keys(&defs["Step"]), listed(STEP_KEYS, &[]));
+        assert_eq!(keys(&defs["Var"]), listed(VAR_KEYS, &[]));
+    }
+
+    #[test]
+    fn unknown_key_with_hint() {
+        let problems = check_str(
+            "Kylefile.toml",
+            "[tasks.lint]\nrun = \"echo lint\"\n\n[tasks.build]\nrun = \"echo build\"\ndepends = [\"lint\"]\n",
+        );
+        assert_eq!(
+            problems,
+            vec!["unknown key 'depends' in task 'build' (did you mean 'deps'?)"]
+        );
+    }
+
+    #[test]
+    fn unknown_key_is_located() {
+        let content = "tasks:\n  build:\n    run: echo build\n    dsc: Build it\n";
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("Kylefile.yaml");
+        fs::write(&path, content).unwrap();
+
+        let diagnostics = check(&path).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("did you mean 'desc'?"));
+        let span = diagnostics[0].span.clone().unwrap();
+        assert_eq!(&content[span], "dsc");
+    }
+
+    #[test]
+    fn empty_run_and_missing_deps() {
+        let problems = check_str(
+            "Kylefile.toml",
+            "[tasks.a]\nrun = \"\"\n\n[tasks.b]\nrun = \"echo b\"\ndeps = [\"nope\", \"ghost:build\"]\n\n[tasks.c]\ndesc = \"nothing\"\n",
+        );
+        assert_eq!(
+            problems,
+            vec![
+                "task 'a' has an empty `run`",
+                "task 'c' has no `run` and no `deps`",
+                "task 'b' depends on undefined task 'nope'",
+                "task 'b' depends on 'ghost:build', but namespace 'ghost' does not exist",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_params() {
+        let problems = check_str(
+            "Kylefile.yaml",
+            "tasks:\n  a:\n    params: [\"*rest\", name, 9lives]\n    run: echo a\n",
+        );
+        assert_eq!(
+            problems,
+            vec![
+                "task 'a': parameter 'name' follows one that takes the rest of the arguments",
+                "task 'a' has an invalid parameter name '9lives'",
+            ]
+        );
+    }
+
+    #[test]
+    fn cycles_are_reported_once() {
+        let problems = check_str(
+            "Kylefile.yaml",
+            "tasks:\n  a:\n    run: echo a\n    deps: [b]\n  b:\n    run:\n      - task: a\n",
+        );
+        assert_eq!(problems, vec!["circular dependency: a → b → a"]);
+    }
+
+    #[test]
+    fn reserved_names() {
+        let problems = check_str("Kylefile.toml", "[tasks.init]\nrun = \"echo init\"\n");
+        assert_eq!(
+            problems,
+            vec!["task 'init' shadows the built-in `kyle init` command and can never run"]
+        );
+    }
+
+    #[test]
+    fn reserved_names_in_other_task_files_are_fine() {
+        let problems = check_str("Makefile", "help:\n\t@echo usage\n");
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn parse_errors_are_located() {
+        let content = "{\n  \"tasks\": {\n    \"a\": { \"run\": 1 }\n  }\n}\n";
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("Kylefile.json");
+        fs::write(&path, content).unwrap();
+
+        let diagnostics = check(&path).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].span.is_some());
+    }
+
+    #[test]
+    fn namespaced_deps_are_resolved() {
+        let temp = TempDir::new().unwrap();
+        fs::create_dir(temp.path().join("api")).unwrap();
+        fs::write(temp.path().join("api/Makefile"), "build:\n\techo api\n").unwrap();
+        let path = temp.path().join("Kylefile.toml");
+        fs::write(&path, "[tasks.all]\ndeps = [\"api:build\", \"api:test\"]\n").unwrap();
+
+        let problems: Vec<_> = check(&path)
+            .unwrap()
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            problems,
+            vec!["task 'all' depends on undefined task 'test' in namespace 'api'"]
+        );
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(suggest("depends", TASK_KEYS), Some("deps"));
+        assert_eq!(suggest("Description", TASK_KEYS), Some("desc"));
+        assert_eq!(suggest("platfroms", TASK_KEYS), Some("platforms"));
+        assert_eq!(suggest("zzzzzz", TASK_KEYS), None);
+    }
+}
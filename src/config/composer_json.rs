@@ -1,36 +1,133 @@
 use super::Error;
-use super::kylefile::{Kylefile, Task};
-use std::collections::HashMap;
+use super::jsonc;
+use super::kylefile::{Kylefile, Run, Step, Task};
+use indexmap::IndexMap;
+use regex::Regex;
+use std::sync::LazyLock;
 
+/// Scripts composer runs on its own events rather than by name
+const EVENTS: &[&str] = &[
+    "pre-install-cmd",
+    "post-install-cmd",
+    "pre-update-cmd",
+    "post-update-cmd",
+    "pre-status-cmd",
+    "post-status-cmd",
+    "pre-archive-cmd",
+    "post-archive-cmd",
+    "pre-autoload-dump",
+    "post-autoload-dump",
+    "post-root-package-install",
+    "post-create-project-cmd",
+    "pre-operations-exec",
+    "pre-package-install",
+    "post-package-install",
+    "pre-package-update",
+    "post-package-update",
+    "pre-package-uninstall",
+    "post-package-uninstall",
+    "init",
+    "command",
+    "pre-command-run",
+    "pre-file-download",
+    "post-file-download",
+    "pre-pool-create",
+];
+
+/// `Vendor\Class::method`, a PHP callback only composer can call
+static CALLBACK_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z_\\][\w\\]*::[A-Za-z_]\w*$").unwrap());
+
+/// The command that runs a script through composer itself
+fn composer_run(script: &str) -> String {
+    format!("composer run-script {script}")
+}
+
+/// Read composer scripts as tasks. `@script` references call the other task,
+/// `@php` and `@composer` run those binaries, and `scripts-descriptions`
+/// become task descriptions. Event hooks and PHP callbacks need composer, so
+/// those tasks run `composer run-script`.
 pub fn parse(content: &str) -> Result<Kylefile, Error> {
-    let pkg: serde_json::Value = serde_json::from_str(content)?;
+    let pkg: serde_json::Value = jsonc::parse(content)?;
     let name = pkg["name"].as_str().unwrap_or("").to_string();
-    let mut tasks = HashMap::new();
+    let mut tasks = IndexMap::new();
 
     if let Some(scripts) = pkg["scripts"].as_object() {
         for (key, val) in scripts {
-            // Skip composer lifecycle hooks
-            if key.starts_with("pre-") || key.starts_with("post-") {
+            let entries: Vec<&str> = match val {
+                serde_json::Value::String(s) => vec![s.as_str()],
+                serde_json::Value::Array(arr) => arr.iter().filter_map(|v| v.as_str()).collect(),
+                _ => continue,
+            };
+
+            let mut task = Task {
+                desc: pkg["scripts-descriptions"][key]
+                    .as_str()
+                    .unwrap_or("")
+                    .to_string(),
+                ..Default::default()
+            };
+
+            if EVENTS.contains(&key.as_str()) {
+                task.run = composer_run(key).into();
+                task.private = true;
+                task.notes
+                    .push("composer event hook; it runs through composer run-script".into());
+                tasks.insert(key.clone(), task);
                 continue;
             }
 
-            let cmd = match val {
-                serde_json::Value::String(s) => s.clone(),
-                serde_json::Value::Array(arr) => arr
-                    .iter()
-                    .filter_map(|v| v.as_str().map(String::from))
-                    .collect::<Vec<_>>()
-                    .join(" && "),
-                _ => continue,
-            };
+            let mut steps = Vec::new();
+            for entry in entries {
+                let entry = entry.trim();
+                if CALLBACK_RE.is_match(entry) {
+                    task.notes.push(format!(
+                        "calls the PHP callback {entry}; it runs through composer run-script"
+                    ));
+                    steps = vec![Step::Command(composer_run(key))];
+                    break;
+                }
+                let Some(reference) = entry.strip_prefix('@') else {
+                    steps.push(Step::Command(entry.to_string()));
+                    continue;
+                };
+                let (target, rest) = reference
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((reference, ""));
+                match target {
+                    "php" | "composer" => steps.push(Step::Command(
+                        format!("{target} {rest}").trim_end().to_string(),
+                    )),
+                    "putenv" => {
+                        if let Some((var, value)) = rest.trim().split_once('=') {
+                            task.env.insert(var.to_string(), value.to_string());
+                        }
+                    }
+                    _ => steps.push(Step::Task {
+                        task: target.to_string(),
+                        args: rest.split_whitespace().map(String::from).collect(),
+                    }),
+                }
+            }
 
-            tasks.insert(
-                key.clone(),
-                Task {
-                    run: cmd,
-                    ..Default::default()
-                },
-            );
+            // A script made only of references, like `["@lint", "@test"]`,
+            // runs them in order as dependencies
+            let only_calls = !steps.is_empty()
+                && steps
+                    .iter()
+                    .all(|step| matches!(step, Step::Task { args, .. } if args.is_empty()));
+            if only_calls {
+                task.deps = steps
+                    .into_iter()
+                    .filter_map(|step| match step {
+                        Step::Task { task, .. } => Some(task),
+                        _ => None,
+                    })
+                    .collect();
+            } else {
+                task.run = Run::from_steps(steps);
+            }
+            tasks.insert(key.clone(), task);
         }
     }
 
@@ -55,17 +152,69 @@ mod tests {
     }
 
     #[test]
-    fn skip_lifecycle_hooks() {
-        let content = r#"{"scripts": {"pre-install-cmd": "echo pre", "test": "phpunit", "post-update-cmd": "echo post"}}"#;
+    fn lifecycle_hooks_run_through_composer() {
+        let content = r#"{"scripts": {"pre-install-cmd": "echo pre", "test": "phpunit", "post-update-cmd": "@test", "pre-release": "echo custom"}}"#;
+        let kf = parse(content).unwrap();
+        assert_eq!(kf.tasks.len(), 4);
+        let hook = &kf.tasks["post-update-cmd"];
+        assert!(hook.private);
+        assert_eq!(hook.run, "composer run-script post-update-cmd");
+        assert_eq!(
+            hook.notes,
+            vec!["composer event hook; it runs through composer run-script"]
+        );
+        // Only composer's own events are hooks
+        assert!(!kf.tasks["pre-release"].private);
+        assert_eq!(kf.tasks["pre-release"].run, "echo custom");
+    }
+
+    #[test]
+    fn script_references() {
+        let content = r#"{
+            "scripts": {
+                "lint": "phpcs",
+                "test": ["@putenv XDEBUG_MODE=coverage", "@php vendor/bin/phpunit"],
+                "check": ["@lint", "@test"],
+                "ci": ["@composer validate", "@test --coverage", "echo done"],
+                "migrate": "App\\Console::migrate"
+            },
+            "scripts-descriptions": {"check": "Lint and test"}
+        }"#;
         let kf = parse(content).unwrap();
-        assert_eq!(kf.tasks.len(), 1);
-        assert!(kf.tasks.contains_key("test"));
+        assert_eq!(kf.tasks["test"].run, "php vendor/bin/phpunit");
+        assert_eq!(kf.tasks["test"].env["XDEBUG_MODE"], "coverage");
+
+        let check = &kf.tasks["check"];
+        assert_eq!(check.desc, "Lint and test");
+        assert_eq!(check.deps, vec!["lint", "test"]);
+        assert!(check.run.is_empty());
+
+        assert_eq!(
+            kf.tasks["ci"].run.steps(),
+            vec![
+                Step::Command("composer validate".into()),
+                Step::Task {
+                    task: "test".into(),
+                    args: vec!["--coverage".into()],
+                },
+                Step::Command("echo done".into()),
+            ]
+        );
+
+        let migrate = &kf.tasks["migrate"];
+        assert_eq!(migrate.run, "composer run-script migrate");
+        assert_eq!(
+            migrate.notes,
+            vec![
+                "calls the PHP callback App\\Console::migrate; it runs through composer run-script"
+            ]
+        );
     }
 
     #[test]
     fn parse_array_scripts() {
         let content = r#"{"scripts": {"check": ["phpcs", "phpstan"]}}"#;
         let kf = parse(content).unwrap();
-        assert_eq!(kf.tasks["check"].run, "phpcs && phpstan");
+        assert_eq!(kf.tasks["check"].run.commands(), vec!["phpcs", "phpstan"]);
     }
 }
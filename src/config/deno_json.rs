@@ -1,26 +1,58 @@
 use super::Error;
+use super::jsonc;
 use super::kylefile::{Kylefile, Task};
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use serde::Deserialize;
 
+/// A task as Deno 2 writes it: a command string, or an object with an
+/// optional command, a description and dependencies
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TaskDef {
+    Command(String),
+    Full {
+        #[serde(default)]
+        command: String,
+        #[serde(default)]
+        description: String,
+        #[serde(default)]
+        dependencies: Vec<String>,
+    },
+}
+
+/// Read deno.json or deno.jsonc tasks
 pub fn parse(content: &str) -> Result<Kylefile, Error> {
-    let deno: serde_json::Value = serde_json::from_str(content)?;
-    let mut tasks = HashMap::new();
+    let deno: serde_json::Value = jsonc::parse(content)?;
+    let name = deno["name"].as_str().unwrap_or("").to_string();
+    let mut tasks = IndexMap::new();
 
     if let Some(task_map) = deno["tasks"].as_object() {
         for (key, val) in task_map {
-            if let Some(cmd) = val.as_str() {
-                tasks.insert(
-                    key.clone(),
-                    Task {
-                        run: cmd.to_string(),
-                        ..Default::default()
-                    },
-                );
-            }
+            let Ok(def) = TaskDef::deserialize(val) else {
+                continue;
+            };
+            let task = match def {
+                TaskDef::Command(cmd) => Task {
+                    run: cmd.into(),
+                    ..Default::default()
+                },
+                TaskDef::Full {
+                    command,
+                    description,
+                    dependencies,
+                } => Task {
+                    desc: description,
+                    run: command.into(),
+                    deps: dependencies,
+                    ..Default::default()
+                },
+            };
+            tasks.insert(key.clone(), task);
         }
     }
 
     Ok(Kylefile {
+        name,
         tasks,
         ..Default::default()
     })
@@ -52,4 +84,30 @@ mod tests {
         let kf = parse(content).unwrap();
         assert!(kf.tasks.is_empty());
     }
+
+    #[test]
+    fn parse_jsonc_and_task_objects() {
+        let content = r#"{
+            // Deno 2 task objects
+            "name": "@acme/api",
+            "tasks": {
+                "build": {
+                    "description": "Build the app",
+                    "command": "deno compile main.ts",
+                    "dependencies": ["check"],
+                },
+                "check": "deno check main.ts",
+                "ci": {"dependencies": ["check", "build"]},
+            },
+        }"#;
+        let kf = parse(content).unwrap();
+        assert_eq!(kf.name, "@acme/api");
+        let build = &kf.tasks["build"];
+        assert_eq!(build.desc, "Build the app");
+        assert_eq!(build.run, "deno compile main.ts");
+        assert_eq!(build.deps, vec!["check"]);
+        assert_eq!(kf.tasks["check"].run, "deno check main.ts");
+        assert!(kf.tasks["ci"].run.is_empty());
+        assert_eq!(kf.tasks["ci"].deps, vec!["check", "build"]);
+    }
 }
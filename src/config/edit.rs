@@ -0,0 +1,413 @@
+use super::Error;
+use super::format::Format;
+use super::kylefile::Task;
+use super::layout::{self, Line};
+use super::loader;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Table};
+
+/// Add `task` to the Kylefile at `path`, leaving the rest of the file as written
+pub fn add_task(path: &Path, name: &str, task: &Task) -> Result<(), Error> {
+    edit(path, |content, format| {
+        add_text(content, format, name, task)
+    })
+}
+
+/// Remove a task from the Kylefile at `path`, along with the comments above it
+pub fn remove_task(path: &Path, name: &str) -> Result<(), Error> {
+    edit(path, |content, format| remove_text(content, format, name))
+}
+
+fn edit(
+    path: &Path,
+    apply: impl FnOnce(&str, Format) -> Result<String, Error>,
+) -> Result<(), Error> {
+    let content = fs::read_to_string(path)?;
+    let format = loader::kylefile_format(path, &content)?;
+    // Removing a task could drop an anchor another task's alias needs
+    layout::refuse_yaml_anchors(&content, format)?;
+    let edited = apply(&content, format)?;
+    fs::write(path, edited)?;
+    Ok(())
+}
+
+fn add_text(content: &str, format: Format, name: &str, task: &Task) -> Result<String, Error> {
+    if format.parse(content)?.tasks.contains_key(name) {
+        return Err(Error::TaskExists(name.to_string()));
+    }
+    let edited = match format {
+        Format::Toml => toml_add(content, name, task)?,
+        Format::Yaml => yaml_add(content, name, task)?,
+        Format::Json => json_edit(content, |tasks| {
+            let task = serde_json::to_value(task).map_err(|e| Error::Serialize(e.to_string()))?;
+            tasks.insert(name.to_string(), task);
+            Ok(())
+        })?,
+    };
+    // Never write a file kyle can no longer load
+    format.parse(&edited)?;
+    Ok(edited)
+}
+
+fn remove_text(content: &str, format: Format, name: &str) -> Result<String, Error> {
+    let kylefile = format.parse(content)?;
+    if !kylefile.tasks.contains_key(name) {
+        return Err(Error::TaskNotFound(name.to_string()));
+    }
+    let edited = match format {
+        Format::Toml => toml_remove(content, name)?,
+        Format::Yaml => yaml_remove(content, name, kylefile.tasks.len() == 1),
+        Format::Json => json_edit(content, |tasks| {
+            tasks.shift_remove(name);
+            Ok(())
+        })?,
+    };
+    format.parse(&edited)?;
+    Ok(edited)
+}
+
+fn toml_document(content: &str) -> Result<DocumentMut, Error> {
+    content
+        .parse()
+        .map_err(|e: toml_edit::TomlError| Error::Edit(format!("toml parse error: {e}")))
+}
+
+fn toml_add(content: &str, name: &str, task: &Task) -> Result<String, Error> {
+    let mut doc = toml_document(content)?;
+    let rendered = toml::to_string(task).map_err(|e| Error::Serialize(e.to_string()))?;
+    let mut table = toml_document(&rendered)?.as_table().clone();
+
+    if !doc.contains_key("tasks") {
+        let mut tasks = Table::new();
+        tasks.set_implicit(true);
+        doc.insert("tasks", Item::Table(tasks));
+    }
+    match &mut doc["tasks"] {
+        // `tasks = { build = { ... } }`
+        Item::Value(toml_edit::Value::InlineTable(tasks)) => {
+            tasks.insert(name, table.into_inline_table().into());
+        }
+        Item::Table(tasks) => {
+            // Match `[tasks]` tables that hold `build = { ... }` lines
+            if tasks.iter().any(|(_, item)| item.is_inline_table()) {
+                tasks.insert(name, toml_edit::value(table.into_inline_table()));
+            } else {
+                table.decor_mut().set_prefix("\n");
+                tasks.insert(name, Item::Table(table));
+            }
+        }
+        _ => return Err(Error::Edit("'tasks' isn't a table".into())),
+    }
+    Ok(doc.to_string())
+}
+
+fn toml_remove(content: &str, name: &str) -> Result<String, Error> {
+    let mut doc = toml_document(content)?;
+    if let Some(tasks) = doc["tasks"].as_table_like_mut() {
+        tasks.remove(name);
+    }
+    Ok(doc.to_string())
+}
+
+/// JSON has no comments to keep, so edit the tree and write it back out
+/// behind the original header
+fn json_edit(
+    content: &str,
+    apply: impl FnOnce(&mut Map<String, Value>) -> Result<(), Error>,
+) -> Result<String, Error> {
+    let mut value = Format::Json.parse_value(content)?;
+    let Some(kylefile) = value.as_object_mut() else {
+        return Err(Error::Edit("the Kylefile isn't an object".into()));
+    };
+    let tasks = kylefile
+        .entry("tasks")
+        .or_insert_with(|| Value::Object(Map::new()));
+    let Some(tasks) = tasks.as_object_mut() else {
+        return Err(Error::Edit("'tasks' isn't an object".into()));
+    };
+    apply(tasks)?;
+    if tasks.is_empty() {
+        kylefile.shift_remove("tasks");
+    }
+    layout::write(
+        &value,
+        Format::Json,
+        &layout::extract(content, Format::Json),
+    )
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Whether a classified line belongs under `prefix`
+fn is_under(line: &Line, prefix: &[&str]) -> bool {
+    let path = match line {
+        Line::Key { path, .. } => Some(path),
+        Line::Other { within, .. } => within.as_ref(),
+        _ => None,
+    };
+    path.is_some_and(|path| {
+        path.len() > prefix.len() && path.iter().zip(prefix).all(|(a, b)| a == b)
+    })
+}
+
+/// One past the last line of the block introduced at `start`: lines under
+/// `prefix`, plus comments indented deeper than `indent`. Comments at or
+/// left of `indent` belong to whatever comes next.
+fn block_end(raw: &[&str], lines: &[Line], start: usize, prefix: &[&str], indent: usize) -> usize {
+    let mut end = start + 1;
+    for (i, line) in lines.iter().enumerate().skip(start + 1) {
+        match line {
+            Line::Blank => {}
+            Line::Comment { .. } => {
+                if indent_of(raw[i]) > indent {
+                    end = i + 1;
+                }
+            }
+            _ if is_under(line, prefix) => end = i + 1,
+            _ => break,
+        }
+    }
+    end
+}
+
+fn join(raw: &[&str]) -> String {
+    let mut out = raw.join("\n");
+    out.push('\n');
+    out
+}
+
+fn yaml_add(content: &str, name: &str, task: &Task) -> Result<String, Error> {
+    let mut raw: Vec<&str> = content.lines().collect();
+    let lines = layout::lines(content, Format::Yaml);
+    let mut entry = Map::new();
+    entry.insert(
+        name.to_string(),
+        serde_json::to_value(task).map_err(|e| Error::Serialize(e.to_string()))?,
+    );
+    let rendered = serde_yml::to_string(&entry).map_err(|e| Error::Serialize(e.to_string()))?;
+
+    let Some(at) = lines
+        .iter()
+        .position(|line| matches!(line, Line::Key { path, .. } if path == &["tasks"]))
+    else {
+        // No tasks yet: start the block at the end of the file
+        if raw.last().is_some_and(|line| !line.trim().is_empty()) {
+            raw.push("");
+        }
+        let block = indented(&rendered, 2);
+        raw.push("tasks:");
+        raw.extend(block.lines());
+        return Ok(join(&raw));
+    };
+
+    let tasks_indent = indent_of(raw[at]);
+    let (key, value) = raw[at].split_once(':').unwrap_or((raw[at], ""));
+    let value = value.trim();
+    // `tasks: {}` can become a block; anything else inline is left to `kyle fmt`
+    let rest = value.strip_prefix("{}");
+    let tasks_line = format!("{key}:{}", rest.unwrap_or_default());
+    if rest.is_some_and(|rest| rest.is_empty() || rest.trim_start().starts_with('#')) {
+        raw[at] = tasks_line.trim_end();
+    } else if !value.is_empty() && !value.starts_with('#') {
+        return Err(Error::Edit(
+            "'tasks' is written inline; run `kyle fmt` first".into(),
+        ));
+    }
+
+    let task_keys: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| matches!(line, Line::Key { path, .. } if path.len() == 2 && path[0] == "tasks"))
+        .map(|(i, _)| i)
+        .collect();
+    let task_indent = task_keys
+        .first()
+        .map_or(tasks_indent + 2, |&i| indent_of(raw[i]));
+    // Keep blank lines between tasks if the file already has them
+    let spaced = match task_keys.get(1) {
+        Some(&second) => lines[..second]
+            .iter()
+            .rev()
+            .find(|line| !matches!(line, Line::Comment { .. }))
+            .is_some_and(|line| *line == Line::Blank),
+        None => !task_keys.is_empty(),
+    };
+
+    let end = block_end(&raw, &lines, at, &["tasks"], tasks_indent);
+    let block = indented(&rendered, task_indent);
+    let mut insert: Vec<&str> = Vec::new();
+    if spaced {
+        insert.push("");
+    }
+    insert.extend(block.lines());
+    raw.splice(end..end, insert);
+    Ok(join(&raw))
+}
+
+fn indented(text: &str, indent: usize) -> String {
+    let pad = " ".repeat(indent);
+    text.lines().map(|line| format!("{pad}{line}\n")).collect()
+}
+
+fn yaml_remove(content: &str, name: &str, last: bool) -> String {
+    let mut raw: Vec<&str> = content.lines().collect();
+    let lines = layout::lines(content, Format::Yaml);
+    let key = |path: &[&str]| {
+        lines
+            .iter()
+            .position(|line| matches!(line, Line::Key { path: p, .. } if p == path))
+    };
+
+    // Removing the last task takes the `tasks:` key with it
+    let (prefix, at) = match (last, key(&["tasks"])) {
+        (true, Some(at)) => (vec!["tasks"], at),
+        _ => (
+            vec!["tasks", name],
+            key(&["tasks", name]).unwrap_or_default(),
+        ),
+    };
+    let indent = indent_of(raw[at]);
+    let end = block_end(&raw, &lines, at, &prefix, indent);
+
+    // Comments directly above the task go with it
+    let mut start = at;
+    while start > 0
+        && matches!(lines[start - 1], Line::Comment { .. })
+        && indent_of(raw[start - 1]) == indent
+    {
+        start -= 1;
+    }
+    raw.drain(start..end);
+
+    // Don't leave two blank lines, or one at the end of the file or block
+    let blank = |line: Option<&&str>| line.is_none_or(|line| line.trim().is_empty());
+    if start > 0 && blank(raw.get(start - 1)) && blank(raw.get(start)) {
+        raw.remove(start - 1);
+    } else if start > 0
+        && raw[start - 1].trim_end().ends_with(':')
+        && blank(raw.get(start))
+        && start < raw.len()
+    {
+        raw.remove(start);
+    }
+    join(&raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task() -> Task {
+        Task {
+            desc: "Run \"tests\"".into(),
+            run: "cargo test".into(),
+            deps: vec!["build".into()],
+            ..Default::default()
+        }
+    }
+
+    const TOML: &str = "# kyle: toml\nname = \"demo\"\n\n# Compile\n[tasks.build]\nrun = \"cargo build\"  # release later\n\n[tasks.build.env]\nA = \"1\"\n\n# Lint it\n[tasks.lint]\nrun = \"cargo clippy\"\n";
+
+    const YAML: &str = "# kyle: yaml\nname: demo\n\ntasks:\n  # Compile\n  build:\n    run: |\n      cargo build\n\n      echo done\n    # still build\n\n  # Lint it\n  lint:\n    run: cargo clippy # strict\n\nvars:\n  a: b\n";
+
+    #[test]
+    fn toml_add_keeps_comments() {
+        let out = add_text(TOML, Format::Toml, "test", &task()).unwrap();
+        assert!(out.starts_with(TOML));
+        assert!(out.ends_with(
+            "run = \"cargo clippy\"\n\n[tasks.test]\ndesc = 'Run \"tests\"'\nrun = \"cargo test\"\ndeps = [\"build\"]\n"
+        ));
+        assert!(matches!(
+            add_text(&out, Format::Toml, "test", &task()),
+            Err(Error::TaskExists(_))
+        ));
+    }
+
+    #[test]
+    fn toml_add_matches_inline_tasks() {
+        let content = "[tasks]\nbuild = { run = \"make\" }  # fast\n";
+        let out = add_text(content, Format::Toml, "test", &task()).unwrap();
+        assert!(out.starts_with(content));
+        assert!(out.contains("test = {"));
+
+        let out = add_text("name = \"demo\"\n", Format::Toml, "test", &task()).unwrap();
+        assert!(out.contains("\n[tasks.test]\n"));
+    }
+
+    #[test]
+    fn toml_remove_takes_its_comments() {
+        let out = remove_text(TOML, Format::Toml, "build").unwrap();
+        assert_eq!(
+            out,
+            "# kyle: toml\nname = \"demo\"\n\n# Lint it\n[tasks.lint]\nrun = \"cargo clippy\"\n"
+        );
+        assert!(matches!(
+            remove_text(TOML, Format::Toml, "nope"),
+            Err(Error::TaskNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn yaml_add_after_last_task() {
+        let out = add_text(YAML, Format::Yaml, "test", &task()).unwrap();
+        assert!(out.contains(
+            "    run: cargo clippy # strict\n\n  test:\n    desc: Run \"tests\"\n    run: cargo test\n    deps:\n    - build\n\nvars:\n"
+        ));
+        assert!(out.starts_with(&YAML[..YAML.find("vars:").unwrap() - 1]));
+
+        let out = add_text("name: demo\n", Format::Yaml, "test", &task()).unwrap();
+        assert_eq!(
+            Format::Yaml.parse(&out).unwrap().tasks["test"].deps,
+            vec!["build"]
+        );
+
+        let out = add_text("tasks: {}\n", Format::Yaml, "test", &task()).unwrap();
+        assert!(out.starts_with("tasks:\n  test:\n"));
+        assert!(matches!(
+            add_text("tasks: { a: { run: x } }\n", Format::Yaml, "test", &task()),
+            Err(Error::Edit(_))
+        ));
+    }
+
+    #[test]
+    fn yaml_anchors_are_not_edited() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("Kylefile.yaml");
+        let content = "x-defaults: &d\n  dir: app\n\ntasks:\n  build:\n    <<: *d\n    run: make\n";
+        fs::write(&path, content).unwrap();
+
+        assert!(matches!(remove_task(&path, "build"), Err(Error::Edit(_))));
+        assert!(matches!(
+            add_task(&path, "lint", &task()),
+            Err(Error::Edit(_))
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+    }
+
+    #[test]
+    fn yaml_remove_keeps_the_rest() {
+        let out = remove_text(YAML, Format::Yaml, "build").unwrap();
+        assert_eq!(
+            out,
+            "# kyle: yaml\nname: demo\n\ntasks:\n  # Lint it\n  lint:\n    run: cargo clippy # strict\n\nvars:\n  a: b\n"
+        );
+        let out = remove_text(&out, Format::Yaml, "lint").unwrap();
+        assert_eq!(out, "# kyle: yaml\nname: demo\n\nvars:\n  a: b\n");
+    }
+
+    #[test]
+    fn json_edits_keep_header() {
+        let content = "# kyle: json\n{\n  \"name\": \"demo\"\n}\n";
+        let out = add_text(content, Format::Json, "test", &task()).unwrap();
+        assert!(out.starts_with("# kyle: json\n{"));
+        assert_eq!(
+            Format::Json.parse(&out).unwrap().tasks["test"].run,
+            "cargo test"
+        );
+        assert_eq!(remove_text(&out, Format::Json, "test").unwrap(), content);
+    }
+}
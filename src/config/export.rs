@@ -0,0 +1,534 @@
+use super::Error;
+use super::jsonc;
+use super::kylefile::{Kylefile, Run, Step, Task};
+use super::package_json::PackageManager;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::sync::LazyLock;
+
+/// First line of every generated file; `kyle export` only overwrites files that carry it
+pub const GENERATED: &str = "Generated by `kyle export` from the Kylefile; edit that instead.";
+
+static MAKE_TARGET_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9_][a-zA-Z0-9_\-\.]*$").unwrap());
+
+static JUST_RECIPE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-zA-Z_][a-zA-Z0-9_\-]*$").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Makefile,
+    Justfile,
+    PackageJson,
+    Taskfile,
+}
+
+impl Target {
+    pub const NAMES: &[&str] = &["makefile", "justfile", "package-json", "taskfile"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "makefile" | "make" => Some(Self::Makefile),
+            "justfile" | "just" => Some(Self::Justfile),
+            "package-json" | "package.json" | "npm" => Some(Self::PackageJson),
+            "taskfile" | "task" => Some(Self::Taskfile),
+            _ => None,
+        }
+    }
+
+    /// The file the target tool reads by default
+    pub fn filename(self) -> &'static str {
+        match self {
+            Self::Makefile => "Makefile",
+            Self::Justfile => "justfile",
+            Self::PackageJson => "package.json",
+            Self::Taskfile => "Taskfile.yml",
+        }
+    }
+
+    fn valid_name(self, name: &str) -> bool {
+        match self {
+            Self::Makefile => MAKE_TARGET_RE.is_match(name),
+            Self::Justfile => JUST_RECIPE_RE.is_match(name),
+            Self::PackageJson | Self::Taskfile => true,
+        }
+    }
+}
+
+/// A generated file, plus what didn't carry over for the caller to report
+pub struct Exported {
+    pub content: String,
+    pub notes: Vec<String>,
+}
+
+/// One task as the target tool will run it
+struct Recipe<'a> {
+    name: &'a str,
+    desc: &'a str,
+    /// Prerequisites for the target tool to run first; empty when delegating,
+    /// since `kyle <task>` runs the deps itself
+    deps: &'a [String],
+    /// `None` delegates to `kyle <task>`
+    lines: Option<Vec<Line>>,
+}
+
+struct Line {
+    cmd: String,
+    echo: bool,
+    ignore_error: bool,
+}
+
+/// Generate a file for `target` that runs the Kylefile's tasks. Each task
+/// delegates to `kyle <task>`, or with `inline` has its commands copied in;
+/// tasks that need kyle (vars, env, `dir`, deferred steps, per-platform
+/// commands, params, prompts, platform filters) delegate either way.
+/// Inlined package.json scripts run their deps with the Kylefile's
+/// `package_manager`, npm if unset.
+pub fn export(kylefile: &Kylefile, target: Target, inline: bool) -> Result<Exported, Error> {
+    let mut notes = Vec::new();
+    let mut recipes = Vec::new();
+
+    for (name, task) in &kylefile.tasks {
+        if !target.valid_name(name) {
+            notes.push(format!(
+                "skipped '{name}': not a valid {} name",
+                target.filename()
+            ));
+            continue;
+        }
+
+        let lines = if inline {
+            match inline_lines(kylefile, task) {
+                Ok(lines) => Some(lines),
+                Err(reason) => {
+                    notes.push(format!("'{name}' runs through kyle: it {reason}"));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        recipes.push(Recipe {
+            name,
+            desc: task.desc.lines().next().unwrap_or(""),
+            deps: if lines.is_some() { &task.deps } else { &[] },
+            lines,
+        });
+    }
+
+    let content = match target {
+        Target::Makefile => makefile(&recipes),
+        Target::Justfile => justfile(&recipes),
+        Target::Taskfile => taskfile(&recipes)?,
+        Target::PackageJson => package_json(
+            &recipes,
+            kylefile.package_manager.unwrap_or(PackageManager::Npm),
+        )?,
+    };
+    Ok(Exported { content, notes })
+}
+
+/// Set the exported scripts in an existing package.json, keeping everything else
+pub fn merge_scripts(package_json: &str, exported: &str) -> Result<String, Error> {
+    let mut package: Map<String, Value> = jsonc::parse(package_json)?;
+    let exported: Map<String, Value> = serde_json::from_str(exported)?;
+
+    let scripts = package
+        .entry("scripts")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if let (Value::Object(scripts), Some(Value::Object(new))) = (scripts, exported.get("scripts")) {
+        for (name, cmd) in new {
+            scripts.insert(name.clone(), cmd.clone());
+        }
+    }
+
+    let mut out = serde_json::to_string_pretty(&package).map_err(serialize_error)?;
+    out.push('\n');
+    Ok(out)
+}
+
+/// The task's commands for copying into another tool's file, or why it has to
+/// go through kyle instead
+fn inline_lines(kylefile: &Kylefile, task: &Task) -> Result<Vec<Line>, &'static str> {
+    if !kylefile.env.is_empty() || !task.env.is_empty() {
+        return Err("sets env");
+    }
+    if !task.vars.is_empty() {
+        return Err("has vars");
+    }
+    if task.dir.is_some() {
+        return Err("sets dir");
+    }
+    if !task.params.is_empty() {
+        return Err("takes params");
+    }
+    if task.confirm.is_some() {
+        return Err("asks for confirmation");
+    }
+    if !task.platforms.is_empty() {
+        return Err("only runs on some platforms");
+    }
+    if task.private {
+        return Err("is private");
+    }
+    if !task.defer.is_empty() {
+        return Err("has deferred steps");
+    }
+    if task.deps.iter().any(|dep| dep.contains(':')) {
+        return Err("depends on another namespace");
+    }
+    let steps = match &task.run {
+        Run::Platform(_) => return Err("has per-platform commands"),
+        run => run.steps(),
+    };
+
+    let mut lines = Vec::new();
+    for step in steps {
+        let line = match step {
+            Step::Command(cmd) => Line {
+                cmd,
+                echo: false,
+                ignore_error: false,
+            },
+            Step::Cmd {
+                cmd,
+                echo,
+                ignore_error,
+            } => Line {
+                cmd,
+                echo,
+                ignore_error,
+            },
+            Step::Task { task, args } => Line {
+                cmd: std::iter::once("kyle".to_string())
+                    .chain(std::iter::once(task))
+                    .chain(args.iter().map(|arg| shell_word(arg)))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                echo: false,
+                ignore_error: false,
+            },
+            Step::Defer { .. } => return Err("has deferred steps"),
+        };
+        if line.cmd.contains("{{") {
+            return Err("uses templates");
+        }
+        if line.cmd.trim().contains('\n') {
+            return Err("has multi-line commands");
+        }
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+fn shell_word(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c))
+    {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+fn makefile(recipes: &[Recipe]) -> String {
+    let mut out = format!("# {GENERATED}\n");
+
+    let names: Vec<&str> = recipes.iter().map(|r| r.name).collect();
+    if !names.is_empty() {
+        out.push_str(&format!("\n.PHONY: {}\n", names.join(" ")));
+    }
+
+    for recipe in recipes {
+        out.push('\n');
+        if !recipe.desc.is_empty() {
+            out.push_str(&format!("# {}\n", recipe.desc));
+        }
+        out.push_str(recipe.name);
+        out.push(':');
+        for dep in recipe.deps {
+            out.push(' ');
+            out.push_str(dep);
+        }
+        out.push('\n');
+        match &recipe.lines {
+            None => out.push_str(&format!("\t@kyle {}\n", recipe.name)),
+            Some(lines) => {
+                for line in lines {
+                    // Make expands `$` itself, so the shell's `$` is written `$$`
+                    let cmd = line.cmd.trim().replace('$', "$$");
+                    out.push_str(&format!("\t{}{cmd}\n", prefixes(line)));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn justfile(recipes: &[Recipe]) -> String {
+    let mut out = format!("# {GENERATED}\n");
+
+    for recipe in recipes {
+        out.push('\n');
+        if !recipe.desc.is_empty() {
+            out.push_str(&format!("# {}\n", recipe.desc));
+        }
+        out.push_str(recipe.name);
+        out.push(':');
+        for dep in recipe.deps {
+            out.push(' ');
+            out.push_str(dep);
+        }
+        out.push('\n');
+        match &recipe.lines {
+            None => out.push_str(&format!("    @kyle {}\n", recipe.name)),
+            Some(lines) => {
+                for line in lines {
+                    out.push_str(&format!("    {}{}\n", prefixes(line), line.cmd.trim()));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Make and just share `@` (don't echo) and `-` (ignore errors) recipe prefixes
+fn prefixes(line: &Line) -> String {
+    let mut prefixes = String::new();
+    if line.ignore_error {
+        prefixes.push('-');
+    }
+    if !line.echo {
+        prefixes.push('@');
+    }
+    prefixes
+}
+
+fn taskfile(recipes: &[Recipe]) -> Result<String, Error> {
+    let mut tasks = Map::new();
+    for recipe in recipes {
+        let mut task = Map::new();
+        if !recipe.desc.is_empty() {
+            task.insert("desc".into(), recipe.desc.into());
+        }
+        if !recipe.deps.is_empty() {
+            task.insert("deps".into(), recipe.deps.into());
+        }
+
+        let (cmds, silent) = match &recipe.lines {
+            None => (vec![Value::from(format!("kyle {}", recipe.name))], true),
+            Some(lines) => {
+                let cmds = lines
+                    .iter()
+                    .map(|line| {
+                        let cmd = line.cmd.trim();
+                        if line.ignore_error {
+                            serde_json::json!({ "cmd": cmd, "ignore_error": true })
+                        } else {
+                            cmd.into()
+                        }
+                    })
+                    .collect();
+                (cmds, lines.iter().all(|line| !line.echo))
+            }
+        };
+        if !cmds.is_empty() {
+            task.insert("cmds".into(), cmds.into());
+        }
+        if silent {
+            task.insert("silent".into(), true.into());
+        }
+        tasks.insert(recipe.name.into(), task.into());
+    }
+
+    let taskfile = serde_json::json!({ "version": "3", "tasks": tasks });
+    let yaml = serde_yml::to_string(&taskfile).map_err(serialize_error)?;
+    Ok(format!("# {GENERATED}\n{yaml}"))
+}
+
+/// npm scripts have no deps, so an inlined script runs its deps' scripts first
+fn package_json(recipes: &[Recipe], manager: PackageManager) -> Result<String, Error> {
+    let mut scripts = Map::new();
+    for recipe in recipes {
+        let script = match &recipe.lines {
+            None => format!("kyle {}", recipe.name),
+            Some(lines) => {
+                let parts: Vec<String> = recipe
+                    .deps
+                    .iter()
+                    .map(|dep| format!("{} {}", manager.run_command(), shell_word(dep)))
+                    .chain(lines.iter().map(|line| {
+                        let cmd = line.cmd.trim();
+                        if line.ignore_error {
+                            format!("({cmd} || true)")
+                        } else {
+                            cmd.to_string()
+                        }
+                    }))
+                    .collect();
+                if parts.is_empty() {
+                    format!("kyle {}", recipe.name)
+                } else {
+                    parts.join(" && ")
+                }
+            }
+        };
+        scripts.insert(recipe.name.into(), script.into());
+    }
+
+    let package = serde_json::json!({ "scripts": scripts });
+    let mut out = serde_json::to_string_pretty(&package).map_err(serialize_error)?;
+    out.push('\n');
+    Ok(out)
+}
+
+fn serialize_error(e: impl std::fmt::Display) -> Error {
+    Error::Serialize(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Format, makefile};
+    use std::path::Path;
+
+    fn kylefile() -> Kylefile {
+        Format::Toml
+            .parse(
+                r#"
+[tasks.build]
+desc = "Build it"
+run = [{ cmd = "cc -o app main.c", echo = true }, "echo $HOME"]
+deps = ["lint"]
+
+[tasks.lint]
+run = [{ cmd = "lint src", ignore_error = true }]
+
+[tasks.release]
+run = "echo {{ .VERSION }}"
+vars = { VERSION = "1.0" }
+
+[tasks."build:prod"]
+run = "make prod"
+
+[tasks.deploy]
+run = "./deploy.ps1"
+platforms = ["windows"]
+confirm = true
+"#,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn makefile_delegates_by_default() {
+        let exported = export(&kylefile(), Target::Makefile, false).unwrap();
+        assert!(exported.content.starts_with(&format!("# {GENERATED}\n")));
+        assert!(
+            exported
+                .content
+                .contains("# Build it\nbuild:\n\t@kyle build\n")
+        );
+        assert_eq!(
+            exported.notes,
+            vec!["skipped 'build:prod': not a valid Makefile name"]
+        );
+    }
+
+    #[test]
+    fn makefile_inlines_commands() {
+        let exported = export(&kylefile(), Target::Makefile, true).unwrap();
+        assert!(
+            exported
+                .content
+                .contains("# Build it\nbuild: lint\n\tcc -o app main.c\n\t@echo $$HOME\n")
+        );
+        assert!(exported.content.contains("lint:\n\t-@lint src\n"));
+        assert!(exported.content.contains("release:\n\t@kyle release\n"));
+        assert!(
+            exported
+                .notes
+                .contains(&"'release' runs through kyle: it has vars".to_string())
+        );
+        // A Windows-only task mustn't become a target that runs everywhere
+        assert!(exported.content.contains("deploy:\n\t@kyle deploy\n"));
+        assert!(
+            exported
+                .notes
+                .contains(&"'deploy' runs through kyle: it asks for confirmation".to_string())
+        );
+
+        // kyle reads the generated Makefile back the same way
+        let parsed = makefile::parse(&exported.content, Path::new("")).unwrap();
+        assert_eq!(parsed.tasks["build"].desc, "Build it");
+        assert_eq!(parsed.tasks["build"].deps, vec!["lint"]);
+    }
+
+    #[test]
+    fn justfile_inlines_commands() {
+        let exported = export(&kylefile(), Target::Justfile, true).unwrap();
+        assert!(
+            exported
+                .content
+                .contains("# Build it\nbuild: lint\n    cc -o app main.c\n    @echo $HOME\n")
+        );
+        assert!(exported.content.contains("release:\n    @kyle release\n"));
+    }
+
+    #[test]
+    fn taskfile_keeps_deps() {
+        let exported = export(&kylefile(), Target::Taskfile, true).unwrap();
+        let taskfile: Value = serde_yml::from_str(&exported.content).unwrap();
+        assert_eq!(taskfile["version"], "3");
+        assert_eq!(
+            taskfile["tasks"]["build"]["deps"],
+            serde_json::json!(["lint"])
+        );
+        assert_eq!(
+            taskfile["tasks"]["lint"]["cmds"],
+            serde_json::json!([{ "cmd": "lint src", "ignore_error": true }])
+        );
+        assert_eq!(
+            taskfile["tasks"]["build:prod"]["cmds"],
+            serde_json::json!(["make prod"])
+        );
+        assert_eq!(
+            taskfile["tasks"]["release"]["cmds"],
+            serde_json::json!(["kyle release"])
+        );
+    }
+
+    #[test]
+    fn package_json_runs_deps_first() {
+        let exported = export(&kylefile(), Target::PackageJson, true).unwrap();
+        let package: Value = serde_json::from_str(&exported.content).unwrap();
+        assert_eq!(
+            package["scripts"]["build"],
+            "npm run lint && cc -o app main.c && echo $HOME"
+        );
+        assert_eq!(package["scripts"]["lint"], "(lint src || true)");
+
+        let mut kylefile = kylefile();
+        kylefile.package_manager = Some(PackageManager::Pnpm);
+        let exported = export(&kylefile, Target::PackageJson, true).unwrap();
+        let package: Value = serde_json::from_str(&exported.content).unwrap();
+        assert_eq!(
+            package["scripts"]["build"],
+            "pnpm run lint && cc -o app main.c && echo $HOME"
+        );
+    }
+
+    #[test]
+    fn merge_scripts_keeps_other_fields() {
+        let existing = "{\n  // the app\n  \"name\": \"app\",\n  \"scripts\": {\"start\": \"node .\", \"build\": \"tsc\",},\n}";
+        let exported = export(&kylefile(), Target::PackageJson, false).unwrap();
+        let merged: Value =
+            serde_json::from_str(&merge_scripts(existing, &exported.content).unwrap()).unwrap();
+        assert_eq!(merged["name"], "app");
+        assert_eq!(merged["scripts"]["start"], "node .");
+        assert_eq!(merged["scripts"]["build"], "kyle build");
+    }
+}
@@ -0,0 +1,184 @@
+use super::Error;
+use super::kylefile::Kylefile;
+use super::loader::parse_kylefile;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Merge the Kylefiles listed in `extends` beneath `kylefile`.
+/// Later entries override earlier ones, and local definitions override all of them.
+pub fn resolve(kylefile: Kylefile, path: &Path) -> Result<Kylefile, Error> {
+    let mut chain = vec![canonical(path)];
+    resolve_chain(kylefile, path, &mut chain)
+}
+
+fn resolve_chain(
+    mut kylefile: Kylefile,
+    path: &Path,
+    chain: &mut Vec<PathBuf>,
+) -> Result<Kylefile, Error> {
+    if kylefile.extends.is_empty() {
+        return Ok(kylefile);
+    }
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut inherited = Kylefile::default();
+
+    for entry in &kylefile.extends {
+        let target = expand_path(entry, base_dir);
+        let target_key = canonical(&target);
+
+        if let Some(start) = chain.iter().position(|p| *p == target_key) {
+            let mut cycle: Vec<String> = chain[start..]
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect();
+            cycle.push(target_key.display().to_string());
+            return Err(Error::ExtendsCycle(cycle.join(" → ")));
+        }
+
+        let wrap = |e: Error| Error::Extends {
+            path: entry.clone(),
+            source: Box::new(e),
+        };
+        let content = fs::read_to_string(&target).map_err(|e| wrap(e.into()))?;
+        let parent = parse_kylefile(&target, &content).map_err(wrap)?;
+
+        chain.push(target_key.clone());
+        let mut parent = resolve_chain(parent, &target, chain)?;
+        chain.pop();
+
+        // Tasks inherited further up the chain keep their own origin
+        for task in parent.tasks.values_mut() {
+            task.origin.get_or_insert_with(|| target_key.clone());
+        }
+
+        inherited.tasks.extend(parent.tasks);
+        inherited.vars.extend(parent.vars);
+        inherited.env.extend(parent.env);
+        inherited.deprecated.extend(parent.deprecated);
+    }
+
+    for (name, task) in inherited.tasks {
+        kylefile.tasks.entry(name).or_insert(task);
+    }
+    for (name, var) in inherited.vars {
+        kylefile.vars.entry(name).or_insert(var);
+    }
+    for (name, value) in inherited.env {
+        kylefile.env.entry(name).or_insert(value);
+    }
+    kylefile.deprecated.extend(inherited.deprecated);
+
+    Ok(kylefile)
+}
+
+/// Resolve an `extends` entry relative to the file that lists it, expanding `~`
+fn expand_path(entry: &str, base_dir: &Path) -> PathBuf {
+    if let Some(rest) = entry.strip_prefix("~/")
+        && let Some(home) = dirs::home_dir()
+    {
+        return home.join(rest);
+    }
+    base_dir.join(entry)
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn load(path: &Path) -> Result<Kylefile, Error> {
+        let content = fs::read_to_string(path)?;
+        resolve(parse_kylefile(path, &content)?, path)
+    }
+
+    #[test]
+    fn inherits_tasks_vars_and_env() {
+        let temp = TempDir::new().unwrap();
+        fs::create_dir(temp.path().join("shared")).unwrap();
+        fs::write(
+            temp.path().join("shared/base.toml"),
+            "[vars]\nIMAGE = \"base\"\n\n[env]\nCI = \"1\"\n\n[tasks.lint]\nrun = \"echo lint\"\n\n[tasks.build]\nrun = \"echo base-build\"\n",
+        )
+        .unwrap();
+        let local = temp.path().join("Kylefile.toml");
+        fs::write(
+            &local,
+            "extends = [\"shared/base.toml\"]\n\n[tasks.build]\nrun = \"echo local-build\"\n",
+        )
+        .unwrap();
+
+        let kf = load(&local).unwrap();
+        assert_eq!(kf.tasks["build"].run, "echo local-build");
+        assert!(kf.tasks["build"].origin.is_none());
+        assert_eq!(kf.tasks["lint"].run, "echo lint");
+        assert_eq!(
+            kf.tasks["lint"].origin,
+            Some(canonical(&temp.path().join("shared/base.toml")))
+        );
+        assert!(kf.vars.contains_key("IMAGE"));
+        assert_eq!(kf.env["CI"], "1");
+    }
+
+    #[test]
+    fn later_extends_override_earlier() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("a.toml"), "[tasks.x]\nrun = \"echo a\"\n").unwrap();
+        fs::write(
+            temp.path().join("b.yaml"),
+            "tasks:\n  x:\n    run: echo b\n",
+        )
+        .unwrap();
+        let local = temp.path().join("Kylefile.toml");
+        fs::write(&local, "extends = [\"a.toml\", \"b.yaml\"]\n").unwrap();
+
+        let kf = load(&local).unwrap();
+        assert_eq!(kf.tasks["x"].run, "echo b");
+    }
+
+    #[test]
+    fn nested_extends_keep_origin() {
+        let temp = TempDir::new().unwrap();
+        fs::write(
+            temp.path().join("root.toml"),
+            "[tasks.fmt]\nrun = \"echo fmt\"\n",
+        )
+        .unwrap();
+        fs::write(temp.path().join("mid.toml"), "extends = [\"root.toml\"]\n").unwrap();
+        let local = temp.path().join("Kylefile.toml");
+        fs::write(&local, "extends = [\"mid.toml\"]\n").unwrap();
+
+        let kf = load(&local).unwrap();
+        assert_eq!(
+            kf.tasks["fmt"].origin,
+            Some(canonical(&temp.path().join("root.toml")))
+        );
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("a.toml"), "extends = [\"b.toml\"]\n").unwrap();
+        fs::write(temp.path().join("b.toml"), "extends = [\"a.toml\"]\n").unwrap();
+
+        let err = load(&temp.path().join("a.toml")).unwrap_err();
+        assert!(matches!(err, Error::ExtendsCycle(_)), "{err}");
+    }
+
+    #[test]
+    fn missing_file_names_the_entry() {
+        let temp = TempDir::new().unwrap();
+        let local = temp.path().join("Kylefile.toml");
+        fs::write(&local, "extends = [\"missing.toml\"]\n").unwrap();
+
+        let err = load(&local).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("failed to load extended Kylefile 'missing.toml'")
+        );
+    }
+}
@@ -1,10 +1,15 @@
 use super::Error;
+use super::jsonc;
 use super::kylefile::Kylefile;
+use super::layout::{self, Comments};
+use super::schema::URL as SCHEMA_URL;
+use serde_json::{Map, Value};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Format {
     Yaml,
     Toml,
+    Json,
 }
 
 impl Format {
@@ -12,6 +17,7 @@ impl Format {
         match self {
             Self::Yaml => "yaml",
             Self::Toml => "toml",
+            Self::Json => "json",
         }
     }
 
@@ -19,6 +25,7 @@ impl Format {
         match self {
             Self::Yaml => &[".yaml", ".yml"],
             Self::Toml => &[".toml"],
+            Self::Json => &[".json"],
         }
     }
 
@@ -27,6 +34,8 @@ impl Format {
             Some(Self::Yaml)
         } else if name.eq_ignore_ascii_case("toml") {
             Some(Self::Toml)
+        } else if name.eq_ignore_ascii_case("json") {
+            Some(Self::Json)
         } else {
             None
         }
@@ -36,6 +45,7 @@ impl Format {
         match ext {
             ".yaml" | ".yml" => Some(Self::Yaml),
             ".toml" => Some(Self::Toml),
+            ".json" => Some(Self::Json),
             _ => None,
         }
     }
@@ -44,6 +54,133 @@ impl Format {
         match self {
             Self::Yaml => serde_yml::from_str(content).map_err(Error::Yaml),
             Self::Toml => toml::from_str(content).map_err(Error::Toml),
+            Self::Json => jsonc::parse(&strip_header(content)).map_err(Error::Json),
         }
     }
+
+    /// Parse into an untyped tree, keeping keys serde would otherwise ignore
+    pub fn parse_value(self, content: &str) -> Result<serde_json::Value, Error> {
+        match self {
+            Self::Yaml => serde_yml::from_str(content).map_err(Error::Yaml),
+            Self::Toml => toml::from_str(content).map_err(Error::Toml),
+            Self::Json => jsonc::parse(&strip_header(content)).map_err(Error::Json),
+        }
+    }
+
+    /// Serialize a Kylefile in the canonical layout, behind the format header
+    /// so the result also works as an extensionless `Kylefile`. Task notes are
+    /// written as comments above the task; JSON has no comments, so they're
+    /// dropped there.
+    pub fn render(self, kylefile: &Kylefile) -> Result<String, Error> {
+        let mut value =
+            serde_json::to_value(kylefile).map_err(|e| Error::Serialize(e.to_string()))?;
+        let header = match self {
+            Self::Toml => vec![format!("#:schema {SCHEMA_URL}"), "# kyle: toml".into()],
+            Self::Yaml => vec![
+                "# kyle: yaml".into(),
+                format!("# yaml-language-server: $schema={SCHEMA_URL}"),
+            ],
+            Self::Json => vec!["# kyle: json".into()],
+        };
+        let mut comments = Comments {
+            header,
+            ..Default::default()
+        };
+        if let (Self::Json, Value::Object(fields)) = (self, &mut value) {
+            let mut with_schema = Map::new();
+            with_schema.insert("$schema".into(), SCHEMA_URL.into());
+            with_schema.append(fields);
+            *fields = with_schema;
+        }
+
+        for (name, task) in &kylefile.tasks {
+            if !task.notes.is_empty() {
+                comments.leading.insert(
+                    vec!["tasks".into(), name.clone()],
+                    task.notes
+                        .iter()
+                        .map(|note| format!("# kyle import: {note}"))
+                        .collect(),
+                );
+            }
+        }
+
+        layout::write(&value, self, &comments)
+    }
+}
+
+/// JSON has no comments, so drop leading `#` lines such as the `# kyle: json`
+/// header before parsing, keeping newlines so error line numbers still match.
+fn strip_header(content: &str) -> String {
+    let mut in_header = true;
+    content
+        .split_inclusive('\n')
+        .map(|line| {
+            in_header = in_header && line.trim_start().starts_with('#');
+            if in_header { "\n" } else { line }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Run, Step, Task};
+
+    fn kylefile() -> Kylefile {
+        let mut kylefile = Kylefile {
+            name: "demo".into(),
+            ..Default::default()
+        };
+        kylefile.tasks.insert(
+            "test".into(),
+            Task {
+                run: "cargo test".into(),
+                deps: vec!["build".into()],
+                ..Default::default()
+            },
+        );
+        kylefile.tasks.insert(
+            "build".into(),
+            Task {
+                desc: "Build \"it\"".into(),
+                run: Run::Steps(vec![
+                    Step::cmd("$(CC) main.c", true, false),
+                    Step::Command("echo done".into()),
+                ]),
+                notes: vec!["make variables are not expanded: $(CC)".into()],
+                ..Default::default()
+            },
+        );
+        kylefile
+    }
+
+    #[test]
+    fn render_round_trips_in_order() {
+        for format in [Format::Toml, Format::Yaml, Format::Json] {
+            let rendered = format.render(&kylefile()).unwrap();
+            let parsed = format.parse(&rendered).unwrap();
+            assert_eq!(parsed.name, "demo");
+            assert_eq!(
+                parsed.tasks.keys().collect::<Vec<_>>(),
+                vec!["test", "build"]
+            );
+            assert_eq!(parsed.tasks["build"].desc, "Build \"it\"");
+            assert_eq!(parsed.tasks["build"].run, kylefile().tasks["build"].run);
+            assert_eq!(parsed.tasks["test"].deps, vec!["build"]);
+        }
+    }
+
+    #[test]
+    fn render_writes_notes_as_comments() {
+        let toml = Format::Toml.render(&kylefile()).unwrap();
+        assert!(
+            toml.contains("# kyle import: make variables are not expanded: $(CC)\n[tasks.build]\n")
+        );
+
+        let yaml = Format::Yaml.render(&kylefile()).unwrap();
+        assert!(
+            yaml.contains("  # kyle import: make variables are not expanded: $(CC)\n  build:\n")
+        );
+    }
 }
@@ -0,0 +1,122 @@
+use serde::de::DeserializeOwned;
+
+/// Parse JSON that may carry `//` and `/* */` comments and trailing commas,
+/// as deno.jsonc and editor-touched package.json files do. Comments and
+/// trailing commas are blanked out rather than removed, so error positions
+/// still point into the original text.
+pub fn parse<T: DeserializeOwned>(content: &str) -> Result<T, serde_json::Error> {
+    serde_json::from_str(&strip(content))
+}
+
+fn strip(content: &str) -> String {
+    let blank = |out: &mut String, c: char| {
+        if c == '\n' {
+            out.push('\n');
+        } else {
+            out.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        }
+    };
+
+    // Comments first, so a comma followed by one is seen as trailing below
+    let mut uncommented = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            uncommented.push(c);
+            match c {
+                '\\' => uncommented.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                uncommented.push(c);
+            }
+            ('/', Some('/')) => {
+                blank(&mut uncommented, c);
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    blank(&mut uncommented, next);
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                blank(&mut uncommented, c);
+                let mut prev = '\0';
+                for next in chars.by_ref() {
+                    blank(&mut uncommented, next);
+                    if prev == '*' && next == '/' {
+                        break;
+                    }
+                    prev = next;
+                }
+            }
+            _ => uncommented.push(c),
+        }
+    }
+
+    let mut out = String::with_capacity(uncommented.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in uncommented.char_indices() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' && uncommented[i + 1..].trim_start().starts_with(['}', ']']) {
+            out.push(' ');
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    #[test]
+    fn comments_and_trailing_commas() {
+        let content = r#"{
+  // line comment
+  "tasks": {
+    "dev": "deno run --watch main.ts", /* block */
+    "url": "https://example.com/*not-a-comment*/",
+  },
+  "list": [1, 2,],
+}"#;
+        let value: Value = parse(content).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "tasks": {
+                    "dev": "deno run --watch main.ts",
+                    "url": "https://example.com/*not-a-comment*/"
+                },
+                "list": [1, 2]
+            })
+        );
+    }
+
+    #[test]
+    fn errors_keep_their_position() {
+        let content = "{\n  // comment\n  \"a\": 1,\n  \"b\": ?\n}";
+        let err = parse::<Value>(content).unwrap_err();
+        assert_eq!((err.line(), err.column()), (4, 8));
+    }
+
+    #[test]
+    fn escaped_quotes_stay_in_strings() {
+        let value: Value = parse(r#"{"a": "say \"hi\", // ok",}"#).unwrap();
+        assert_eq!(value["a"], "say \"hi\", // ok");
+    }
+}
@@ -1,97 +1,828 @@
 use super::Error;
-use super::kylefile::{Kylefile, Task};
+use super::kylefile::{Confirm, Kylefile, Run, Step, Task, Var, escape_templates};
 use regex::Regex;
-use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
 
-static RECIPE_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^([a-zA-Z_][a-zA-Z0-9_\-]*)(?:\s+[^:]+)?:\s*(.*)$").unwrap());
-
 static COMMENT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^#\s*(.*)$").unwrap());
 
-static SETTING_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^set\s+").unwrap());
+static ATTRIBUTES_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\[(.+)\]$").unwrap());
 
-static ALIAS_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^alias\s+").unwrap());
+static ASSIGNMENT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(export\s+)?([a-zA-Z_][a-zA-Z0-9_\-]*)\s*:=\s*(.+)$").unwrap());
 
-pub fn parse(content: &str) -> Result<Kylefile, Error> {
-    let mut tasks: HashMap<String, Task> = HashMap::new();
-    let mut pending_comment: Option<String> = None;
+static IMPORT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^import(\?)?\s+(.+)$").unwrap());
 
-    let lines: Vec<&str> = content.lines().collect();
-    let mut i = 0;
+static MOD_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^mod(\?)?\s+([a-zA-Z_][a-zA-Z0-9_\-]*)(?:\s+(.+))?$").unwrap());
 
-    while i < lines.len() {
-        let line = lines[i];
+/// Names a kyle template can refer to
+static IDENT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-zA-Z_][a-zA-Z0-9_]*$").unwrap());
 
-        if SETTING_RE.is_match(line) || ALIAS_RE.is_match(line) {
-            pending_comment = None;
-            i += 1;
-            continue;
+/// How deep `import` and `mod` may nest before kyle assumes a cycle
+const MAX_DEPTH: usize = 16;
+
+/// Closes the heredoc a script recipe is fed through
+const SCRIPT_END: &str = "KYLE_SCRIPT";
+
+/// Interpreter for `[script]` recipes that don't name one, as in just
+const SCRIPT_INTERPRETER: &str = "sh -eu";
+
+/// Files just reads a `mod name` from, inside the `name` directory
+const MODULE_FILES: &[&str] = &["mod.just", "justfile", "Justfile", ".justfile"];
+
+const PLATFORM_ATTRIBUTES: &[&str] = &[
+    "linux",
+    "macos",
+    "windows",
+    "unix",
+    "openbsd",
+    "freebsd",
+    "netbsd",
+    "dragonfly",
+    "android",
+    "ios",
+];
+
+/// Attributes that don't change what kyle runs
+const IGNORED_ATTRIBUTES: &[&str] = &[
+    "default",
+    "exit-message",
+    "extension",
+    "metadata",
+    "no-cd",
+    "no-exit-message",
+    "parallel",
+    "positional-arguments",
+];
+
+/// Read a justfile, following `import` and `mod` relative to `dir`. Module
+/// recipes become `module:recipe` tasks.
+pub fn parse(content: &str, dir: &Path) -> Result<Kylefile, Error> {
+    let mut parser = Parser::new(dir, 0);
+    parser.read(content, "justfile");
+    Ok(parser.finish())
+}
+
+struct Header {
+    name: String,
+    quiet: bool,
+    params: Vec<String>,
+    /// `$name` params, which just also sets as environment variables
+    exported: Vec<String>,
+    deps: Vec<Dep>,
+    /// Dependencies after `&&`, which run once the recipe is done
+    after: Vec<Dep>,
+    notes: Vec<String>,
+}
+
+struct Dep {
+    name: String,
+    args: Vec<String>,
+}
+
+struct Parser {
+    dir: PathBuf,
+    kylefile: Kylefile,
+    export_all: bool,
+    quiet: bool,
+    depth: usize,
+}
+
+impl Parser {
+    fn new(dir: &Path, depth: usize) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            kylefile: Kylefile::default(),
+            export_all: false,
+            quiet: false,
+            depth,
         }
+    }
 
-        if let Some(caps) = COMMENT_RE.captures(line) {
-            let comment = caps[1].trim().to_string();
-            if !comment.is_empty() && !comment.starts_with('!') {
-                pending_comment = Some(comment);
-            }
+    fn read(&mut self, content: &str, file: &str) {
+        let lines: Vec<&str> = content.lines().collect();
+        let mut comment: Option<String> = None;
+        let mut attributes: Vec<(String, Option<String>)> = Vec::new();
+        let mut i = 0;
+
+        while i < lines.len() {
+            let line = lines[i];
+            let no = i + 1;
             i += 1;
-            continue;
+            let at = |message: &str| format!("{file}:{no}: {message}");
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() {
+                comment = None;
+                continue;
+            }
+            if let Some(caps) = COMMENT_RE.captures(trimmed) {
+                let text = caps[1].trim();
+                if !text.is_empty() && !text.starts_with('!') {
+                    comment = Some(text.to_string());
+                }
+                continue;
+            }
+            if let Some(caps) = ATTRIBUTES_RE.captures(trimmed) {
+                attributes.extend(parse_attributes(&caps[1]));
+                continue;
+            }
+
+            let desc = comment.take();
+            let attrs = std::mem::take(&mut attributes);
+
+            if line.starts_with([' ', '\t']) {
+                self.kylefile
+                    .warnings
+                    .push(at(&format!("not understood: {trimmed}")));
+            } else if let Some(setting) = trimmed.strip_prefix("set ") {
+                self.setting(setting);
+            } else if trimmed.starts_with("alias ") || trimmed.starts_with("unexport ") {
+            } else if let Some(caps) = IMPORT_RE.captures(trimmed) {
+                self.import(&caps[2], caps.get(1).is_some(), &at);
+            } else if let Some(caps) = MOD_RE.captures(trimmed) {
+                let group = attrs
+                    .iter()
+                    .find(|(name, _)| name == "group")
+                    .and_then(|(_, arg)| arg.clone());
+                let path = caps.get(3).map(|m| m.as_str());
+                self.module(&caps[2], path, caps.get(1).is_some(), group, &at);
+            } else if let Some(caps) = ASSIGNMENT_RE.captures(trimmed) {
+                self.assign(&caps[2], caps[3].trim(), caps.get(1).is_some(), &at);
+            } else if let Some(header) = parse_header(trimmed) {
+                // The body runs to the last indented line, blank lines included
+                let start = i;
+                let mut end = i;
+                while end < lines.len() {
+                    if lines[end].starts_with([' ', '\t']) && !lines[end].trim().is_empty() {
+                        i = end + 1;
+                    } else if !lines[end].trim().is_empty() {
+                        break;
+                    }
+                    end += 1;
+                }
+                self.recipe(header, &lines[start..i], desc, attrs, &at);
+            } else {
+                self.kylefile
+                    .warnings
+                    .push(at(&format!("not understood: {trimmed}")));
+            }
+        }
+    }
+
+    fn setting(&mut self, setting: &str) {
+        let (name, value) = match setting.split_once(":=") {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (setting.trim(), "true"),
+        };
+        match name {
+            "export" => self.export_all = value == "true",
+            "quiet" => self.quiet = value == "true",
+            _ => {}
         }
+    }
 
-        if let Some(caps) = RECIPE_RE.captures(line) {
-            let recipe_name = caps[1].to_string();
+    fn assign(&mut self, name: &str, value: &str, export: bool, at: &dyn Fn(&str) -> String) {
+        let var = match value.strip_prefix('`').and_then(|v| v.strip_suffix('`')) {
+            Some(cmd) => Var::Dynamic {
+                sh: cmd.to_string(),
+            },
+            None => match translate(value) {
+                Some(text) => Var::Static(text),
+                None => {
+                    self.kylefile
+                        .warnings
+                        .push(at(&format!("expression not evaluated: {value}")));
+                    Var::Static(value.to_string())
+                }
+            },
+        };
+        self.kylefile.vars.insert(name.to_string(), var);
+        if export {
+            self.kylefile
+                .env
+                .insert(name.to_string(), format!("{{{{{name}}}}}"));
+        }
+    }
 
-            if recipe_name.starts_with('_') {
-                pending_comment = None;
-                i += 1;
-                continue;
+    fn import(&mut self, path: &str, optional: bool, at: &dyn Fn(&str) -> String) {
+        let path = unquote(path);
+        if self.depth >= MAX_DEPTH {
+            self.kylefile
+                .warnings
+                .push(at("imports nest too deeply; stopped"));
+            return;
+        }
+        match fs::read_to_string(self.dir.join(&path)) {
+            Ok(content) => {
+                self.depth += 1;
+                self.read(&content, &path);
+                self.depth -= 1;
+            }
+            Err(_) if !optional => {
+                self.kylefile
+                    .warnings
+                    .push(at(&format!("imported file not found: {path}")));
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// Add a module's recipes as `name:recipe` tasks that run in the module's directory
+    fn module(
+        &mut self,
+        name: &str,
+        path: Option<&str>,
+        optional: bool,
+        group: Option<String>,
+        at: &dyn Fn(&str) -> String,
+    ) {
+        let candidates: Vec<PathBuf> = match path {
+            Some(path) => {
+                let path = self.dir.join(unquote(path));
+                let inside = MODULE_FILES.iter().map(|file| path.join(file)).collect();
+                if path.is_dir() { inside } else { vec![path] }
+            }
+            None => std::iter::once(self.dir.join(format!("{name}.just")))
+                .chain(
+                    MODULE_FILES
+                        .iter()
+                        .map(|file| self.dir.join(name).join(file)),
+                )
+                .collect(),
+        };
+        let Some(file) = candidates.into_iter().find(|file| file.is_file()) else {
+            if !optional {
+                self.kylefile
+                    .warnings
+                    .push(at(&format!("module not found: {name}")));
             }
+            return;
+        };
+        if self.depth >= MAX_DEPTH {
+            self.kylefile
+                .warnings
+                .push(at("modules nest too deeply; stopped"));
+            return;
+        }
+        let Ok(content) = fs::read_to_string(&file) else {
+            self.kylefile
+                .warnings
+                .push(at(&format!("can't read module {}", file.display())));
+            return;
+        };
 
-            let deps: Vec<String> = caps[2]
-                .split_whitespace()
-                .filter(|d| !d.starts_with('(') && !d.contains('='))
-                .map(|s| s.to_string())
-                .collect();
+        let module_dir = file.parent().unwrap_or(Path::new(""));
+        let label = file.strip_prefix(&self.dir).unwrap_or(&file);
+        let mut parser = Parser::new(module_dir, self.depth + 1);
+        parser.read(&content, &label.to_string_lossy());
+        let module = parser.finish();
 
-            let mut commands: Vec<String> = Vec::new();
-            i += 1;
-            while i < lines.len() {
-                let cmd_line = lines[i];
-                if cmd_line.starts_with("    ") || cmd_line.starts_with('\t') {
-                    let cmd = cmd_line.trim_start();
-                    let cmd = cmd.strip_prefix('@').unwrap_or(cmd);
-                    if !cmd.is_empty() {
-                        commands.push(cmd.to_string());
+        let rel = module_dir.strip_prefix(&self.dir).unwrap_or(Path::new(""));
+        let local = |task: &str| {
+            if module.tasks.contains_key(task) {
+                format!("{name}:{task}")
+            } else {
+                task.to_string()
+            }
+        };
+        for (task_name, mut task) in module.tasks.clone() {
+            task.deps = task.deps.iter().map(|dep| local(dep)).collect();
+            if let Run::Steps(steps) = &mut task.run {
+                for step in steps {
+                    if let Step::Task { task: call, .. } = step {
+                        *call = local(call);
                     }
-                    i += 1;
-                } else if cmd_line.trim().is_empty() {
-                    i += 1;
-                } else {
-                    break;
                 }
             }
+            for (key, var) in &module.vars {
+                task.vars.entry(key.clone()).or_insert_with(|| var.clone());
+            }
+            for (key, value) in &module.env {
+                task.env.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+            if !rel.as_os_str().is_empty() {
+                let dir = match &task.dir {
+                    Some(dir) => rel.join(dir),
+                    None => rel.to_path_buf(),
+                };
+                task.dir = Some(dir.to_string_lossy().into_owned());
+            }
+            if task.group.is_empty() {
+                task.group = group.clone().unwrap_or_else(|| name.to_string());
+            }
+            self.kylefile
+                .tasks
+                .insert(format!("{name}:{task_name}"), task);
+        }
+        self.kylefile.warnings.extend(module.warnings);
+    }
 
-            let task = Task {
-                desc: pending_comment.take().unwrap_or_default(),
-                run: commands.join(" && "),
-                deps,
+    fn recipe(
+        &mut self,
+        header: Header,
+        body: &[&str],
+        desc: Option<String>,
+        attributes: Vec<(String, Option<String>)>,
+        at: &dyn Fn(&str) -> String,
+    ) {
+        let mut task = Task {
+            desc: desc.unwrap_or_default(),
+            private: header.name.starts_with('_'),
+            params: header.params,
+            ..Default::default()
+        };
+        let mut notes = header.notes;
+        let mut quiet = header.quiet || self.quiet;
+        let mut script: Option<String> = None;
+        let mut skipped = Vec::new();
+
+        for (attribute, arg) in attributes {
+            match attribute.as_str() {
+                "private" => task.private = true,
+                "group" => task.group = arg.unwrap_or_default(),
+                "confirm" => {
+                    task.confirm = Some(match arg {
+                        Some(question) => Confirm::Prompt(question),
+                        None => Confirm::Enabled(true),
+                    });
+                }
+                "doc" => task.desc = arg.unwrap_or_default(),
+                "working-directory" => task.dir = arg,
+                "script" => script = Some(arg.unwrap_or_else(|| SCRIPT_INTERPRETER.to_string())),
+                "no-quiet" => quiet = false,
+                name if PLATFORM_ATTRIBUTES.contains(&name) => task.platforms.push(attribute),
+                name if IGNORED_ATTRIBUTES.contains(&name) => {}
+                _ => skipped.push(format!("[{attribute}]")),
+            }
+        }
+
+        // Recipe lines are written indented; just strips the common indentation
+        let indent = body
+            .iter()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        let lines: Vec<&str> = body
+            .iter()
+            .map(|line| line.get(indent..).unwrap_or("").trim_end())
+            .collect();
+
+        let mut steps = Vec::new();
+        for dep in header.deps {
+            if dep.args.is_empty() {
+                task.deps.push(dep.name);
+            } else {
+                steps.push(Step::Task {
+                    task: dep.name,
+                    args: dep.args,
+                });
+            }
+        }
+
+        let shebang = lines
+            .first()
+            .and_then(|line| line.strip_prefix("#!"))
+            .map(|interpreter| interpreter.trim().to_string());
+        if let Some(interpreter) = shebang.clone().or(script) {
+            let code = if shebang.is_some() {
+                &lines[1..]
+            } else {
+                &lines[..]
             };
+            let code = interpolate(code.join("\n").trim_matches('\n'), &mut notes);
+            steps.push(Step::Command(format!(
+                "{interpreter} <<'{SCRIPT_END}'\n{code}\n{SCRIPT_END}"
+            )));
+        } else {
+            let mut pending: Option<String> = None;
+            for line in lines {
+                if line.is_empty() {
+                    continue;
+                }
+                let line = match pending.take() {
+                    Some(start) => format!("{start} {}", line.trim_start()),
+                    None => line.to_string(),
+                };
+                if let Some(start) = line.strip_suffix('\\') {
+                    pending = Some(start.trim_end().to_string());
+                    continue;
+                }
+                if line.starts_with('#') {
+                    continue;
+                }
+                let line = interpolate(&line, &mut notes);
+                steps.extend(parse_recipe_line(&line, quiet));
+            }
+            if let Some(line) = pending {
+                let line = interpolate(&line, &mut notes);
+                steps.extend(parse_recipe_line(&line, quiet));
+            }
+        }
+
+        for dep in header.after {
+            steps.push(Step::Task {
+                task: dep.name,
+                args: dep.args,
+            });
+        }
+        task.run = Run::from_steps(steps);
+
+        for name in header.exported {
+            task.env.insert(name.clone(), format!("{{{{{name}}}}}"));
+        }
+        if !skipped.is_empty() {
+            notes.push(format!("attributes not imported: {}", skipped.join(" ")));
+        }
+        task.notes = notes;
+
+        if self.kylefile.tasks.contains_key(&header.name) {
+            self.kylefile.warnings.push(at(&format!(
+                "recipe '{}' is defined again; the later one wins",
+                header.name
+            )));
+        }
+        self.kylefile.tasks.insert(header.name, task);
+    }
+
+    fn finish(mut self) -> Kylefile {
+        if self.export_all {
+            let kylefile = &mut self.kylefile;
+            for name in kylefile.vars.keys() {
+                kylefile
+                    .env
+                    .entry(name.clone())
+                    .or_insert_with(|| format!("{{{{{name}}}}}"));
+            }
+            for task in kylefile.tasks.values_mut() {
+                for param in task.params() {
+                    task.env
+                        .entry(param.name.clone())
+                        .or_insert_with(|| format!("{{{{{}}}}}", param.name));
+                }
+            }
+        }
+        self.kylefile
+    }
+}
+
+/// Parse `name param='default' *rest: dep (dep2 "arg") && after`
+fn parse_header(line: &str) -> Option<Header> {
+    let (quiet, rest) = match line.strip_prefix('@') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let len = name_len(rest);
+    if len == 0 || rest.starts_with('-') {
+        return None;
+    }
+    let mut header = Header {
+        name: rest[..len].to_string(),
+        quiet,
+        params: Vec::new(),
+        exported: Vec::new(),
+        deps: Vec::new(),
+        after: Vec::new(),
+        notes: Vec::new(),
+    };
+    let mut rest = &rest[len..];
+
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix(':') {
+            if after.starts_with('=') {
+                return None;
+            }
+            rest = after;
+            break;
+        }
+        let (exported, param) = match rest.strip_prefix('$') {
+            Some(param) => (true, param),
+            None => (false, rest),
+        };
+        let (prefix, param) = match param.strip_prefix(['*', '+']) {
+            Some(stripped) => (&param[..1], stripped),
+            None => ("", param),
+        };
+        let len = name_len(param);
+        if len == 0 {
+            return None;
+        }
+        let name = &param[..len];
+        let mut spec = format!("{prefix}{name}");
+        rest = &param[len..];
+        if let Some(value) = rest.strip_prefix('=') {
+            let (expr, after) = take_value(value)?;
+            let default = match expr.strip_prefix('`').and_then(|e| e.strip_suffix('`')) {
+                Some(cmd) => format!("$({cmd})"),
+                None => translate(expr).unwrap_or_else(|| {
+                    header
+                        .notes
+                        .push(format!("default not evaluated for '{name}': {expr}"));
+                    expr.to_string()
+                }),
+            };
+            spec.push('=');
+            spec.push_str(&default);
+            rest = after;
+        }
+        if exported {
+            header.exported.push(name.to_string());
+        }
+        header.params.push(spec);
+    }
 
-            tasks.insert(recipe_name, task);
+    let mut after = false;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() || rest.starts_with('#') {
+            break;
+        }
+        if let Some(r) = rest.strip_prefix("&&") {
+            after = true;
+            rest = r;
             continue;
         }
+        let dep = if rest.starts_with('(') {
+            let (group, r) = take_value(rest)?;
+            rest = r;
+            let mut inner = group[1..group.len() - 1].trim_start();
+            let len = path_len(inner);
+            if len == 0 {
+                return None;
+            }
+            let name = inner[..len].replace("::", ":");
+            inner = &inner[len..];
+            let mut args = Vec::new();
+            loop {
+                inner = inner.trim_start();
+                if inner.is_empty() {
+                    break;
+                }
+                let (expr, r) = take_value(inner)?;
+                inner = r;
+                args.push(translate(expr).unwrap_or_else(|| {
+                    header
+                        .notes
+                        .push(format!("argument not evaluated for '{name}': {expr}"));
+                    expr.to_string()
+                }));
+            }
+            Dep { name, args }
+        } else {
+            let len = path_len(rest);
+            if len == 0 {
+                return None;
+            }
+            let name = rest[..len].replace("::", ":");
+            rest = &rest[len..];
+            Dep {
+                name,
+                args: Vec::new(),
+            }
+        };
+        if after {
+            header.after.push(dep);
+        } else {
+            header.deps.push(dep);
+        }
+    }
+    Some(header)
+}
+
+/// Length of the recipe or parameter name `text` starts with
+fn name_len(text: &str) -> usize {
+    if text.starts_with(|c: char| c.is_ascii_digit()) {
+        return 0;
+    }
+    text.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(text.len())
+}
+
+/// Length of the dependency `text` starts with, which may be a module path
+/// like `docs::build`
+fn path_len(text: &str) -> usize {
+    let mut len = name_len(text);
+    while len > 0 && text[len..].starts_with("::") {
+        let next = name_len(&text[len + 2..]);
+        if next == 0 {
+            break;
+        }
+        len += 2 + next;
+    }
+    len
+}
+
+/// Split one value off the front of `text`: a string, a backtick command,
+/// a parenthesised expression or a name
+fn take_value(text: &str) -> Option<(&str, &str)> {
+    let first = text.chars().next()?;
+    let end = match first {
+        '\'' | '`' => text[1..].find(first)? + 2,
+        '"' => {
+            let mut escaped = false;
+            let close = text[1..].char_indices().find(|&(_, c)| {
+                let found = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                found
+            })?;
+            close.0 + 2
+        }
+        '(' => {
+            let mut depth = 0;
+            let mut quote: Option<char> = None;
+            let mut end = None;
+            for (i, c) in text.char_indices() {
+                match (quote, c) {
+                    (Some(q), c) if c == q => quote = None,
+                    (Some(_), _) => {}
+                    (None, '\'' | '"' | '`') => quote = Some(c),
+                    (None, '(') => depth += 1,
+                    (None, ')') => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = Some(i + 1);
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            end?
+        }
+        _ => {
+            let len = name_len(text);
+            if len == 0 {
+                return None;
+            }
+            len
+        }
+    };
+    Some((&text[..end], &text[end..]))
+}
+
+/// Turn a just expression made of strings, names, `+` and `/` into kyle
+/// template text; anything else (function calls, `if`) gives `None`
+fn translate(expr: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut rest = expr.trim();
+    loop {
+        let (token, after) = take_value(rest)?;
+        let inner = || &token[1..token.len() - 1];
+        match token.chars().next()? {
+            '\'' => out.push_str(&escape_templates(inner())),
+            '"' => out.push_str(&escape_templates(&unescape(inner()))),
+            '(' => out.push_str(&translate(inner())?),
+            _ if IDENT_RE.is_match(token) && token != "if" => {
+                out.push_str(&format!("{{{{{token}}}}}"));
+            }
+            _ => return None,
+        }
+        rest = after.trim_start();
+        if rest.is_empty() {
+            return Some(out);
+        }
+        if let Some(after) = rest.strip_prefix('+') {
+            rest = after.trim_start();
+        } else if let Some(after) = rest.strip_prefix('/') {
+            out.push('/');
+            rest = after.trim_start();
+        } else {
+            return None;
+        }
+    }
+}
+
+/// Replace `{{ expr }}` interpolations with kyle templates. Expressions
+/// kyle can't translate stay as written and are noted.
+fn interpolate(text: &str, notes: &mut Vec<String>) -> String {
+    let mut out = String::new();
+    let mut literal = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        literal.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        // `{{{{` is just's escape for a literal `{{`
+        if let Some(after) = after.strip_prefix("{{") {
+            literal.push_str("{{");
+            rest = after;
+            continue;
+        }
+        let Some(end) = after.find("}}") else {
+            literal.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+        let expr = &after[..end];
+        match translate(expr) {
+            Some(template) => {
+                out.push_str(&escape_templates(&std::mem::take(&mut literal)));
+                out.push_str(&template);
+            }
+            None => {
+                let written = format!("{{{{{expr}}}}}");
+                let note = format!("just expressions are not evaluated: {}", written.trim());
+                if !notes.contains(&note) {
+                    notes.push(note);
+                }
+                literal.push_str(&written);
+            }
+        }
+        rest = &after[end + 2..];
+    }
+    literal.push_str(rest);
+    out.push_str(&escape_templates(&literal));
+    out
+}
+
+/// Parse the inside of `[private, group('ci'), confirm: 'Sure?']`
+fn parse_attributes(text: &str) -> Vec<(String, Option<String>)> {
+    let mut attributes = Vec::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let len = name_len(rest);
+        if len == 0 {
+            break;
+        }
+        let name = rest[..len].to_string();
+        rest = rest[len..].trim_start();
+        let mut arg = None;
+        if rest.starts_with('(') {
+            if let Some((group, after)) = take_value(rest) {
+                let inner = group[1..group.len() - 1].trim();
+                arg = take_value(inner).map(|(value, _)| unquote(value));
+                rest = after;
+            }
+        } else if let Some(after) = rest.strip_prefix(':')
+            && let Some((value, after)) = take_value(after.trim_start())
+        {
+            arg = Some(unquote(value));
+            rest = after;
+        }
+        attributes.push((name, arg));
+        rest = rest.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+    attributes
+}
 
-        if line.trim().is_empty() {
-            pending_comment = None;
+/// The text of a quoted string, or `text` itself
+fn unquote(text: &str) -> String {
+    let text = text.trim();
+    if let Some(inner) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        return unescape(inner);
+    }
+    match text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        Some(inner) => inner.to_string(),
+        None => text.to_string(),
+    }
+}
+
+/// Resolve the escapes just allows in double-quoted strings
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
         }
-        i += 1;
     }
+    out
+}
 
-    Ok(Kylefile {
-        name: String::new(),
-        includes: Default::default(),
-        tasks,
-    })
+/// Parse a recipe line, honouring just's `@` and `-` (ignore errors)
+/// prefixes. `@` hides the command, or shows it in a quiet recipe.
+fn parse_recipe_line(line: &str, quiet: bool) -> Option<Step> {
+    let mut at = false;
+    let mut ignore_error = false;
+    let mut cmd = line;
+    loop {
+        if let Some(rest) = cmd.strip_prefix('@') {
+            at = true;
+            cmd = rest;
+        } else if let Some(rest) = cmd.strip_prefix('-') {
+            ignore_error = true;
+            cmd = rest;
+        } else {
+            break;
+        }
+    }
+    if cmd.is_empty() {
+        return None;
+    }
+    Some(Step::cmd(cmd, at == quiet, ignore_error))
 }
 
 #[cfg(test)]
@@ -101,51 +832,241 @@ mod tests {
     #[test]
     fn parse_simple_recipe() {
         let content = "build:\n    cargo build\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert!(kf.tasks.contains_key("build"));
-        assert_eq!(kf.tasks["build"].run, "cargo build");
+        assert_eq!(kf.tasks["build"].run.commands(), vec!["cargo build"]);
     }
 
     #[test]
     fn parse_recipe_with_deps() {
         let content = "test: build\n    cargo test\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert_eq!(kf.tasks["test"].deps, vec!["build"]);
     }
 
+    #[test]
+    fn parse_parameters() {
+        let content = "deploy env target='debug' +flags=\"-v\" $TOKEN='x': build\n    ./deploy {{env}} {{target}} {{flags}}\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        let task = &kf.tasks["deploy"];
+        assert_eq!(task.deps, vec!["build"]);
+        assert_eq!(
+            task.params,
+            vec!["env", "target=debug", "+flags=-v", "TOKEN=x"]
+        );
+        assert_eq!(task.env["TOKEN"], "{{TOKEN}}");
+        assert_eq!(
+            task.run.commands(),
+            vec!["./deploy {{env}} {{target}} {{flags}}"]
+        );
+        assert!(task.notes.is_empty());
+    }
+
+    #[test]
+    fn translate_expressions() {
+        let content = "\
+name := \"app\"
+dist := \"out\" / name + \"-\" + 'v1'
+host := os()
+
+build:
+    echo {{ dist }} {{ name + \".tar\" }} {{{{literal}} {{ arch() }}
+";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.vars["dist"], Var::Static("out/{{name}}-v1".into()));
+        assert_eq!(kf.vars["host"], Var::Static("os()".into()));
+        assert_eq!(
+            kf.warnings,
+            vec!["justfile:3: expression not evaluated: os()"]
+        );
+        assert_eq!(
+            kf.tasks["build"].run.commands(),
+            vec!["echo {{dist}} {{name}}.tar \\{{literal}} {{ arch() }}"]
+        );
+        assert_eq!(
+            kf.tasks["build"].notes,
+            vec!["just expressions are not evaluated: {{ arch() }}"]
+        );
+    }
+
+    #[test]
+    fn parse_attributes() {
+        let content = "\
+[private]
+helper:
+    echo helper
+
+# Ship it
+[group('release'), confirm('Really ship?')]
+[linux, macos]
+[no-cd]
+[doc('Publish the release')]
+ship:
+    echo ship
+
+[confirm]
+[group: 'release']
+[parallel]
+[unknown-thing]
+wipe:
+    rm -rf out
+";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert!(kf.tasks["helper"].private);
+        let ship = &kf.tasks["ship"];
+        assert_eq!(ship.group, "release");
+        assert_eq!(ship.desc, "Publish the release");
+        assert_eq!(ship.confirm, Some(Confirm::Prompt("Really ship?".into())));
+        assert_eq!(ship.platforms, vec!["linux", "macos"]);
+        assert!(ship.notes.is_empty());
+        let wipe = &kf.tasks["wipe"];
+        assert_eq!(wipe.confirm, Some(Confirm::Enabled(true)));
+        assert_eq!(wipe.group, "release");
+        assert_eq!(wipe.notes, vec!["attributes not imported: [unknown-thing]"]);
+    }
+
+    #[test]
+    fn shebang_recipes_run_as_one_script() {
+        let content = "test:\n    #!/usr/bin/env python3\n    for i in range(2):\n        print({{count}})\n\n    print('done')\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["test"].run,
+            "/usr/bin/env python3 <<'KYLE_SCRIPT'\nfor i in range(2):\n    print({{count}})\n\nprint('done')\nKYLE_SCRIPT"
+        );
+    }
+
+    #[test]
+    fn script_attribute() {
+        let content = "[script('bash')]\nbuild:\n    set -e\n    echo hi\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["build"].run,
+            "bash <<'KYLE_SCRIPT'\nset -e\necho hi\nKYLE_SCRIPT"
+        );
+    }
+
+    #[test]
+    fn dependency_arguments_and_subsequent_deps() {
+        let content =
+            "build: lint (compile \"release\" target) && (notify 'done') clean\n    echo build\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        let task = &kf.tasks["build"];
+        assert_eq!(task.deps, vec!["lint"]);
+        assert_eq!(
+            task.run,
+            Run::Steps(vec![
+                Step::Task {
+                    task: "compile".into(),
+                    args: vec!["release".into(), "{{target}}".into()],
+                },
+                Step::cmd("echo build", true, false),
+                Step::Task {
+                    task: "notify".into(),
+                    args: vec!["done".into()],
+                },
+                Step::Task {
+                    task: "clean".into(),
+                    args: vec![],
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn quiet_recipes_and_continuations() {
+        let content = "@build:\n    echo one \\\n      two\n    @echo shown\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["build"].run,
+            Run::Steps(vec![
+                Step::Command("echo one two".into()),
+                Step::cmd("echo shown", true, false),
+            ])
+        );
+    }
+
+    #[test]
+    fn follow_imports_and_modules() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("common.just"),
+            "# Lint\nlint:\n    echo lint\n",
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        fs::write(
+            dir.path().join("docs/justfile"),
+            "site := \"public\"\n\nbuild: clean\n    echo {{site}}\n\nclean:\n    rm -rf {{site}}\n",
+        )
+        .unwrap();
+        let content = "import 'common.just'\nimport? 'local.just'\nmod docs\nmod missing\n\nall: lint docs::build\n";
+        let kf = parse(content, dir.path()).unwrap();
+
+        assert_eq!(kf.tasks["lint"].desc, "Lint");
+        let build = &kf.tasks["docs:build"];
+        assert_eq!(build.deps, vec!["docs:clean"]);
+        assert_eq!(build.dir.as_deref(), Some("docs"));
+        assert_eq!(build.group, "docs");
+        assert_eq!(build.vars["site"], Var::Static("public".into()));
+        assert_eq!(kf.tasks["all"].deps, vec!["lint", "docs:build"]);
+        assert_eq!(kf.warnings, vec!["justfile:4: module not found: missing"]);
+    }
+
+    #[test]
+    fn export_setting_exports_vars_and_params() {
+        let content = "set export\nversion := \"1\"\n\nrelease tag:\n    git tag $tag\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.env["version"], "{{version}}");
+        assert_eq!(kf.tasks["release"].env["tag"], "{{tag}}");
+    }
+
     #[test]
     fn parse_comment_as_description() {
         let content = "# Build the project\nbuild:\n    cargo build\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert_eq!(kf.tasks["build"].desc, "Build the project");
     }
 
     #[test]
-    fn skip_private_recipes() {
-        let content = "_helper:\n    echo helper\n\nbuild:\n    echo build\n";
-        let kf = parse(content).unwrap();
-        assert!(!kf.tasks.contains_key("_helper"));
-        assert!(kf.tasks.contains_key("build"));
+    fn underscore_recipes_are_private() {
+        let content = "_helper:\n    echo helper\n\nbuild: _helper\n    echo build\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert!(kf.tasks["_helper"].private);
+        assert!(!kf.tasks["build"].private);
+        assert_eq!(kf.tasks["build"].deps, vec!["_helper"]);
     }
 
     #[test]
     fn handle_quiet_prefix() {
         let content = "build:\n    @echo building\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert_eq!(kf.tasks["build"].run, "echo building");
     }
 
     #[test]
     fn multi_line_commands() {
         let content = "build:\n    echo step1\n    echo step2\n";
-        let kf = parse(content).unwrap();
-        assert_eq!(kf.tasks["build"].run, "echo step1 && echo step2");
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["build"].run.commands(),
+            vec!["echo step1", "echo step2"]
+        );
+    }
+
+    #[test]
+    fn handle_ignore_errors_prefix() {
+        let content = "clean:\n    -@rm -rf build/\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["clean"].run,
+            Run::Steps(vec![Step::cmd("rm -rf build/", false, true)])
+        );
     }
 
     #[test]
     fn skip_settings() {
         let content = "set shell := [\"bash\", \"-c\"]\n\nbuild:\n    echo build\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert!(kf.tasks.contains_key("build"));
         assert!(!kf.tasks.contains_key("set"));
     }
@@ -153,15 +1074,30 @@ mod tests {
     #[test]
     fn skip_aliases() {
         let content = "alias b := build\n\nbuild:\n    echo build\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert!(kf.tasks.contains_key("build"));
         assert!(!kf.tasks.contains_key("alias"));
     }
 
+    #[test]
+    fn parse_assignments_as_vars() {
+        let content = "version := \"1.2.0\"\nexport sha := `git rev-parse HEAD`\n\nbuild:\n    echo {{version}}\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.vars["version"], Var::Static("1.2.0".into()));
+        assert_eq!(
+            kf.vars["sha"],
+            Var::Dynamic {
+                sh: "git rev-parse HEAD".into()
+            }
+        );
+        assert!(!kf.tasks.contains_key("version"));
+        assert!(!kf.tasks.contains_key("export"));
+    }
+
     #[test]
     fn multiple_recipes() {
         let content = "# Build\nbuild:\n    echo build\n\n# Test\ntest: build\n    echo test\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert_eq!(kf.tasks.len(), 2);
         assert_eq!(kf.tasks["build"].desc, "Build");
         assert_eq!(kf.tasks["test"].desc, "Test");
@@ -1,23 +1,319 @@
+use super::loader::GLOBAL_NAMESPACE;
+use super::package_json::PackageManager;
+use super::platform;
+use super::version::Deprecated;
+use indexmap::IndexMap;
+use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::LazyLock;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+/// `{{ .NAME }}` or `{{NAME}}`; a leading backslash keeps the braces literal
+pub(crate) static TEMPLATE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\\)?\{\{\s*\.?([a-zA-Z_][a-zA-Z0-9_]*)\s*\}\}").unwrap());
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
 pub struct Task {
-    #[serde(default)]
+    /// Description shown in task listings
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub desc: String,
-    #[serde(default)]
-    pub run: String,
-    #[serde(default)]
+    /// Section the task is listed under, e.g. `Build`; ungrouped tasks come first
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub group: String,
+    /// Left out of task listings; still runs by name or as a dependency
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub private: bool,
+    /// Named arguments filled from the command line in order: `name`,
+    /// `name=default`, or `*name` / `+name` to collect the rest
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<String>,
+    /// Ask before running: `true`, or the question to ask
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm: Option<Confirm>,
+    #[serde(default, skip_serializing_if = "Run::is_empty")]
+    pub run: Run,
+    /// Tasks to run first; `ns:task` names run in another namespace
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub deps: Vec<String>,
+    /// Cleanup steps that run after `run`, even when it fails or is interrupted
+    #[serde(default, alias = "finally", skip_serializing_if = "Vec::is_empty")]
+    pub defer: Vec<Step>,
+    /// Working directory, relative to the Kylefile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// Environment variables for the task's commands
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, String>,
+    /// Variables for `{{ .NAME }}` templates, shadowing Kylefile vars
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub vars: IndexMap<String, Var>,
+    /// Platforms the task runs on, e.g. `["linux", "darwin"]`; empty means all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<String>,
+    /// File the task was inherited from through `extends`; `None` when defined locally
+    #[serde(skip)]
+    pub origin: Option<PathBuf>,
+    /// Defined in the user-global Kylefile rather than the project
+    #[serde(skip)]
+    pub global: bool,
+    /// What was lost converting the task from another tool's file, e.g. make
+    /// variables kyle doesn't expand; `kyle import` writes these as comments
+    #[serde(skip)]
+    pub notes: Vec<String>,
+}
+
+impl Task {
+    pub fn params(&self) -> Vec<Param> {
+        self.params.iter().map(|spec| Param::parse(spec)).collect()
+    }
+}
+
+/// A task's `confirm` value
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Confirm {
+    /// `true` asks "Run task 'name'?"
+    Enabled(bool),
+    /// The question to ask
+    Prompt(String),
+}
+
+impl Confirm {
+    /// The question to ask before running `task`, if any
+    pub fn question(&self, task: &str) -> Option<String> {
+        match self {
+            Self::Enabled(true) => Some(format!("Run task '{task}'?")),
+            Self::Enabled(false) => None,
+            Self::Prompt(question) => Some(question.clone()),
+        }
+    }
+}
+
+/// One entry of a task's `params`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub default: Option<String>,
+    /// Takes every remaining argument, joined with spaces
+    pub rest: bool,
+    /// Must be given on the command line
+    pub required: bool,
+}
+
+impl Param {
+    pub fn parse(spec: &str) -> Self {
+        let (rest, at_least_one, spec) = if let Some(spec) = spec.strip_prefix('*') {
+            (true, false, spec)
+        } else if let Some(spec) = spec.strip_prefix('+') {
+            (true, true, spec)
+        } else {
+            (false, true, spec)
+        };
+        let (name, default) = match spec.split_once('=') {
+            Some((name, default)) => (name, Some(default.to_string())),
+            None => (spec, None),
+        };
+        Self {
+            name: name.trim().to_string(),
+            required: at_least_one && default.is_none(),
+            default,
+            rest,
+        }
+    }
+}
+
+/// A variable value: a literal, or a shell command whose trimmed output is
+/// used (`{ sh = "git rev-parse --short HEAD" }`).
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Var {
+    Static(String),
+    Dynamic { sh: String },
+}
+
+/// A task's `run` value: either a single shell command or a list of steps
+/// that are executed one at a time, each in its own shell. It can also be
+/// a table of per-platform overrides (`run.linux`, `run.macos`, `run.default`).
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Run {
+    Command(String),
+    Steps(Vec<Step>),
+    Platform(BTreeMap<String, Run>),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+/// One entry in a step list
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Step {
+    /// Plain shell command
+    Command(String),
+    /// Shell command with per-step settings
+    Cmd {
+        cmd: String,
+        /// Print the command before running it
+        #[serde(default, skip_serializing_if = "is_false")]
+        echo: bool,
+        /// Keep going if the command fails
+        #[serde(default, skip_serializing_if = "is_false")]
+        ignore_error: bool,
+    },
+    /// Call another task, optionally namespaced, with arguments
+    Task {
+        task: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+    },
+    /// Cleanup step, a command or task call, that runs once the task
+    /// finishes if the steps before it ran, even on failure
+    Defer { defer: Box<Step> },
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+impl Default for Run {
+    fn default() -> Self {
+        Self::Command(String::new())
+    }
+}
+
+impl From<String> for Run {
+    fn from(cmd: String) -> Self {
+        Self::Command(cmd)
+    }
+}
+
+impl From<&str> for Run {
+    fn from(cmd: &str) -> Self {
+        Self::Command(cmd.to_string())
+    }
+}
+
+impl PartialEq<&str> for Run {
+    fn eq(&self, other: &&str) -> bool {
+        matches!(self, Self::Command(cmd) if cmd == other)
+    }
+}
+
+impl Run {
+    /// Build a `Run` from parsed steps, collapsing a lone plain command
+    /// back into the single-string form.
+    pub fn from_steps(steps: Vec<Step>) -> Self {
+        match steps.as_slice() {
+            [] => Self::default(),
+            [Step::Command(cmd)] => Self::Command(cmd.clone()),
+            _ => Self::Steps(steps),
+        }
+    }
+
+    pub fn from_commands(cmds: Vec<String>) -> Self {
+        Self::from_steps(cmds.into_iter().map(Step::Command).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Command(cmd) => cmd.trim().is_empty(),
+            Self::Steps(steps) => steps.is_empty(),
+            Self::Platform(runs) => runs.is_empty(),
+        }
+    }
+
+    pub fn steps(&self) -> Vec<Step> {
+        match self {
+            Self::Command(cmd) if cmd.trim().is_empty() => Vec::new(),
+            Self::Command(cmd) => vec![Step::Command(cmd.clone())],
+            Self::Steps(steps) => steps.clone(),
+            Self::Platform(runs) => platform::select(runs).map(Run::steps).unwrap_or_default(),
+        }
+    }
+
+    /// Shell commands in execution order, skipping task calls and deferred steps
+    pub fn commands(&self) -> Vec<&str> {
+        match self {
+            Self::Command(cmd) if cmd.trim().is_empty() => Vec::new(),
+            Self::Command(cmd) => vec![cmd.as_str()],
+            Self::Steps(steps) => steps.iter().filter_map(Step::command).collect(),
+            Self::Platform(runs) => platform::select(runs)
+                .map(Run::commands)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Names of the tasks called from `task:` steps, deferred or not
+    fn calls_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Self::Command(_) => Vec::new(),
+            Self::Steps(steps) => steps.iter_mut().filter_map(Step::call_mut).collect(),
+            Self::Platform(runs) => runs.values_mut().flat_map(Run::calls_mut).collect(),
+        }
+    }
+
+    fn texts_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Self::Command(cmd) => vec![cmd],
+            Self::Steps(steps) => steps.iter_mut().flat_map(Step::texts_mut).collect(),
+            Self::Platform(runs) => runs.values_mut().flat_map(Run::texts_mut).collect(),
+        }
+    }
+}
+
+impl Step {
+    fn call_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Self::Task { task, .. } => Some(task),
+            Self::Defer { defer } => defer.call_mut(),
+            _ => None,
+        }
+    }
+
+    fn texts_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Self::Command(cmd) | Self::Cmd { cmd, .. } => vec![cmd],
+            Self::Task { task, args } => std::iter::once(task).chain(args.iter_mut()).collect(),
+            Self::Defer { defer } => defer.texts_mut(),
+        }
+    }
+
+    /// Build a command step, using the plain form when no settings are set
+    pub fn cmd(cmd: impl Into<String>, echo: bool, ignore_error: bool) -> Self {
+        let cmd = cmd.into();
+        if echo || ignore_error {
+            Self::Cmd {
+                cmd,
+                echo,
+                ignore_error,
+            }
+        } else {
+            Self::Command(cmd)
+        }
+    }
+
+    /// A cleanup step registered where it appears in `run`
+    pub fn defer(step: Step) -> Self {
+        Self::Defer {
+            defer: Box::new(step),
+        }
+    }
+
+    pub fn command(&self) -> Option<&str> {
+        match self {
+            Self::Command(cmd) | Self::Cmd { cmd, .. } => Some(cmd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
 #[serde(untagged)]
 pub enum Includes {
     #[default]
     None,
     List(Vec<String>),
-    Map(HashMap<String, String>),
+    Map(IndexMap<String, String>),
 }
 
 impl Includes {
@@ -48,12 +344,126 @@ impl Includes {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
 pub struct Kylefile {
-    #[serde(default)]
+    /// Project name
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub name: String,
-    #[serde(default)]
+    /// Minimum kyle version this Kylefile needs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Kylefiles to inherit tasks, vars and env from; local definitions win
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extends: Vec<String>,
+    /// Directories to register as namespaces
+    #[serde(default, skip_serializing_if = "Includes::is_empty")]
     pub includes: Includes,
-    #[serde(default)]
-    pub tasks: HashMap<String, Task>,
+    /// Variables for `{{ .NAME }}` templates in every task
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub vars: IndexMap<String, Var>,
+    /// Environment variables for every task
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, String>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tasks: IndexMap<String, Task>,
+    /// Tasks left out because they don't run on this platform, with the platforms they need
+    #[serde(skip)]
+    pub unavailable: HashMap<String, Vec<String>>,
+    /// Deprecated keys in use, for the loader to warn about
+    #[serde(skip)]
+    pub deprecated: Vec<Deprecated>,
+    /// Parts of another tool's task file kyle couldn't model, as `FILE:LINE: message`
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+    /// The tool that runs a package.json's scripts
+    #[serde(skip)]
+    pub package_manager: Option<PackageManager>,
+}
+
+impl Kylefile {
+    /// Merge user-global tasks beneath this Kylefile's own; project definitions win.
+    /// The global file's `vars` and `env` travel with its tasks so they don't leak
+    /// into project tasks.
+    /// Deps and calls naming a global task the project shadows still mean the
+    /// global one, so they're written as `~:name`.
+    pub fn merge_global(&mut self, global: Kylefile) {
+        let shadowed: Vec<String> = global
+            .tasks
+            .keys()
+            .filter(|name| self.tasks.contains_key(*name))
+            .cloned()
+            .collect();
+        let qualify = |call: &mut String| {
+            if shadowed.contains(call) {
+                *call = format!("{GLOBAL_NAMESPACE}:{call}");
+            }
+        };
+        for (name, mut task) in global.tasks {
+            task.global = true;
+            task.deps.iter_mut().for_each(qualify);
+            task.run.calls_mut().into_iter().for_each(qualify);
+            task.defer
+                .iter_mut()
+                .filter_map(Step::call_mut)
+                .for_each(qualify);
+            for (key, var) in &global.vars {
+                task.vars.entry(key.clone()).or_insert_with(|| var.clone());
+            }
+            for (key, value) in &global.env {
+                task.env.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+            self.tasks.entry(name).or_insert(task);
+        }
+    }
+
+    /// Escape template syntax in commands so it reaches the shell verbatim.
+    /// Used for sources that were not written with kyle's templates in mind,
+    /// e.g. a Makefile running `docker ps --format '{{.Names}}'`.
+    pub fn escape_templates(&mut self) {
+        for task in self.tasks.values_mut() {
+            for text in task
+                .run
+                .texts_mut()
+                .into_iter()
+                .chain(task.defer.iter_mut().flat_map(Step::texts_mut))
+            {
+                *text = escape_templates(text);
+            }
+        }
+    }
+
+    /// Drop tasks that don't run on this platform and pick per-platform `run`
+    /// overrides, recording what was dropped so invoking it gives a clear error.
+    pub fn apply_platform(&mut self) {
+        let unavailable = &mut self.unavailable;
+        self.tasks.retain(|name, task| {
+            if !task.platforms.is_empty() && !task.platforms.iter().any(|p| platform::matches(p)) {
+                unavailable.insert(name.clone(), task.platforms.clone());
+                return false;
+            }
+            if let Run::Platform(runs) = &task.run {
+                match platform::select(runs) {
+                    Some(run) => task.run = run.clone(),
+                    None => {
+                        unavailable.insert(name.clone(), runs.keys().cloned().collect());
+                        return false;
+                    }
+                }
+            }
+            true
+        });
+    }
+}
+
+/// Escape template syntax in `text` so it expands to itself
+pub(crate) fn escape_templates(text: &str) -> String {
+    TEMPLATE_RE
+        .replace_all(text, |caps: &regex::Captures| {
+            if caps.get(1).is_some() {
+                caps[0].to_string()
+            } else {
+                format!("\\{}", &caps[0])
+            }
+        })
+        .into_owned()
 }
@@ -0,0 +1,890 @@
+//! Canonical text layout for Kylefiles, shared by `kyle fmt`, `kyle import`
+//! and `kyle init`.
+//!
+//! Neither serializer keeps comments, so they're carried over by key path:
+//! each comment is recorded against the key it sits above (or beside), the
+//! document is written out fresh, and the comments are put back above the
+//! same keys. A comment whose key no longer has a line of its own, such as
+//! one inside a multi-line array, moves up to the closest key that does.
+
+use super::check::{KYLEFILE_KEYS, STEP_KEYS, TASK_KEYS, VAR_KEYS};
+use super::format::Format;
+use super::{Error, loader};
+use indexmap::IndexMap;
+use regex::Regex;
+use serde_json::{Map, Value};
+use serde_yml::libyml::parser::{Event, Parser, ScalarStyle};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+static BARE_KEY_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_-]+$").unwrap());
+
+type KeyPath = Vec<String>;
+
+/// Comments to write into a Kylefile, each stored without indentation
+#[derive(Debug, Default)]
+pub(super) struct Comments {
+    /// Leading comment block of the file, e.g. the `# kyle: toml` header
+    pub header: Vec<String>,
+    /// Comment lines above a key
+    pub leading: IndexMap<KeyPath, Vec<String>>,
+    /// Comment at the end of a key's line
+    pub trailing: IndexMap<KeyPath, String>,
+    /// Comments after the last key
+    pub footer: Vec<String>,
+}
+
+/// The Kylefile at `path` in the canonical layout, or `None` when it already is
+pub fn reformat(path: &Path, sort_tasks: bool) -> Result<Option<String>, Error> {
+    let content = fs::read_to_string(path)?;
+    let format = loader::kylefile_format(path, &content)?;
+    let formatted = reformat_text(&content, format, sort_tasks)?;
+    Ok(Some(formatted).filter(|formatted| *formatted != content))
+}
+
+/// Rewrite a Kylefile in the canonical layout, keeping its comments.
+/// Tasks keep their order unless `sort_tasks` is set.
+fn reformat_text(content: &str, format: Format, sort_tasks: bool) -> Result<String, Error> {
+    // Refuse to touch a file kyle can't load, or can't write back as it was
+    format.parse(content)?;
+    refuse_yaml_anchors(content, format)?;
+
+    let mut value = format.parse_value(content)?;
+    canonicalize(&mut value, sort_tasks);
+    write(&value, format, &extract(content, format))
+}
+
+/// Kylefiles are written back from their data, which would expand YAML
+/// aliases in place and turn a `<<` merge key into a literal key, so files
+/// using anchors are left for the user to edit by hand.
+pub(super) fn refuse_yaml_anchors(content: &str, format: Format) -> Result<(), Error> {
+    match yaml_anchor_line(content) {
+        Some(line) if format == Format::Yaml => Err(Error::Edit(format!(
+            "line {line} uses a YAML anchor, alias or merge key, which kyle can't keep; edit the file by hand"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Line of the first anchor, alias or `<<` merge key in a YAML document
+fn yaml_anchor_line(content: &str) -> Option<u64> {
+    let mut parser = Parser::new(Cow::Borrowed(content.as_bytes()));
+    loop {
+        let (event, mark) = parser.parse_next_event().ok()?;
+        let found = match &event {
+            Event::StreamEnd => return None,
+            Event::Alias(_) => true,
+            Event::Scalar(scalar) => {
+                scalar.anchor.is_some()
+                    || (scalar.style == ScalarStyle::Plain && &*scalar.value == b"<<")
+            }
+            Event::SequenceStart(start) => start.anchor.is_some(),
+            Event::MappingStart(start) => start.anchor.is_some(),
+            _ => false,
+        };
+        if found {
+            return Some(mark.line() + 1);
+        }
+    }
+}
+
+/// Put keys in the order the docs list them; keys kyle doesn't know keep
+/// their place after the known ones.
+fn canonicalize(value: &mut Value, sort_tasks: bool) {
+    let Some(kylefile) = value.as_object_mut() else {
+        return;
+    };
+    order_keys(kylefile, KYLEFILE_KEYS);
+
+    if let Some(vars) = kylefile.get_mut("vars").and_then(Value::as_object_mut) {
+        for var in vars.values_mut().filter_map(Value::as_object_mut) {
+            order_keys(var, VAR_KEYS);
+        }
+    }
+
+    let Some(tasks) = kylefile.get_mut("tasks").and_then(Value::as_object_mut) else {
+        return;
+    };
+    if sort_tasks {
+        tasks.sort_keys();
+    }
+    for task in tasks.values_mut().filter_map(Value::as_object_mut) {
+        order_keys(task, TASK_KEYS);
+        for key in ["run", "defer", "finally"] {
+            for step in task
+                .get_mut(key)
+                .and_then(Value::as_array_mut)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_object_mut)
+            {
+                order_keys(step, STEP_KEYS);
+            }
+        }
+        for var in task
+            .get_mut("vars")
+            .and_then(Value::as_object_mut)
+            .into_iter()
+            .flat_map(|vars| vars.values_mut())
+            .filter_map(Value::as_object_mut)
+        {
+            order_keys(var, VAR_KEYS);
+        }
+    }
+}
+
+fn order_keys(map: &mut Map<String, Value>, order: &[&str]) {
+    let rank = |key: &str| order.iter().position(|k| *k == key).unwrap_or(order.len());
+    let mut entries: Vec<(String, Value)> = std::mem::take(map).into_iter().collect();
+    entries.sort_by_key(|(key, _)| rank(key));
+    map.extend(entries);
+}
+
+/// Write `value` in the canonical layout for `format`, with `comments` in place
+pub(super) fn write(value: &Value, format: Format, comments: &Comments) -> Result<String, Error> {
+    let body = match format {
+        Format::Toml => toml_text(value)?,
+        Format::Yaml => yaml_text(value)?,
+        Format::Json => {
+            let mut json = serde_json::to_string_pretty(value).map_err(serialize_error)?;
+            json.push('\n');
+            json
+        }
+    };
+    Ok(apply(&body, format, comments))
+}
+
+fn serialize_error(e: impl std::fmt::Display) -> Error {
+    Error::Serialize(e.to_string())
+}
+
+/// Top-level values, then one table per top-level map and per task. Anything
+/// deeper is written inline so a task reads as one block.
+fn toml_text(value: &Value) -> Result<String, Error> {
+    let Some(kylefile) = value.as_object() else {
+        return Err(Error::Serialize("a Kylefile must be a table".into()));
+    };
+
+    let mut out = String::new();
+    // TOML needs plain values before the first table header
+    for (key, value) in kylefile.iter().filter(|(_, v)| !v.is_object()) {
+        out.push_str(&format!("{} = {}\n", toml_key(key), toml_value(value)?));
+    }
+    for (key, table) in kylefile.iter().filter(|(_, v)| v.is_object()) {
+        let Some(table) = table.as_object() else {
+            continue;
+        };
+        if key != "tasks" {
+            toml_table(&mut out, &toml_key(key), table)?;
+            continue;
+        }
+        for (name, task) in table {
+            let header = format!("tasks.{}", toml_key(name));
+            toml_table(&mut out, &header, task.as_object().unwrap_or(&Map::new()))?;
+        }
+    }
+    Ok(out)
+}
+
+fn toml_table(out: &mut String, header: &str, table: &Map<String, Value>) -> Result<(), Error> {
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(&format!("[{header}]\n"));
+    for (key, value) in table {
+        out.push_str(&format!("{} = {}\n", toml_key(key), toml_value(value)?));
+    }
+    Ok(())
+}
+
+fn toml_key(key: &str) -> String {
+    if BARE_KEY_RE.is_match(key) {
+        key.to_string()
+    } else {
+        toml::Value::String(key.to_string()).to_string()
+    }
+}
+
+fn toml_value(value: &Value) -> Result<String, Error> {
+    let value = toml::Value::try_from(value).map_err(serialize_error)?;
+    Ok(value.to_string())
+}
+
+/// serde_yml's layout, with a blank line between tasks and after each
+/// nested top-level block
+fn yaml_text(value: &Value) -> Result<String, Error> {
+    let yaml = serde_yml::to_string(value).map_err(serialize_error)?;
+    // serde_yml writes `{}` for an empty mapping, which reads badly after a header
+    if yaml.trim() == "{}" {
+        return Ok(String::new());
+    }
+
+    let mut out = String::new();
+    let mut nested = false;
+    let mut tasks_seen = 0;
+    for (text, line) in yaml.lines().zip(yaml_lines(&yaml)) {
+        if let Line::Key { path, .. } = &line {
+            let blank = match path.as_slice() {
+                [_] => nested,
+                [tasks, _] if tasks == "tasks" => {
+                    tasks_seen += 1;
+                    tasks_seen > 1
+                }
+                _ => false,
+            };
+            if blank {
+                out.push('\n');
+            }
+            nested = path.len() > 1;
+        } else {
+            nested = true;
+        }
+        out.push_str(text);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// What a line of a Kylefile holds, as far as placing comments goes
+#[derive(Debug, PartialEq)]
+pub(super) enum Line {
+    Blank,
+    /// A whole-line comment; `within` is the key whose multi-line value it sits in
+    Comment {
+        text: String,
+        within: Option<KeyPath>,
+    },
+    /// The line that introduces `path`
+    Key {
+        path: KeyPath,
+        comment: Option<String>,
+    },
+    /// More of a value started on an earlier line
+    Other {
+        within: Option<KeyPath>,
+        comment: Option<String>,
+    },
+}
+
+pub(super) fn lines(content: &str, format: Format) -> Vec<Line> {
+    match format {
+        Format::Toml => toml_lines(content),
+        Format::Yaml => yaml_lines(content),
+        // JSON has no comments, only the `#` header kyle strips before parsing
+        Format::Json => content
+            .lines()
+            .map(|line| match line.trim() {
+                "" => Line::Blank,
+                text if text.starts_with('#') => Line::Comment {
+                    text: text.to_string(),
+                    within: None,
+                },
+                _ => Line::Other {
+                    within: None,
+                    comment: None,
+                },
+            })
+            .collect(),
+    }
+}
+
+/// Record each comment against the key it belongs to
+pub(super) fn extract(content: &str, format: Format) -> Comments {
+    let mut comments = Comments::default();
+    let mut pending: Vec<String> = Vec::new();
+    let mut in_header = true;
+
+    for line in lines(content, format) {
+        // The header is the comment block at the very top of the file
+        if in_header {
+            if let Line::Comment { text, within: None } = line {
+                comments.header.push(text);
+                continue;
+            }
+            in_header = false;
+        }
+        match line {
+            Line::Comment { text, within: None } => pending.push(text),
+            Line::Comment {
+                text,
+                within: Some(path),
+            } => comments.leading.entry(path).or_default().push(text),
+            Line::Blank => {}
+            Line::Key { path, comment } => {
+                if !pending.is_empty() {
+                    comments
+                        .leading
+                        .entry(path.clone())
+                        .or_default()
+                        .append(&mut pending);
+                }
+                if let Some(comment) = comment {
+                    comments.trailing.insert(path, comment);
+                }
+            }
+            Line::Other { within, comment } => {
+                if let Some(comment) = comment {
+                    comments
+                        .leading
+                        .entry(within.unwrap_or_default())
+                        .or_default()
+                        .push(comment);
+                }
+            }
+        }
+    }
+    comments.footer = pending;
+    comments
+}
+
+/// Put `comments` into `body`, which has none of its own
+fn apply(body: &str, format: Format, comments: &Comments) -> String {
+    if format == Format::Json {
+        let mut out = comments.header.join("\n");
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(body);
+        return out;
+    }
+
+    let lines = lines(body, format);
+    let paths: Vec<&KeyPath> = lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Key { path, .. } => Some(path),
+            _ => None,
+        })
+        .collect();
+
+    // Find each comment a line to sit on: its own key, else the first key
+    // beneath it, else the closest key above it, else the top of the body
+    let target = |path: &KeyPath| -> Option<KeyPath> {
+        if paths.contains(&path) {
+            return Some(path.clone());
+        }
+        if let Some(child) = paths.iter().find(|p| p.starts_with(path)) {
+            return Some((*child).clone());
+        }
+        (1..path.len())
+            .rev()
+            .map(|len| path[..len].to_vec())
+            .find(|parent| paths.contains(&parent))
+    };
+
+    let mut top: Vec<String> = Vec::new();
+    let mut leading: HashMap<KeyPath, Vec<String>> = HashMap::new();
+    let mut trailing: HashMap<KeyPath, String> = HashMap::new();
+    for (path, texts) in &comments.leading {
+        match target(path) {
+            Some(path) => leading
+                .entry(path)
+                .or_default()
+                .extend(texts.iter().cloned()),
+            None => top.extend(texts.iter().cloned()),
+        }
+    }
+    for (path, text) in &comments.trailing {
+        match target(path) {
+            Some(found) if found == *path => {
+                trailing.insert(found, text.clone());
+            }
+            Some(found) => leading.entry(found).or_default().push(text.clone()),
+            None => top.push(text.clone()),
+        }
+    }
+
+    let mut out: Vec<String> = comments.header.clone();
+    let header_len = out.len();
+    out.extend(top);
+    for (text, line) in body.lines().zip(&lines) {
+        if let Line::Key { path, .. } = line {
+            let indent = &text[..text.len() - text.trim_start().len()];
+            if let Some(texts) = leading.remove(path) {
+                out.extend(texts.iter().map(|t| format!("{indent}{t}")));
+            }
+            if let Some(comment) = trailing.remove(path) {
+                out.push(format!("{text}  {comment}"));
+                continue;
+            }
+        }
+        out.push(text.to_string());
+    }
+    if !comments.footer.is_empty() {
+        out.push(String::new());
+        out.extend(comments.footer.iter().cloned());
+    }
+
+    // Keep the header its own block, so it's still the header next time
+    if header_len > 0
+        && out
+            .get(header_len)
+            .is_some_and(|l| l.trim_start().starts_with('#'))
+    {
+        out.insert(header_len, String::new());
+    }
+
+    let mut text = out.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Where a TOML string or bracket left open on one line carries on to the next
+#[derive(Default)]
+struct TomlState {
+    /// Closing delimiter of an open multi-line string
+    string: Option<&'static str>,
+    /// Open `[` and `{` outside strings
+    depth: usize,
+}
+
+impl TomlState {
+    fn open(&self) -> bool {
+        self.string.is_some() || self.depth > 0
+    }
+
+    /// Scan a stretch of TOML, returning the trailing comment if there is one
+    fn scan(&mut self, text: &str) -> Option<String> {
+        let bytes = text.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            if let Some(delim) = self.string {
+                match find_closing(text, i, delim) {
+                    Some(end) => {
+                        self.string = None;
+                        i = end;
+                        continue;
+                    }
+                    None => return None,
+                }
+            }
+            match bytes[i] {
+                b'#' => return Some(text[i..].trim_end().to_string()),
+                b'"' | b'\'' => {
+                    let quote = if bytes[i] == b'"' { "\"" } else { "'" };
+                    let triple = if quote == "\"" { "\"\"\"" } else { "'''" };
+                    if text[i..].starts_with(triple) {
+                        self.string = Some(triple);
+                        i += 3;
+                        continue;
+                    }
+                    i = find_closing(text, i + 1, quote).unwrap_or(bytes.len());
+                    continue;
+                }
+                b'[' | b'{' => self.depth += 1,
+                b']' | b'}' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+            i += 1;
+        }
+        None
+    }
+}
+
+/// Index just past `delim` at or after `from`, skipping backslash escapes
+/// in basic strings
+fn find_closing(text: &str, from: usize, delim: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let escapes = delim.starts_with('"');
+    let mut i = from;
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if text[i..].starts_with(delim) {
+            return Some(i + delim.len());
+        }
+        i += 1;
+    }
+    None
+}
+
+fn toml_lines(content: &str) -> Vec<Line> {
+    let mut state = TomlState::default();
+    let mut table: KeyPath = Vec::new();
+    let mut current: Option<KeyPath> = None;
+    let mut counts: HashMap<KeyPath, usize> = HashMap::new();
+
+    content
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            if state.open() {
+                let within = current.clone();
+                if state.string.is_none() && trimmed.starts_with('#') {
+                    return Line::Comment {
+                        text: trimmed.to_string(),
+                        within,
+                    };
+                }
+                let comment = state.scan(line);
+                return Line::Other { within, comment };
+            }
+            if trimmed.is_empty() {
+                return Line::Blank;
+            }
+            if trimmed.starts_with('#') {
+                return Line::Comment {
+                    text: trimmed.to_string(),
+                    within: None,
+                };
+            }
+
+            if let Some(rest) = trimmed.strip_prefix("[[") {
+                if let Some((mut path, rest)) = toml_key_path(rest) {
+                    let index = counts.entry(path.clone()).or_default();
+                    path.push(format!("[{index}]"));
+                    *index += 1;
+                    table = path.clone();
+                    let comment = rest.strip_prefix("]]").and_then(|r| state.scan(r));
+                    return Line::Key { path, comment };
+                }
+            } else if let Some(rest) = trimmed.strip_prefix('[') {
+                if let Some((path, rest)) = toml_key_path(rest) {
+                    table = path.clone();
+                    let comment = rest.strip_prefix(']').and_then(|r| state.scan(r));
+                    return Line::Key { path, comment };
+                }
+            } else if let Some((keys, rest)) = toml_key_path(trimmed)
+                && let Some(value) = rest.strip_prefix('=')
+            {
+                let path: KeyPath = table.iter().cloned().chain(keys).collect();
+                let comment = state.scan(value);
+                current = Some(path.clone());
+                return Line::Key { path, comment };
+            }
+            Line::Other {
+                within: None,
+                comment: None,
+            }
+        })
+        .collect()
+}
+
+/// Parse a dotted TOML key, returning its parts and the text after it
+fn toml_key_path(text: &str) -> Option<(KeyPath, &str)> {
+    let mut parts = Vec::new();
+    let mut rest = text.trim_start();
+    loop {
+        let (part, after) = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = find_closing(quoted, 0, "\"")?;
+            (
+                quoted[..end - 1]
+                    .replace("\\\"", "\"")
+                    .replace("\\\\", "\\"),
+                &quoted[end..],
+            )
+        } else if let Some(quoted) = rest.strip_prefix('\'') {
+            let end = quoted.find('\'')?;
+            (quoted[..end].to_string(), &quoted[end + 1..])
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return None;
+            }
+            (rest[..end].to_string(), &rest[end..])
+        };
+        parts.push(part);
+        rest = after.trim_start();
+        match rest.strip_prefix('.') {
+            Some(after) => rest = after.trim_start(),
+            None => return Some((parts, rest)),
+        }
+    }
+}
+
+/// Where a YAML value left open on one line carries on to the next
+enum YamlState {
+    Plain,
+    /// Inside a `|` or `>` block scalar owned by a key at this indent
+    Block(usize),
+    /// Inside a quoted string that spans lines
+    Quoted(char),
+}
+
+fn yaml_lines(content: &str) -> Vec<Line> {
+    // (indent, path segment, whether it's a sequence item)
+    let mut stack: Vec<(usize, String, bool)> = Vec::new();
+    let mut counts: HashMap<KeyPath, usize> = HashMap::new();
+    let mut state = YamlState::Plain;
+    let path_of = |stack: &[(usize, String, bool)]| -> KeyPath {
+        stack
+            .iter()
+            .map(|(_, segment, _)| segment.clone())
+            .collect()
+    };
+
+    content
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+            let within = Some(path_of(&stack)).filter(|p| !p.is_empty());
+
+            match state {
+                YamlState::Block(owner) if trimmed.is_empty() || indent > owner => {
+                    return if trimmed.is_empty() {
+                        Line::Blank
+                    } else {
+                        Line::Other {
+                            within,
+                            comment: None,
+                        }
+                    };
+                }
+                YamlState::Quoted(quote) => {
+                    if let Some(end) = yaml_closing(line, 0, quote) {
+                        state = YamlState::Plain;
+                        return Line::Other {
+                            within,
+                            comment: yaml_comment(&line[end..]),
+                        };
+                    }
+                    return Line::Other {
+                        within,
+                        comment: None,
+                    };
+                }
+                _ => state = YamlState::Plain,
+            }
+
+            if trimmed.is_empty() {
+                return Line::Blank;
+            }
+            if trimmed.starts_with('#') {
+                return Line::Comment {
+                    text: trimmed.trim_end().to_string(),
+                    within: None,
+                };
+            }
+
+            let (mut indent, mut text) = (indent, trimmed);
+            if text == "-" || text.starts_with("- ") {
+                while stack
+                    .last()
+                    .is_some_and(|(i, _, item)| *i > indent || (*i == indent && *item))
+                {
+                    stack.pop();
+                }
+                let parent = path_of(&stack);
+                let count = counts.entry(parent).or_default();
+                stack.push((indent, format!("[{count}]"), true));
+                *count += 1;
+
+                let rest = text[1..].trim_start();
+                indent += text.len() - rest.len();
+                text = rest;
+                if yaml_key(text).is_none() {
+                    let (comment, next) = yaml_value(text);
+                    state = next;
+                    return Line::Key {
+                        path: path_of(&stack),
+                        comment,
+                    };
+                }
+            }
+
+            let Some((key, value)) = yaml_key(text) else {
+                return Line::Other {
+                    within,
+                    comment: None,
+                };
+            };
+            while stack.last().is_some_and(|(i, _, _)| *i >= indent) {
+                stack.pop();
+            }
+            stack.push((indent, key, false));
+            let path = path_of(&stack);
+            counts.remove(&path);
+
+            let value = value.trim_start();
+            if value.starts_with('|') || value.starts_with('>') {
+                state = YamlState::Block(indent);
+                return Line::Key {
+                    path,
+                    comment: None,
+                };
+            }
+            let (comment, next) = yaml_value(value);
+            state = next;
+            Line::Key { path, comment }
+        })
+        .collect()
+}
+
+/// Split `key: value` into the key and the text after the colon
+fn yaml_key(text: &str) -> Option<(String, &str)> {
+    for quote in ['"', '\''] {
+        if let Some(rest) = text.strip_prefix(quote) {
+            let end = yaml_closing(rest, 0, quote)?;
+            let key = rest[..end - 1].to_string();
+            let after = rest[end..].trim_start().strip_prefix(':')?;
+            if !after.is_empty() && !after.starts_with(' ') {
+                return None;
+            }
+            let key = match quote {
+                '"' => key.replace("\\\"", "\"").replace("\\\\", "\\"),
+                _ => key.replace("''", "'"),
+            };
+            return Some((key, after));
+        }
+    }
+    if text.starts_with(['[', '{', '#', '&', '*', '!', '|', '>', '%', '@', '`']) {
+        return None;
+    }
+    let colon = text
+        .match_indices(':')
+        .map(|(i, _)| i)
+        .find(|&i| text[i + 1..].is_empty() || text[i + 1..].starts_with(' '))?;
+    Some((text[..colon].trim_end().to_string(), &text[colon + 1..]))
+}
+
+/// Index just past the closing `quote`, honouring `\"` and `''` escapes
+fn yaml_closing(text: &str, from: usize, quote: char) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let quote = quote as u8;
+    let mut i = from;
+    while i < bytes.len() {
+        if quote == b'"' && bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i] == quote {
+            if quote == b'\'' && bytes.get(i + 1) == Some(&b'\'') {
+                i += 2;
+                continue;
+            }
+            return Some(i + 1);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// A scalar's trailing comment, and whether a quote is left open
+fn yaml_value(value: &str) -> (Option<String>, YamlState) {
+    let value = value.trim_start();
+    for quote in ['"', '\''] {
+        if let Some(rest) = value.strip_prefix(quote) {
+            return match yaml_closing(rest, 0, quote) {
+                Some(end) => (yaml_comment(&rest[end..]), YamlState::Plain),
+                None => (None, YamlState::Quoted(quote)),
+            };
+        }
+    }
+    (yaml_comment(value), YamlState::Plain)
+}
+
+fn yaml_comment(text: &str) -> Option<String> {
+    if text.starts_with('#') {
+        return Some(text.trim_end().to_string());
+    }
+    text.find(" #")
+        .map(|i| text[i + 1..].trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(content: &str, format: Format) -> String {
+        let formatted = reformat_text(content, format, false).unwrap();
+        assert_eq!(
+            reformat_text(&formatted, format, false).unwrap(),
+            formatted,
+            "formatting is not idempotent"
+        );
+        formatted
+    }
+
+    #[test]
+    fn toml_keeps_comments_in_canonical_layout() {
+        let content = r#"# kyle: toml
+name="demo"   # trailing
+
+# Builds
+[tasks.build]
+run = [
+  # compile first
+  "cargo build",
+]
+desc = "Say \"hi\""
+
+[tasks.build.env]
+MODE = "release"
+
+# the end
+"#;
+        assert_eq!(
+            fmt(content, Format::Toml),
+            r#"# kyle: toml
+name = "demo"  # trailing
+
+# Builds
+[tasks.build]
+desc = 'Say "hi"'
+# compile first
+run = ["cargo build"]
+env = { MODE = "release" }
+
+# the end
+"#
+        );
+    }
+
+    #[test]
+    fn yaml_keeps_comments_in_canonical_layout() {
+        let content = "# kyle: yaml\ntasks:\n    # Lint it\n    lint:\n        run: cargo clippy\n    build:\n        deps: [lint]  # first\n        run:\n            # step one\n            - cargo build\n            - cmd: echo done\n              echo: true\n";
+        assert_eq!(
+            fmt(content, Format::Yaml),
+            "# kyle: yaml\ntasks:\n  # Lint it\n  lint:\n    run: cargo clippy\n\n  build:\n    run:\n    # step one\n    - cargo build\n    - cmd: echo done\n      echo: true\n    deps:  # first\n    - lint\n"
+        );
+    }
+
+    #[test]
+    fn yaml_block_scalars_are_not_comments() {
+        let content = "tasks:\n  build:\n    run: |\n      # kept verbatim\n      make\n";
+        assert_eq!(fmt(content, Format::Yaml), content);
+    }
+
+    #[test]
+    fn sorts_tasks_on_request() {
+        let content = "[tasks.b]\nrun = \"b\"\n\n# A\n[tasks.a]\nrun = \"a\"\n";
+        assert_eq!(fmt(content, Format::Toml), content);
+        assert_eq!(
+            reformat_text(content, Format::Toml, true).unwrap(),
+            "# A\n[tasks.a]\nrun = \"a\"\n\n[tasks.b]\nrun = \"b\"\n"
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_kept() {
+        let content = "[tasks.build]\nrun = \"make\"\ndepends = [\"lint\"]\n";
+        assert!(fmt(content, Format::Toml).contains("depends = [\"lint\"]"));
+    }
+
+    #[test]
+    fn yaml_anchors_are_left_alone() {
+        let content = "x-defaults: &d\n  dir: app\n\ntasks:\n  build:\n    <<: *d\n    run: make\n";
+        let err = reformat_text(content, Format::Yaml, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "can't edit the Kylefile: line 1 uses a YAML anchor, alias or merge key, which kyle can't keep; edit the file by hand"
+        );
+
+        // `&` and `*` inside commands are just text
+        let content = "tasks:\n  build:\n    run: make && ls *.o\n";
+        assert_eq!(fmt(content, Format::Yaml), content);
+    }
+
+    #[test]
+    fn invalid_files_are_left_alone() {
+        assert!(reformat_text("[tasks.build]\nrun = 1\n", Format::Toml, false).is_err());
+    }
+}
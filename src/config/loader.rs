@@ -1,16 +1,23 @@
 use super::format::Format;
 use super::kylefile::Kylefile;
+use super::package_json::PackageManager;
 use super::{
-    Error, composer_json, deno_json, justfile, makefile, package_json, pyproject, rakefile,
-    standard, taskfile,
+    Error, composer_json, deno_json, extends, justfile, makefile, package_json, pyproject,
+    rakefile, standard, taskfile, version,
 };
 use crate::cli::RESERVED_COMMANDS;
 use crate::output;
 use crate::settings;
 use std::fs;
-use std::path::Path;
-
-const DEFAULT_FILENAMES: &[&str] = &["Kylefile", "Kylefile.yaml", "Kylefile.yml", "Kylefile.toml"];
+use std::path::{Path, PathBuf};
+
+const DEFAULT_FILENAMES: &[&str] = &[
+    "Kylefile",
+    "Kylefile.yaml",
+    "Kylefile.yml",
+    "Kylefile.toml",
+    "Kylefile.json",
+];
 const FALLBACK_FILENAMES: &[&str] = &[
     "Makefile",
     "makefile",
@@ -18,7 +25,13 @@ const FALLBACK_FILENAMES: &[&str] = &[
     "justfile",
     "Justfile",
     "Taskfile.yml",
+    "taskfile.yml",
     "Taskfile.yaml",
+    "taskfile.yaml",
+    "Taskfile.dist.yml",
+    "taskfile.dist.yml",
+    "Taskfile.dist.yaml",
+    "taskfile.dist.yaml",
     "Rakefile",
     "rakefile",
     "package.json",
@@ -36,6 +49,9 @@ const FALLBACK_FILENAMES: &[&str] = &[
 ];
 const HEADER_PREFIX: &str = "kyle:";
 
+/// Namespace for addressing user-global tasks explicitly, e.g. `~:docker-prune`
+pub const GLOBAL_NAMESPACE: &str = "~";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Source {
     Kylefile,
@@ -54,6 +70,7 @@ pub enum Source {
     Gradle,
     Maven,
     CMake,
+    Global,
 }
 
 impl std::fmt::Display for Source {
@@ -75,6 +92,7 @@ impl std::fmt::Display for Source {
             Self::Gradle => write!(f, "build.gradle"),
             Self::Maven => write!(f, "pom.xml"),
             Self::CMake => write!(f, "CMakeLists.txt"),
+            Self::Global => write!(f, "global Kylefile"),
         }
     }
 }
@@ -90,59 +108,87 @@ pub fn load(path: &str) -> Result<(Kylefile, Source), Error> {
 /// Load a Kylefile from a specific directory
 /// This is used for namespace resolution
 pub fn load_from_dir(dir: &Path) -> Result<(Kylefile, Source), Error> {
-    for name in DEFAULT_FILENAMES {
-        let path = dir.join(name);
-        if path.exists() {
-            return load_file(&path);
-        }
-    }
-
-    for name in FALLBACK_FILENAMES {
-        let path = dir.join(name);
-        if path.exists() {
-            return load_file(&path);
-        }
+    if let Some(path) = find_file(dir) {
+        return load_file(&path);
     }
 
     if let Some(result) = find_by_extension(dir) {
         return result;
     }
 
-    let all_names: Vec<&'static str> = DEFAULT_FILENAMES
+    Err(not_found())
+}
+
+/// First task file in `dir`, in detection order. A Kylefile always wins.
+pub fn find_file(dir: &Path) -> Option<PathBuf> {
+    DEFAULT_FILENAMES
         .iter()
-        .chain(FALLBACK_FILENAMES.iter())
-        .copied()
-        .collect();
+        .chain(FALLBACK_FILENAMES)
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+}
 
-    Err(Error::NotFound(all_names))
+fn not_found() -> Error {
+    Error::NotFound(
+        DEFAULT_FILENAMES
+            .iter()
+            .chain(FALLBACK_FILENAMES)
+            .copied()
+            .collect(),
+    )
 }
 
+/// Load the project's task file with user-global tasks merged beneath it.
+/// Falls back to the global tasks alone when the project has no task file.
 fn load_from_current_dir() -> Result<(Kylefile, Source), Error> {
-    for name in DEFAULT_FILENAMES {
-        let path = Path::new(name);
-        if path.exists() {
-            return load_file(path);
+    let project = load_project();
+
+    let global = match load_global() {
+        Ok(global) => global,
+        Err(e) => {
+            output::warn(&format!("ignoring global Kylefile: {e}"));
+            None
+        }
+    };
+
+    match (project, global) {
+        (Ok((mut kylefile, source)), Some(global)) => {
+            kylefile.merge_global(global);
+            Ok((kylefile, source))
         }
+        (Err(Error::NotFound(_)), Some(global)) => {
+            let mut kylefile = Kylefile::default();
+            kylefile.merge_global(global);
+            Ok((kylefile, Source::Global))
+        }
+        (result, None) => result,
+        (Err(e), Some(_)) => Err(e),
     }
+}
 
-    for name in FALLBACK_FILENAMES {
-        let path = Path::new(name);
+/// Load the user-global Kylefile from the config directory, if there is one
+pub fn load_global() -> Result<Option<Kylefile>, Error> {
+    let dir = settings::dir();
+    for name in DEFAULT_FILENAMES {
+        let path = dir.join(name);
         if path.exists() {
-            return load_file(path);
+            return load_file(&path).map(|(kylefile, _)| Some(kylefile));
         }
     }
+    Ok(None)
+}
+
+fn load_project() -> Result<(Kylefile, Source), Error> {
+    // An empty base keeps paths relative, e.g. `Kylefile` rather than `./Kylefile`
+    if let Some(path) = find_file(Path::new("")) {
+        return load_file(&path);
+    }
 
     if let Some(result) = find_by_extension(Path::new(".")) {
         return result;
     }
 
-    let all_names: Vec<&'static str> = DEFAULT_FILENAMES
-        .iter()
-        .chain(FALLBACK_FILENAMES.iter())
-        .copied()
-        .collect();
-
-    Err(Error::NotFound(all_names))
+    Err(not_found())
 }
 
 const EXTENSION_MAP: &[(&str, Source)] = &[(".csproj", Source::CSharpProject)];
@@ -164,21 +210,96 @@ fn find_by_extension(dir: &Path) -> Option<Result<(Kylefile, Source), Error>> {
     None
 }
 
+impl Source {
+    /// Whether commands from this source are written with `{{ var }}` templates
+    pub fn uses_templates(self) -> bool {
+        matches!(
+            self,
+            Self::Kylefile | Self::Global | Self::Taskfile | Self::Justfile
+        )
+    }
+
+    /// The command that runs `task` through the tool that owns this source,
+    /// for sources whose tasks are defined by that tool. package.json scripts
+    /// run through `manager`, npm if unknown.
+    pub fn delegate(self, task: &str, manager: Option<PackageManager>) -> Option<String> {
+        let tool = match self {
+            Self::Makefile => "make",
+            Self::Justfile => "just",
+            Self::Taskfile => "task",
+            Self::Rakefile => "rake",
+            Self::PackageJson => manager.unwrap_or(PackageManager::Npm).run_command(),
+            Self::ComposerJson => "composer run-script",
+            Self::DenoJson => "deno task",
+            _ => return None,
+        };
+        // Tasks from just modules are named `mod:task`; just spells that `mod::task`
+        if self == Self::Justfile {
+            return Some(format!("{tool} {}", task.replace(':', "::")));
+        }
+        Some(format!("{tool} {task}"))
+    }
+}
+
 fn load_file(path: &Path) -> Result<(Kylefile, Source), Error> {
+    let (kylefile, source) = read_file(path)?;
+    if source == Source::Kylefile {
+        warn_reserved_tasks(&kylefile);
+        for deprecated in &kylefile.deprecated {
+            output::warn(&deprecated.message);
+        }
+    }
+    Ok((kylefile, source))
+}
+
+/// Load a task file without the warnings `load_file` prints, for `kyle check`
+/// to report the same problems itself
+pub(super) fn read_file(path: &Path) -> Result<(Kylefile, Source), Error> {
+    let (mut kylefile, source) = parse_file(path)?;
+    kylefile.apply_platform();
+    if !source.uses_templates() {
+        kylefile.escape_templates();
+    }
+    Ok((kylefile, source))
+}
+
+/// Whether kyle reads `path` as a native Kylefile rather than another tool's file
+pub fn is_kylefile(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    !FALLBACK_FILENAMES.contains(&name) && !name.ends_with(".csproj")
+}
+
+/// Load a task file for `kyle import` or `kyle export`. Unlike `load`, every
+/// platform's tasks are kept and no global tasks are merged in.
+pub fn load_for_conversion(path: &Path) -> Result<(Kylefile, Source), Error> {
+    let (mut kylefile, source) = parse_file(path)?;
+    if !source.uses_templates() {
+        kylefile.escape_templates();
+    }
+    Ok((kylefile, source))
+}
+
+fn parse_file(path: &Path) -> Result<(Kylefile, Source), Error> {
     let content = fs::read_to_string(path)?;
 
     let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
 
     if matches!(filename, "Makefile" | "makefile" | "GNUmakefile") {
-        return Ok((makefile::parse(&content)?, Source::Makefile));
+        return Ok((
+            makefile::parse(&content, path.parent().unwrap_or(Path::new("")))?,
+            Source::Makefile,
+        ));
     }
 
     if matches!(filename, "justfile" | "Justfile") {
-        return Ok((justfile::parse(&content)?, Source::Justfile));
+        return Ok((
+            justfile::parse(&content, path.parent().unwrap_or(Path::new("")))?,
+            Source::Justfile,
+        ));
     }
 
-    if matches!(filename, "Taskfile.yml" | "Taskfile.yaml") {
-        return Ok((taskfile::parse(&content)?, Source::Taskfile));
+    if taskfile::TASKFILE_NAMES.contains(&filename) {
+        return Ok((taskfile::parse(&content, path)?, Source::Taskfile));
     }
 
     if matches!(filename, "Rakefile" | "rakefile") {
@@ -186,7 +307,10 @@ fn load_file(path: &Path) -> Result<(Kylefile, Source), Error> {
     }
 
     if filename == "package.json" {
-        return Ok((package_json::parse(&content)?, Source::PackageJson));
+        return Ok((
+            package_json::parse(&content, path.parent().unwrap_or(Path::new("")))?,
+            Source::PackageJson,
+        ));
     }
 
     if filename == "composer.json" {
@@ -229,24 +353,43 @@ fn load_file(path: &Path) -> Result<(Kylefile, Source), Error> {
         return Ok((standard::cmake(), Source::CMake));
     }
 
+    let kylefile = parse_kylefile(path, &content)?;
+    let kylefile = extends::resolve(kylefile, path)?;
+    Ok((kylefile, Source::Kylefile))
+}
+
+/// Parse a native Kylefile, picking the format from its extension or header
+pub(super) fn parse_kylefile(path: &Path, content: &str) -> Result<Kylefile, Error> {
+    let format = kylefile_format(path, content)?;
+    let value = format.parse_value(content)?;
+    let declared = value.get("version").and_then(|v| v.as_str());
+
+    // Check the version first: a file written for a newer kyle may use
+    // fields this one can't parse, and "upgrade" is the useful answer
+    if let Some(required) = declared {
+        version::require(required)?;
+    }
+
+    let mut kylefile = format.parse(content)?;
+    kylefile.deprecated = version::deprecated_keys(&value, declared, version::DEPRECATIONS);
+    Ok(kylefile)
+}
+
+pub(super) fn kylefile_format(path: &Path, content: &str) -> Result<Format, Error> {
     let ext = path
         .extension()
         .and_then(|e| e.to_str())
         .map(|e| format!(".{e}"));
 
-    let format = match ext {
+    match ext {
         Some(ref e) => {
-            Format::from_extension(e).ok_or_else(|| Error::UnsupportedExtension(e.clone()))?
+            Format::from_extension(e).ok_or_else(|| Error::UnsupportedExtension(e.clone()))
         }
         None => {
-            let format_name = detect_format_from_header(&content);
-            Format::from_name(&format_name).ok_or(Error::UnknownFormat(format_name))?
+            let format_name = detect_format_from_header(content);
+            Format::from_name(&format_name).ok_or(Error::UnknownFormat(format_name))
         }
-    };
-
-    let kylefile = format.parse(&content)?;
-    warn_reserved_tasks(&kylefile);
-    Ok((kylefile, Source::Kylefile))
+    }
 }
 
 fn warn_reserved_tasks(kylefile: &Kylefile) {
@@ -259,13 +402,13 @@ fn warn_reserved_tasks(kylefile: &Kylefile) {
     }
 }
 
+/// Find the `# kyle: <format>` header among the file's leading comment lines
 fn detect_format_from_header(content: &str) -> String {
     content
         .lines()
-        .next()
-        .and_then(|line| {
-            let line = line.trim().strip_prefix('#')?.trim();
-            let format = line.strip_prefix(HEADER_PREFIX)?.trim();
+        .map_while(|line| line.trim().strip_prefix('#'))
+        .find_map(|comment| {
+            let format = comment.trim().strip_prefix(HEADER_PREFIX)?.trim();
             Some(format.to_ascii_lowercase())
         })
         .unwrap_or_else(|| settings::get().default_format)
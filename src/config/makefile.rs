@@ -1,97 +1,988 @@
 use super::Error;
-use super::kylefile::{Kylefile, Task};
+use super::kylefile::{Kylefile, Run, Step, Task};
+use indexmap::IndexMap;
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
 use std::sync::LazyLock;
 
-static TARGET_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^([a-zA-Z_][a-zA-Z0-9_\-\.]*)[ \t]*:([^=].*)?$").unwrap());
+/// Targets kyle lists as tasks; other targets are files such as `build/app.o`
+static TARGET_NAME_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-zA-Z_][a-zA-Z0-9_\-\.]*$").unwrap());
 
-static PHONY_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\.PHONY\s*:\s*(.+)$").unwrap());
+static COMMENT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^#+\s*(.*)$").unwrap());
 
-static COMMENT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^#\s*(.*)$").unwrap());
+/// `##@ Section` headers that `make help` scripts print between targets
+static SECTION_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^##@\s*(.*)$").unwrap());
 
-pub fn parse(content: &str) -> Result<Kylefile, Error> {
-    let mut tasks: HashMap<String, Task> = HashMap::new();
-    let mut pending_comment: Option<String> = None;
+/// `NAME = value` and the other assignment operators
+static ASSIGN_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([^\s:#=?+!]+)\s*(:::=|::=|:=|\?=|\+=|!=|=)\s*(.*)$").unwrap());
 
-    let lines: Vec<&str> = content.lines().collect();
-    let mut i = 0;
+static DEFINE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^define\s+([^\s:#=?+!]+)\s*(:::=|::=|:=|\?=|\+=|!=|=)?\s*$").unwrap()
+});
 
-    while i < lines.len() {
-        let line = lines[i];
+static CONDITIONAL_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(ifeq|ifneq|ifdef|ifndef)(?:\s+(.*))?$").unwrap());
 
-        if PHONY_RE.is_match(line) {
-            pending_comment = None;
-            i += 1;
-            continue;
+static INCLUDE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(include|-include|sinclude)\s+(.+)$").unwrap());
+
+static SHELL_NAME_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+/// How deep `include` may nest before kyle assumes a cycle
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// How deep variables may refer to each other before kyle assumes a self-reference
+const MAX_EXPANSION_DEPTH: usize = 32;
+
+/// Variables make defines without being told
+const BUILTIN_VARIABLES: &[(&str, &str)] = &[
+    ("AR", "ar"),
+    ("AS", "as"),
+    ("CC", "cc"),
+    ("CPP", "$(CC) -E"),
+    ("CXX", "g++"),
+    ("LD", "ld"),
+    ("MAKE", "make"),
+    ("RM", "rm -f"),
+    ("SHELL", "/bin/sh"),
+];
+
+/// Variables make sets from its own state, which the shell can't stand in for
+const MAKE_STATE_VARIABLES: &[&str] = &[
+    "CURDIR",
+    "GPATH",
+    "MAKECMDGOALS",
+    "MAKEFILES",
+    "MAKEFILE_LIST",
+    "MAKEFLAGS",
+    "MAKELEVEL",
+    "MAKEOVERRIDES",
+    "MAKESHELL",
+    "MAKE_HOST",
+    "MAKE_RESTARTS",
+    "MAKE_TERMERR",
+    "MAKE_TERMOUT",
+    "MAKE_VERSION",
+    "MFLAGS",
+    "SUFFIXES",
+    "VPATH",
+];
+
+const FUNCTIONS: &[&str] = &[
+    "abspath",
+    "addprefix",
+    "addsuffix",
+    "and",
+    "basename",
+    "call",
+    "dir",
+    "error",
+    "eval",
+    "file",
+    "filter",
+    "filter-out",
+    "findstring",
+    "firstword",
+    "flavor",
+    "foreach",
+    "if",
+    "info",
+    "join",
+    "lastword",
+    "notdir",
+    "or",
+    "origin",
+    "patsubst",
+    "realpath",
+    "shell",
+    "sort",
+    "strip",
+    "subst",
+    "suffix",
+    "value",
+    "warning",
+    "wildcard",
+    "word",
+    "wordlist",
+    "words",
+];
+
+/// Read a Makefile, following includes relative to `dir`. Variables are
+/// expanded the way make would for the parts kyle can model; the rest is
+/// left as written and reported in task notes or the Kylefile's warnings.
+pub fn parse(content: &str, dir: &Path) -> Result<Kylefile, Error> {
+    let mut parser = Parser {
+        dir,
+        vars: IndexMap::new(),
+        exported: Vec::new(),
+        export_all: false,
+        rules: Vec::new(),
+        warnings: Vec::new(),
+        depth: 0,
+    };
+    parser.read(content, "Makefile");
+    Ok(parser.finish())
+}
+
+struct Variable {
+    value: String,
+    /// Set with `=`, so expanded where it's used rather than where it's set
+    recursive: bool,
+    /// References left in an immediately expanded value
+    unexpanded: Vec<String>,
+}
+
+struct Rule {
+    targets: Vec<String>,
+    prereqs: Vec<String>,
+    order_only: Vec<String>,
+    recipe: Vec<String>,
+    double_colon: bool,
+    desc: String,
+    group: String,
+}
+
+struct Conditional {
+    /// Whether lines in the current branch are read
+    active: bool,
+    /// Whether the enclosing block is being read
+    parent: bool,
+    /// Whether an earlier branch was taken
+    taken: bool,
+}
+
+/// Where an expansion ends up: make-level text such as prerequisites, or a
+/// recipe that the shell will see
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    Make,
+    Recipe,
+}
+
+/// Automatic variables for one target of a rule
+struct Automatic<'a> {
+    target: &'a str,
+    prereqs: &'a [String],
+    order_only: &'a [String],
+}
+
+#[derive(Default)]
+struct Unexpanded {
+    variables: Vec<String>,
+    functions: Vec<String>,
+}
+
+impl Unexpanded {
+    fn add(list: &mut Vec<String>, reference: &str) {
+        if !list.iter().any(|r| r == reference) {
+            list.push(reference.to_string());
         }
+    }
+}
+
+struct Parser<'a> {
+    dir: &'a Path,
+    vars: IndexMap<String, Variable>,
+    exported: Vec<String>,
+    export_all: bool,
+    rules: Vec<Rule>,
+    warnings: Vec<String>,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn read(&mut self, content: &str, file: &str) {
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        let mut rule: Option<usize> = None;
+        let mut comment: Option<String> = None;
+        let mut group = String::new();
+        // (name, operator, body, line)
+        let mut define: Option<(String, String, Vec<String>, usize)> = None;
+
+        for (no, line) in logical_lines(content) {
+            let at = |message: &str| format!("{file}:{no}: {message}");
+            let active = conditionals.iter().all(|c| c.active);
 
-        if let Some(caps) = COMMENT_RE.captures(line) {
-            let comment = caps[1].trim().to_string();
-            if !comment.is_empty() {
-                pending_comment = Some(comment);
+            if let Some((_, _, body, _)) = &mut define {
+                if line.trim() == "endef" {
+                    let (name, op, body, _) = define.take().expect("define is Some");
+                    if active {
+                        self.assign(&name, &op, &body.join("\n"));
+                    }
+                } else {
+                    body.push(line);
+                }
+                continue;
+            }
+
+            // A recipe is every tab-indented line after a rule
+            if let Some(index) = rule
+                && let Some(recipe_line) = line.strip_prefix('\t')
+            {
+                if active {
+                    self.rules[index].recipe.push(recipe_line.to_string());
+                }
+                continue;
             }
-            i += 1;
-            continue;
-        }
 
-        if let Some(caps) = TARGET_RE.captures(line) {
-            let target_name = caps[1].to_string();
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                comment = None;
+                continue;
+            }
+            if let Some(caps) = SECTION_RE.captures(trimmed) {
+                if active {
+                    group = caps[1].trim().to_string();
+                }
+                comment = None;
+                continue;
+            }
+            if let Some(caps) = COMMENT_RE.captures(trimmed) {
+                let text = caps[1].trim();
+                if active && !text.is_empty() {
+                    comment = Some(text.to_string());
+                }
+                continue;
+            }
+            let (trimmed, trailing) = split_comment(trimmed);
 
-            if target_name.starts_with('.') || target_name.contains('%') {
-                pending_comment = None;
-                i += 1;
+            if let Some(caps) = CONDITIONAL_RE.captures(trimmed) {
+                let parent = active;
+                let cond =
+                    parent && self.condition(&caps[1], caps.get(2).map_or("", |m| m.as_str()), &at);
+                conditionals.push(Conditional {
+                    active: cond,
+                    parent,
+                    taken: cond,
+                });
+                continue;
+            }
+            if trimmed == "else" || trimmed.starts_with("else ") {
+                let Some(top) = conditionals.last_mut() else {
+                    self.warnings.push(at("`else` without a matching `if`"));
+                    continue;
+                };
+                let rest = trimmed["else".len()..].trim();
+                let cond = if rest.is_empty() {
+                    true
+                } else if let Some(caps) = CONDITIONAL_RE.captures(rest) {
+                    let (parent, taken) = (top.parent, top.taken);
+                    !taken
+                        && parent
+                        && self.condition(&caps[1], caps.get(2).map_or("", |m| m.as_str()), &at)
+                } else {
+                    self.warnings
+                        .push(at(&format!("not understood: {trimmed}")));
+                    false
+                };
+                let top = conditionals.last_mut().expect("checked above");
+                top.active = top.parent && !top.taken && cond;
+                top.taken |= top.active;
+                continue;
+            }
+            if trimmed == "endif" {
+                if conditionals.pop().is_none() {
+                    self.warnings.push(at("`endif` without a matching `if`"));
+                }
                 continue;
             }
 
-            let deps_str = caps.get(2).map(|m| m.as_str()).unwrap_or("");
-            let deps: Vec<String> = deps_str
-                .split_whitespace()
-                .filter(|d| !d.starts_with('$') && !d.contains('%'))
-                .map(|s| s.to_string())
-                .collect();
-
-            let mut commands: Vec<String> = Vec::new();
-            i += 1;
-            while i < lines.len() {
-                let cmd_line = lines[i];
-                if cmd_line.starts_with('\t') {
-                    let cmd = cmd_line.trim_start_matches('\t');
-                    let cmd = cmd.strip_prefix('@').unwrap_or(cmd);
-                    let cmd = cmd.strip_prefix('-').unwrap_or(cmd);
-                    if !cmd.trim().is_empty() {
-                        commands.push(cmd.to_string());
-                    }
-                    i += 1;
-                } else if cmd_line.trim().is_empty() {
-                    i += 1;
+            if !active {
+                continue;
+            }
+            rule = None;
+            // `target: ## Description` wins over a comment above the target
+            let desc = match trailing.and_then(|t| t.strip_prefix('#')) {
+                Some(doc) => doc.trim().to_string(),
+                None => comment.take().unwrap_or_default(),
+            };
+            comment = None;
+
+            let mut text = trimmed;
+            let mut export = false;
+            loop {
+                if let Some(rest) = strip_word(text, "override") {
+                    text = rest;
+                } else if let Some(rest) = strip_word(text, "export") {
+                    export = true;
+                    text = rest;
                 } else {
                     break;
                 }
             }
 
-            let task = Task {
-                desc: pending_comment.take().unwrap_or_default(),
-                run: commands.join(" && "),
-                deps,
+            if let Some(caps) = DEFINE_RE.captures(text) {
+                let name = caps[1].to_string();
+                if export {
+                    self.export(&name);
+                }
+                let op = caps.get(2).map_or("=", |m| m.as_str()).to_string();
+                define = Some((name, op, Vec::new(), no));
+                continue;
+            }
+            if let Some(caps) = INCLUDE_RE.captures(text) {
+                let required = &caps[1] == "include";
+                self.include(&caps[2], required, &at);
+                continue;
+            }
+            if let Some(names) = strip_word(text, "unexport") {
+                let names = self.expand_make(names);
+                self.exported
+                    .retain(|name| !names.split_whitespace().any(|n| n == name));
+                continue;
+            }
+            if text.starts_with("vpath") {
+                continue;
+            }
+            if let Some(caps) = ASSIGN_RE.captures(text) {
+                let name = self.expand_make(&caps[1]);
+                self.assign(&name, &caps[2], &caps[3]);
+                if export {
+                    self.export(&name);
+                }
+                continue;
+            }
+            if export {
+                if text.is_empty() {
+                    self.export_all = true;
+                }
+                for name in self.expand_make(text).split_whitespace() {
+                    self.export(name);
+                }
+                continue;
+            }
+
+            rule = self.rule(text, desc, &group, &at);
+        }
+
+        if let Some((name, _, _, no)) = define {
+            self.warnings
+                .push(format!("{file}:{no}: `define {name}` has no `endef`"));
+        }
+        if !conditionals.is_empty() {
+            self.warnings
+                .push(format!("{file}: missing `endif` at end of file"));
+        }
+    }
+
+    fn export(&mut self, name: &str) {
+        if !self.exported.iter().any(|n| n == name) {
+            self.exported.push(name.to_string());
+        }
+    }
+
+    fn assign(&mut self, name: &str, op: &str, value: &str) {
+        match op {
+            "=" => self.set(name, value.to_string(), true, Vec::new()),
+            "?=" => {
+                if !self.vars.contains_key(name) {
+                    self.set(name, value.to_string(), true, Vec::new());
+                }
+            }
+            "+=" => {
+                if let Some(var) = self.vars.get(name) {
+                    let (addition, unexpanded) = if var.recursive {
+                        (value.to_string(), Vec::new())
+                    } else {
+                        self.expand_immediate(value)
+                    };
+                    let var = self.vars.get_mut(name).expect("checked above");
+                    if !var.value.is_empty() {
+                        var.value.push(' ');
+                    }
+                    var.value.push_str(&addition);
+                    var.unexpanded.extend(unexpanded);
+                } else {
+                    self.set(name, value.to_string(), true, Vec::new());
+                }
+            }
+            // `!=` runs a shell command; the shell can do that when the value is used
+            "!=" => {
+                let (command, unexpanded) = self.expand_immediate(value);
+                self.set(name, format!("$({command})"), false, unexpanded);
+            }
+            // `:=`, `::=` and `:::=`
+            _ => {
+                let (value, unexpanded) = self.expand_immediate(value);
+                self.set(name, value, false, unexpanded);
+            }
+        }
+    }
+
+    fn set(&mut self, name: &str, value: String, recursive: bool, unexpanded: Vec<String>) {
+        self.vars.insert(
+            name.to_string(),
+            Variable {
+                value,
+                recursive,
+                unexpanded,
+            },
+        );
+    }
+
+    fn include(&mut self, names: &str, required: bool, at: &dyn Fn(&str) -> String) {
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            self.warnings.push(at("includes nest too deeply; stopped"));
+            return;
+        }
+        for name in self.expand_make(names).split_whitespace() {
+            match fs::read_to_string(self.dir.join(name)) {
+                Ok(content) => {
+                    self.depth += 1;
+                    self.read(&content, name);
+                    self.depth -= 1;
+                }
+                Err(_) if required => {
+                    self.warnings
+                        .push(at(&format!("included file not found: {name}")));
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn condition(&mut self, directive: &str, args: &str, at: &dyn Fn(&str) -> String) -> bool {
+        match directive {
+            "ifdef" | "ifndef" => {
+                let name = self.expand_make(args);
+                let defined = self
+                    .vars
+                    .get(name.trim())
+                    .is_some_and(|var| !var.value.is_empty());
+                defined == (directive == "ifdef")
+            }
+            _ => {
+                let Some((a, b)) = comparison(args) else {
+                    self.warnings
+                        .push(at(&format!("not understood: {directive} {args}")));
+                    return false;
+                };
+                let equal = self.expand_make(a).trim() == self.expand_make(b).trim();
+                equal == (directive == "ifeq")
+            }
+        }
+    }
+
+    /// Record a rule, returning its index so recipe lines can follow
+    fn rule(
+        &mut self,
+        text: &str,
+        desc: String,
+        group: &str,
+        at: &dyn Fn(&str) -> String,
+    ) -> Option<usize> {
+        let Some(colon) = find_unnested(text, ':') else {
+            self.warnings.push(at(&format!("not understood: {text}")));
+            return None;
+        };
+        let mut rest = &text[colon + 1..];
+        let double_colon = rest.starts_with(':');
+        if double_colon {
+            rest = &rest[1..];
+        }
+        let (prereqs, inline) = match find_unnested(rest, ';') {
+            Some(semi) => (&rest[..semi], Some(rest[semi + 1..].trim_start())),
+            None => (rest, None),
+        };
+        if ASSIGN_RE.is_match(prereqs.trim()) {
+            self.warnings
+                .push(at("target-specific variables are not supported"));
+            return None;
+        }
+        if find_unnested(prereqs, ':').is_some() {
+            self.warnings
+                .push(at("static pattern rules are not supported"));
+            // Keep a rule with no targets so its recipe is skipped with it
+            self.rules.push(Rule {
+                targets: Vec::new(),
+                prereqs: Vec::new(),
+                order_only: Vec::new(),
+                recipe: Vec::new(),
+                double_colon,
+                desc,
+                group: group.to_string(),
+            });
+            return Some(self.rules.len() - 1);
+        }
+
+        let words =
+            |text: String| -> Vec<String> { text.split_whitespace().map(String::from).collect() };
+        let targets = words(self.expand_make(&text[..colon]));
+        let prereqs = self.expand_make(prereqs);
+        let (prereqs, order_only) = prereqs.split_once('|').unwrap_or((&prereqs, ""));
+
+        self.rules.push(Rule {
+            targets,
+            prereqs: words(prereqs.to_string()),
+            order_only: words(order_only.to_string()),
+            recipe: inline.map(String::from).into_iter().collect(),
+            double_colon,
+            desc,
+            group: group.to_string(),
+        });
+        Some(self.rules.len() - 1)
+    }
+
+    fn expand_make(&self, text: &str) -> String {
+        self.expand(text, Context::Make, None, &mut Unexpanded::default(), 0)
+    }
+
+    /// Expand a `:=` value, keeping what couldn't be expanded for the notes
+    fn expand_immediate(&self, text: &str) -> (String, Vec<String>) {
+        let mut unexpanded = Unexpanded::default();
+        let value = self.expand(text, Context::Recipe, None, &mut unexpanded, 0);
+        let mut refs = unexpanded.variables;
+        refs.extend(unexpanded.functions);
+        (value, refs)
+    }
+
+    fn expand(
+        &self,
+        text: &str,
+        context: Context,
+        auto: Option<&Automatic>,
+        unexpanded: &mut Unexpanded,
+        depth: usize,
+    ) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(dollar) = rest.find('$') {
+            out.push_str(&rest[..dollar]);
+            let after = &rest[dollar + 1..];
+            let Some(next) = after.chars().next() else {
+                out.push('$');
+                rest = after;
+                break;
+            };
+
+            let (inner, reference_len) = match next {
+                '$' => {
+                    out.push('$');
+                    rest = &after[1..];
+                    continue;
+                }
+                '(' | '{' => {
+                    let close = if next == '(' { ')' } else { '}' };
+                    match find_close(after, next, close) {
+                        Some(end) => (&after[1..end], end + 1),
+                        None => {
+                            out.push_str(&rest[dollar..]);
+                            rest = "";
+                            break;
+                        }
+                    }
+                }
+                c => (&after[..c.len_utf8()], c.len_utf8()),
             };
+            let reference = &rest[dollar..dollar + 1 + reference_len];
+            rest = &after[reference_len..];
+
+            let expanded = self.reference(inner, reference, context, auto, unexpanded, depth);
+            out.push_str(&expanded);
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// Expand the text inside one `$(...)`
+    fn reference(
+        &self,
+        inner: &str,
+        reference: &str,
+        context: Context,
+        auto: Option<&Automatic>,
+        unexpanded: &mut Unexpanded,
+        depth: usize,
+    ) -> String {
+        if depth > MAX_EXPANSION_DEPTH {
+            Unexpanded::add(&mut unexpanded.variables, reference);
+            return reference.to_string();
+        }
+
+        // Functions: only `shell` has an equivalent, the shell's own `$(...)`
+        let word_end = inner.find([' ', '\t', ',']).unwrap_or(inner.len());
+        let word = &inner[..word_end];
+        if FUNCTIONS.contains(&word) && inner[word_end..].starts_with([' ', '\t']) {
+            if word == "shell" {
+                let command = inner[word_end..].trim();
+                let command = self.expand(command, context, auto, unexpanded, depth + 1);
+                return format!("$({command})");
+            }
+            Unexpanded::add(&mut unexpanded.functions, reference);
+            return reference.to_string();
+        }
+
+        if let Some(value) = self.automatic(inner, auto) {
+            return value;
+        }
+        if inner.len() == 1 && "@<^+|?*%".contains(inner)
+            || inner.len() == 2 && "DF".contains(&inner[1..]) && "@<^+|?*%".contains(&inner[..1])
+        {
+            Unexpanded::add(&mut unexpanded.variables, reference);
+            return reference.to_string();
+        }
+
+        let inner = self.expand(inner, context, auto, unexpanded, depth + 1);
+
+        // `$(VAR:.c=.o)` substitution references
+        if let Some((name, spec)) = inner.split_once(':')
+            && let Some((from, to)) = spec.split_once('=')
+            && !name.contains(char::is_whitespace)
+        {
+            let value = self.variable(name, context, auto, unexpanded, depth);
+            return value
+                .split_whitespace()
+                .map(|word| substitute(word, from, to))
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+
+        self.variable(&inner, context, auto, unexpanded, depth)
+    }
+
+    fn variable(
+        &self,
+        name: &str,
+        context: Context,
+        auto: Option<&Automatic>,
+        unexpanded: &mut Unexpanded,
+        depth: usize,
+    ) -> String {
+        if let Some(var) = self.vars.get(name) {
+            if var.recursive {
+                return self.expand(&var.value, context, auto, unexpanded, depth + 1);
+            }
+            for reference in &var.unexpanded {
+                Unexpanded::add(&mut unexpanded.variables, reference);
+            }
+            return var.value.clone();
+        }
+        if let Some((_, value)) = BUILTIN_VARIABLES.iter().find(|(n, _)| *n == name) {
+            return self.expand(value, context, auto, unexpanded, depth + 1);
+        }
+        // make reads undefined variables from the environment; in a recipe
+        // the shell can do the same, except for make's own state
+        match context {
+            Context::Recipe if MAKE_STATE_VARIABLES.contains(&name) || name.starts_with('.') => {
+                let reference = format!("$({name})");
+                Unexpanded::add(&mut unexpanded.variables, &reference);
+                reference
+            }
+            Context::Recipe if SHELL_NAME_RE.is_match(name) => format!("${{{name}}}"),
+            _ => String::new(),
+        }
+    }
+
+    /// `$@`, `$<`, `$^`, `$+`, `$|` and `$?`, plus their `D` and `F` forms
+    fn automatic(&self, name: &str, auto: Option<&Automatic>) -> Option<String> {
+        let auto = auto?;
+        let mut chars = name.chars();
+        let symbol = chars.next()?;
+        let part = chars.next();
+        if chars.next().is_some() || part.is_some_and(|p| p != 'D' && p != 'F') {
+            return None;
+        }
+
+        let mut deduped: Vec<&str> = Vec::new();
+        for prereq in auto.prereqs {
+            if !deduped.contains(&prereq.as_str()) {
+                deduped.push(prereq);
+            }
+        }
+        let words: Vec<&str> = match symbol {
+            '@' => vec![auto.target],
+            '<' => auto
+                .prereqs
+                .first()
+                .map(String::as_str)
+                .into_iter()
+                .collect(),
+            // Tasks always run, so every prerequisite counts as newer
+            '^' | '?' => deduped,
+            '+' => auto.prereqs.iter().map(String::as_str).collect(),
+            '|' => auto.order_only.iter().map(String::as_str).collect(),
+            _ => return None,
+        };
+        Some(
+            words
+                .into_iter()
+                .map(|word| match part {
+                    Some('D') => match word.rfind('/') {
+                        Some(slash) => word[..slash].to_string(),
+                        None => ".".to_string(),
+                    },
+                    Some(_) => word.rsplit('/').next().unwrap_or(word).to_string(),
+                    None => word.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+    }
+
+    fn finish(self) -> Kylefile {
+        let mut tasks: IndexMap<String, (Task, Vec<Step>)> = IndexMap::new();
+        // Only prerequisites with a rule of their own can run as deps; the
+        // rest are files such as `main.o` built by pattern rules
+        let targets: HashSet<&str> = self
+            .rules
+            .iter()
+            .flat_map(|rule| &rule.targets)
+            .map(String::as_str)
+            .filter(|target| TARGET_NAME_RE.is_match(target))
+            .collect();
+
+        for rule in &self.rules {
+            for target in &rule.targets {
+                if !TARGET_NAME_RE.is_match(target) {
+                    continue;
+                }
+                let auto = Automatic {
+                    target,
+                    prereqs: &rule.prereqs,
+                    order_only: &rule.order_only,
+                };
+                let mut unexpanded = Unexpanded::default();
+                // Expand first: a variable can supply the `@` or `-` prefix
+                let steps: Vec<Step> = rule
+                    .recipe
+                    .iter()
+                    .flat_map(|line| {
+                        let expanded =
+                            self.expand(line, Context::Recipe, Some(&auto), &mut unexpanded, 0);
+                        expanded
+                            .lines()
+                            .filter_map(parse_recipe_line)
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+
+                let (task, existing) = tasks.entry(target.clone()).or_default();
+                if task.desc.is_empty() {
+                    task.desc = rule.desc.clone();
+                }
+                if task.group.is_empty() {
+                    task.group = rule.group.clone();
+                }
+                let mut dropped = Vec::new();
+                for dep in rule.prereqs.iter().chain(&rule.order_only) {
+                    if !targets.contains(dep.as_str()) {
+                        dropped.push(dep.as_str());
+                    } else if !task.deps.contains(dep) {
+                        task.deps.push(dep.clone());
+                    }
+                }
+
+                // A later `:` recipe replaces an earlier one; `::` recipes all run
+                if !steps.is_empty() {
+                    if !rule.double_colon {
+                        existing.clear();
+                    }
+                    existing.extend(steps);
+                }
+
+                if !dropped.is_empty() {
+                    task.notes
+                        .push(format!("prerequisites not imported: {}", dropped.join(" ")));
+                }
+                if !unexpanded.variables.is_empty() {
+                    task.notes.push(format!(
+                        "make variables are not expanded: {}",
+                        unexpanded.variables.join(" ")
+                    ));
+                }
+                if !unexpanded.functions.is_empty() {
+                    task.notes.push(format!(
+                        "make functions are not expanded: {}",
+                        unexpanded.functions.join(" ")
+                    ));
+                }
+            }
+        }
+
+        let mut env = IndexMap::new();
+        for (name, var) in &self.vars {
+            if self.export_all || self.exported.contains(name) {
+                let value = if var.recursive {
+                    self.expand_make(&var.value)
+                } else {
+                    var.value.clone()
+                };
+                env.insert(name.clone(), value);
+            }
+        }
+
+        Kylefile {
+            tasks: tasks
+                .into_iter()
+                .map(|(name, (task, steps))| {
+                    (
+                        name,
+                        Task {
+                            run: Run::from_steps(steps),
+                            ..task
+                        },
+                    )
+                })
+                .collect(),
+            env,
+            warnings: self.warnings,
+            ..Default::default()
+        }
+    }
+}
+
+/// Lines with backslash continuations joined, each with the number of its
+/// first line. Outside recipes make folds the break into one space; in a
+/// recipe the shell sees it, so the break just disappears.
+fn logical_lines(content: &str) -> Vec<(usize, String)> {
+    let mut out = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+
+    for (i, line) in content.lines().enumerate() {
+        let (no, mut text) = match current.take() {
+            Some((no, mut text)) => {
+                if text.starts_with('\t') {
+                    text.push_str(line.strip_prefix('\t').unwrap_or(line));
+                } else {
+                    text.push(' ');
+                    text.push_str(line.trim_start());
+                }
+                (no, text)
+            }
+            None => (i + 1, line.to_string()),
+        };
 
-            tasks.insert(target_name, task);
-            continue;
+        let backslashes = text.len() - text.trim_end_matches('\\').len();
+        if backslashes % 2 == 1 {
+            text.pop();
+            if !text.starts_with('\t') {
+                text.truncate(text.trim_end().len());
+            }
+            current = Some((no, text));
+        } else {
+            out.push((no, text));
         }
+    }
+    out.extend(current);
+    out
+}
 
-        if line.trim().is_empty() || !line.starts_with('\t') {
-            pending_comment = None;
+/// A make line split at its comment; `\#` is a literal `#`
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut prev = '\0';
+    for (i, c) in line.char_indices() {
+        if c == '#' && prev != '\\' {
+            return (line[..i].trim_end(), Some(&line[i + 1..]));
         }
-        i += 1;
+        prev = c;
     }
+    (line, None)
+}
 
-    Ok(Kylefile {
-        name: String::new(),
-        includes: Default::default(),
-        tasks,
-    })
+/// `text` without a leading keyword, when it starts with one
+fn strip_word<'a>(text: &'a str, word: &str) -> Option<&'a str> {
+    let rest = text.strip_prefix(word)?;
+    if rest.is_empty() {
+        return Some(rest);
+    }
+    rest.starts_with([' ', '\t']).then(|| rest.trim_start())
+}
+
+/// The first `c` outside `$(...)` and `${...}`
+fn find_unnested(text: &str, c: char) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut prev = '\0';
+    for (i, ch) in text.char_indices() {
+        match ch {
+            '(' | '{' if prev == '$' || depth > 0 => depth += 1,
+            ')' | '}' if depth > 0 => depth -= 1,
+            _ if ch == c && depth == 0 => return Some(i),
+            _ => {}
+        }
+        prev = ch;
+    }
+    None
+}
+
+/// Index of the bracket closing the one `text` starts with
+fn find_close(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, ch) in text.char_indices() {
+        if ch == open {
+            depth += 1;
+        } else if ch == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// The two sides of `ifeq (a,b)`, `ifeq "a" "b"` or `ifeq 'a' 'b'`
+fn comparison(args: &str) -> Option<(&str, &str)> {
+    let args = args.trim();
+    if let Some(inner) = args.strip_prefix('(').and_then(|a| a.strip_suffix(')')) {
+        let comma = find_unnested(inner, ',')?;
+        return Some((&inner[..comma], &inner[comma + 1..]));
+    }
+    let quoted = |text: &str| -> Option<(usize, usize)> {
+        let quote = text.chars().next().filter(|q| *q == '"' || *q == '\'')?;
+        let end = text[1..].find(quote)? + 1;
+        Some((1, end))
+    };
+    let (start, end) = quoted(args)?;
+    let rest = args[end + 1..].trim_start();
+    let (start2, end2) = quoted(rest)?;
+    Some((&args[start..end], &rest[start2..end2]))
+}
+
+/// Apply one `from=to` substitution to a word; without a `%` it matches a suffix
+fn substitute(word: &str, from: &str, to: &str) -> String {
+    let (from, to) = if from.contains('%') {
+        (from.to_string(), to.to_string())
+    } else {
+        (format!("%{from}"), format!("%{to}"))
+    };
+    let (prefix, suffix) = from.split_once('%').expect("pattern has a %");
+    match word
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_suffix(suffix))
+    {
+        Some(stem) => to.replacen('%', stem, 1),
+        None => word.to_string(),
+    }
+}
+
+/// Parse a recipe line, honouring Make's `@` (silent), `-` (ignore errors)
+/// and `+` prefixes in any order.
+fn parse_recipe_line(line: &str) -> Option<Step> {
+    let mut silent = false;
+    let mut ignore_error = false;
+    let mut cmd = line.trim_start();
+    loop {
+        if let Some(rest) = cmd.strip_prefix('@') {
+            silent = true;
+            cmd = rest;
+        } else if let Some(rest) = cmd.strip_prefix('-') {
+            ignore_error = true;
+            cmd = rest;
+        } else if let Some(rest) = cmd.strip_prefix('+') {
+            cmd = rest;
+        } else {
+            break;
+        }
+    }
+    if cmd.trim().is_empty() {
+        return None;
+    }
+    Some(Step::cmd(cmd, !silent, ignore_error))
 }
 
 #[cfg(test)]
@@ -101,36 +992,66 @@ mod tests {
     #[test]
     fn parse_simple_target() {
         let content = "build:\n\tgcc -o main main.c\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert!(kf.tasks.contains_key("build"));
-        assert_eq!(kf.tasks["build"].run, "gcc -o main main.c");
+        assert_eq!(kf.tasks["build"].run.commands(), vec!["gcc -o main main.c"]);
     }
 
     #[test]
     fn parse_target_with_deps() {
-        let content = "test: build\n\t./run_tests.sh\n";
-        let kf = parse(content).unwrap();
+        let content = "test: build\n\t./run_tests.sh\n\nbuild:\n\tcc main.c\n";
+        let kf = parse(content, Path::new("")).unwrap();
         assert_eq!(kf.tasks["test"].deps, vec!["build"]);
     }
 
     #[test]
     fn parse_comment_as_description() {
         let content = "# Build the project\nbuild:\n\tmake all\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert_eq!(kf.tasks["build"].desc, "Build the project");
     }
 
+    #[test]
+    fn parse_trailing_doc_comments() {
+        let content = "# Above\nbuild: deps ## Build the project\n\techo build\n\nlint: # not a doc\n\techo lint\n\ndeps:\n\techo deps\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.tasks["build"].desc, "Build the project");
+        assert_eq!(kf.tasks["build"].deps, vec!["deps"]);
+        assert_eq!(kf.tasks["lint"].desc, "");
+        assert!(kf.tasks["lint"].deps.is_empty());
+    }
+
+    #[test]
+    fn parse_section_headers_as_groups() {
+        let content = "help: ## Show help\n\n##@ Development\nbuild: ## Build\n\n##@ Release\n\npublish: ## Publish\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.tasks["help"].group, "");
+        assert_eq!(kf.tasks["build"].group, "Development");
+        assert_eq!(kf.tasks["build"].desc, "Build");
+        assert_eq!(kf.tasks["publish"].group, "Release");
+    }
+
+    #[test]
+    fn strip_comments_from_assignments() {
+        let content = "CC := clang # compiler\n\nbuild:\n\t$(CC) main.c # keep\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["build"].run.commands(),
+            vec!["clang main.c # keep"]
+        );
+    }
+
     #[test]
     fn parse_phony_targets() {
         let content = ".PHONY: build test\n\nbuild:\n\techo build\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert!(kf.tasks.contains_key("build"));
     }
 
     #[test]
     fn skip_pattern_rules() {
         let content = "%.o: %.c\n\tgcc -c $<\n\nbuild:\n\techo build\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert!(!kf.tasks.contains_key("%.o"));
         assert!(kf.tasks.contains_key("build"));
     }
@@ -138,36 +1059,301 @@ mod tests {
     #[test]
     fn multi_line_commands() {
         let content = "build:\n\techo step1\n\techo step2\n";
-        let kf = parse(content).unwrap();
-        assert_eq!(kf.tasks["build"].run, "echo step1 && echo step2");
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["build"].run.commands(),
+            vec!["echo step1", "echo step2"]
+        );
     }
 
     #[test]
     fn skip_variable_assignments() {
         let content = "CC := gcc\n\nbuild:\n\t$(CC) main.c\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert!(kf.tasks.contains_key("build"));
         assert!(!kf.tasks.contains_key("CC"));
     }
 
+    #[test]
+    fn expand_variables() {
+        let content = "build: $(OBJS) lib\n\t$(CC) -o $@ $^\n\techo $$HOME ${CC} $(PREFIX)\n\nlib:\n\tar rcs lib.a\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.tasks["build"].deps, vec!["lib"]);
+        assert_eq!(
+            kf.tasks["build"].run.commands(),
+            vec!["cc -o build lib", "echo $HOME cc ${PREFIX}"]
+        );
+        assert!(kf.tasks["build"].notes.is_empty());
+    }
+
+    #[test]
+    fn file_prerequisites_are_not_deps() {
+        let content = "app: main.o util.o lib\n\tcc -o $@ $^\n\n%.o: %.c\n\tcc -c $<\n\nlib:\n\tar rcs lib.a\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.tasks["app"].deps, vec!["lib"]);
+        assert_eq!(
+            kf.tasks["app"].run.commands(),
+            vec!["cc -o app main.o util.o lib"]
+        );
+        assert_eq!(
+            kf.tasks["app"].notes,
+            vec!["prerequisites not imported: main.o util.o"]
+        );
+    }
+
+    #[test]
+    fn make_state_variables_are_kept() {
+        let content = "info:\n\techo $(CURDIR) $(MAKEFILE_LIST) $(.DEFAULT_GOAL) $(HOME)\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["info"].run.commands(),
+            vec!["echo $(CURDIR) $(MAKEFILE_LIST) $(.DEFAULT_GOAL) ${HOME}"]
+        );
+        assert_eq!(
+            kf.tasks["info"].notes,
+            vec!["make variables are not expanded: $(CURDIR) $(MAKEFILE_LIST) $(.DEFAULT_GOAL)"]
+        );
+    }
+
+    #[test]
+    fn assignment_flavours() {
+        let content = "\
+A = one
+B := $(A)
+A = two
+C ?= first
+C ?= second
+D = x
+D += $(A)
+E != git describe
+F := $(shell date)
+
+show:
+\techo $(A) $(B) $(C) $(D) $(E) $(F)
+";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["show"].run.commands(),
+            vec!["echo two one first x two $(git describe) $(date)"]
+        );
+    }
+
+    #[test]
+    fn note_unexpanded_functions() {
+        let content = "SRC := $(wildcard *.c)\n\nbuild:\n\tcc $(SRC) $(notdir $@)\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["build"].notes,
+            vec![
+                "make variables are not expanded: $(wildcard *.c)",
+                "make functions are not expanded: $(notdir $@)",
+            ]
+        );
+    }
+
+    #[test]
+    fn substitution_references() {
+        let content = "SRCS = a.c lib/b.c\n\nbuild:\n\tcc $(SRCS:.c=.o) $(SRCS:%.c=out/%.o)\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["build"].run.commands(),
+            vec!["cc a.o lib/b.o out/a.o out/lib/b.o"]
+        );
+    }
+
+    #[test]
+    fn variable_supplies_prefix() {
+        let content = "Q = @\n\nbuild:\n\t$(Q)echo quiet\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.tasks["build"].run, "echo quiet");
+    }
+
+    #[test]
+    fn export_variables_to_env() {
+        let content =
+            "export GOOS = linux\nARCH := amd64\nexport ARCH\nHIDDEN = 1\n\nbuild:\n\tgo build\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.env.len(), 2);
+        assert_eq!(kf.env["GOOS"], "linux");
+        assert_eq!(kf.env["ARCH"], "amd64");
+    }
+
+    #[test]
+    fn join_continued_lines() {
+        let content = "\
+FLAGS = -a \\
+        -b
+
+build: one \\
+       two
+\tcc $(FLAGS) \\
+\t  main.c
+
+one two:
+\techo $@
+";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.tasks["build"].deps, vec!["one", "two"]);
+        assert_eq!(kf.tasks["build"].run.commands(), vec!["cc -a -b   main.c"]);
+    }
+
+    #[test]
+    fn register_every_target_of_a_rule() {
+        let content = "# Check it\nlint test: build\n\techo $@\n\nbuild:\n\tcc main.c\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.tasks["lint"].run.commands(), vec!["echo lint"]);
+        assert_eq!(kf.tasks["test"].run.commands(), vec!["echo test"]);
+        assert_eq!(kf.tasks["test"].deps, vec!["build"]);
+        assert_eq!(kf.tasks["test"].desc, "Check it");
+    }
+
+    #[test]
+    fn double_colon_rules_append() {
+        let content = "clean::\n\trm -rf a\n\nclean:: tidy\n\trm -rf b\n\nall: x\n\techo 1\nall: y\n\techo 2\n\ntidy x y:\n\techo $@\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["clean"].run.commands(),
+            vec!["rm -rf a", "rm -rf b"]
+        );
+        assert_eq!(kf.tasks["clean"].deps, vec!["tidy"]);
+        assert_eq!(kf.tasks["all"].run.commands(), vec!["echo 2"]);
+        assert_eq!(kf.tasks["all"].deps, vec!["x", "y"]);
+    }
+
+    #[test]
+    fn inline_recipe_and_order_only_prereqs() {
+        let content = "build: src | out ; cc -o out/app $< $|\n\nsrc out:\n\tmkdir -p $@\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.tasks["build"].deps, vec!["src", "out"]);
+        assert_eq!(
+            kf.tasks["build"].run.commands(),
+            vec!["cc -o out/app src out"]
+        );
+    }
+
+    #[test]
+    fn evaluate_conditionals() {
+        let content = "\
+MODE = release
+ifeq ($(MODE),debug)
+FLAGS = -g
+else ifeq ($(MODE),release)
+FLAGS = -O2
+else
+FLAGS = -O0
+endif
+ifdef MISSING
+build:
+\techo never
+endif
+ifndef MISSING
+build:
+\tcc $(FLAGS)
+endif
+";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.tasks["build"].run.commands(), vec!["cc -O2"]);
+        assert!(kf.warnings.is_empty());
+    }
+
+    #[test]
+    fn define_blocks() {
+        let content = "define SETUP\n@echo one\necho two\nendef\n\nbuild:\n\t$(SETUP)\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["build"].run,
+            Run::Steps(vec![
+                Step::Command("echo one".into()),
+                Step::cmd("echo two", true, false),
+            ])
+        );
+    }
+
+    #[test]
+    fn follow_includes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("common.mk"),
+            "CC := clang\n\n# Lint it\nlint:\n\techo lint\n",
+        )
+        .unwrap();
+        let content = "include common.mk\n-include local.mk\ninclude missing.mk\n\nbuild: lint\n\t$(CC) main.c\n";
+        let kf = parse(content, dir.path()).unwrap();
+        assert_eq!(kf.tasks["lint"].desc, "Lint it");
+        assert_eq!(kf.tasks["build"].run.commands(), vec!["clang main.c"]);
+        assert_eq!(
+            kf.warnings,
+            vec!["Makefile:3: included file not found: missing.mk"]
+        );
+    }
+
+    #[test]
+    fn warn_about_unsupported_constructs() {
+        let content = "\
+build: CFLAGS += -g
+objs: %.o: %.c
+\tcc $<
+ifeq (a,a)
+build:
+\techo build
+";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.tasks["build"].run.commands(), vec!["echo build"]);
+        assert!(!kf.tasks.contains_key("objs"));
+        assert_eq!(
+            kf.warnings,
+            vec![
+                "Makefile:1: target-specific variables are not supported",
+                "Makefile:2: static pattern rules are not supported",
+                "Makefile: missing `endif` at end of file",
+            ]
+        );
+    }
+
     #[test]
     fn handle_silent_prefix() {
         let content = "build:\n\t@echo building\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert_eq!(kf.tasks["build"].run, "echo building");
     }
 
+    #[test]
+    fn echo_unless_silent() {
+        let content = "build:\n\t@echo quiet\n\techo loud\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["build"].run,
+            Run::Steps(vec![
+                Step::Command("echo quiet".into()),
+                Step::cmd("echo loud", true, false),
+            ])
+        );
+    }
+
     #[test]
     fn handle_ignore_errors_prefix() {
         let content = "clean:\n\t-rm -rf build/\n";
-        let kf = parse(content).unwrap();
-        assert_eq!(kf.tasks["clean"].run, "rm -rf build/");
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["clean"].run,
+            Run::Steps(vec![Step::cmd("rm -rf build/", true, true)])
+        );
+    }
+
+    #[test]
+    fn combined_prefixes() {
+        let content = "clean:\n\t-@rm -rf build/\n";
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(
+            kf.tasks["clean"].run,
+            Run::Steps(vec![Step::cmd("rm -rf build/", false, true)])
+        );
     }
 
     #[test]
     fn multiple_targets() {
         let content = "# Build\nbuild:\n\techo build\n\n# Test\ntest: build\n\techo test\n";
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert_eq!(kf.tasks.len(), 2);
         assert_eq!(kf.tasks["build"].desc, "Build");
         assert_eq!(kf.tasks["test"].desc, "Test");
@@ -1,19 +1,41 @@
+mod check;
 mod composer_json;
 mod deno_json;
+mod edit;
+mod export;
+mod extends;
 mod format;
+mod jsonc;
 mod justfile;
 mod kylefile;
+mod layout;
 mod loader;
 mod makefile;
 mod package_json;
+mod platform;
 mod pyproject;
 mod rakefile;
+mod schema;
 mod standard;
 mod taskfile;
+mod version;
 
+pub use check::{Diagnostic, check};
+pub use edit::{add_task, remove_task};
+pub use export::{Exported, GENERATED, Target as ExportTarget, export, merge_scripts};
 pub use format::Format;
-pub use kylefile::{Includes, Kylefile, Task};
-pub use loader::{Source, load, load_from_dir};
+pub(crate) use jsonc::parse as parse_jsonc;
+pub use kylefile::{Confirm, Includes, Kylefile, Param, Run, Step, Task, Var};
+pub(crate) use kylefile::{TEMPLATE_RE, escape_templates};
+pub use layout::reformat;
+pub use loader::{
+    GLOBAL_NAMESPACE, Source, find_file, is_kylefile, load, load_for_conversion, load_from_dir,
+    load_global,
+};
+pub use package_json::PackageManager;
+pub use platform::current as current_platform;
+pub use schema::{URL as SCHEMA_URL, generate as schema};
+pub use version::Deprecated;
 
 use thiserror::Error;
 
@@ -31,6 +53,18 @@ pub enum Error {
     #[error("json parse error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("failed to serialize Kylefile: {0}")]
+    Serialize(String),
+
+    #[error("task '{0}' already exists")]
+    TaskExists(String),
+
+    #[error("task '{0}' not found")]
+    TaskNotFound(String),
+
+    #[error("can't edit the Kylefile: {0}")]
+    Edit(String),
+
     #[error("unknown format: {0}")]
     UnknownFormat(String),
 
@@ -39,4 +73,20 @@ pub enum Error {
 
     #[error("no Kylefile found (looked for: {0:?})")]
     NotFound(Vec<&'static str>),
+
+    #[error("failed to load extended Kylefile '{path}': {source}")]
+    Extends {
+        path: String,
+        #[source]
+        source: Box<Error>,
+    },
+
+    #[error("circular extends: {0}")]
+    ExtendsCycle(String),
+
+    #[error("this Kylefile needs kyle >= {required} (running {current}); run `kyle upgrade`")]
+    VersionTooNew { required: String, current: String },
+
+    #[error("invalid version '{0}': expected MAJOR.MINOR.PATCH")]
+    InvalidVersion(String),
 }
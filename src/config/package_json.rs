@@ -1,41 +1,270 @@
 use super::Error;
-use super::kylefile::{Kylefile, Task};
-use std::collections::HashMap;
+use super::jsonc;
+use super::kylefile::{Kylefile, Run, Step, Task, escape_templates};
+use indexmap::IndexMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-pub fn parse(content: &str) -> Result<Kylefile, Error> {
-    let pkg: serde_json::Value = serde_json::from_str(content)?;
-    let name = pkg["name"].as_str().unwrap_or("").to_string();
-    let mut tasks = HashMap::new();
-
-    if let Some(scripts) = pkg["scripts"].as_object() {
-        for (key, val) in scripts {
-            if let Some(cmd) = val.as_str() {
-                tasks.insert(
-                    key.clone(),
-                    Task {
-                        run: cmd.to_string(),
-                        ..Default::default()
-                    },
-                );
+/// Lockfiles that give away the package manager, checked in order
+const LOCKFILES: &[(&str, PackageManager)] = &[
+    ("bun.lock", PackageManager::Bun),
+    ("bun.lockb", PackageManager::Bun),
+    ("pnpm-lock.yaml", PackageManager::Pnpm),
+    ("yarn.lock", PackageManager::Yarn),
+    ("package-lock.json", PackageManager::Npm),
+];
+
+/// The tool that runs a package.json's scripts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    Npm,
+    Pnpm,
+    Yarn,
+    Bun,
+}
+
+/// The package manager a package.json says it uses, and where that was found
+struct Detected {
+    manager: PackageManager,
+    /// Major version from `packageManager`, e.g. 9 for `pnpm@9.1.0`
+    major: Option<u64>,
+    /// The package's own directory, or the workspace root it belongs to
+    root: PathBuf,
+}
+
+impl Detected {
+    /// From the `packageManager` field, then the lockfile in `dir`. A
+    /// workspace member usually has neither, so the workspace root's are used.
+    fn find(pkg: &serde_json::Value, dir: &Path) -> Option<Self> {
+        let dir = fs::canonicalize(if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        })
+        .unwrap_or_else(|_| dir.to_path_buf());
+        if let Some(found) = Self::in_dir(pkg, &dir) {
+            return Some(found);
+        }
+        for ancestor in dir.ancestors().skip(1) {
+            let root_pkg = fs::read_to_string(ancestor.join("package.json"))
+                .ok()
+                .and_then(|content| jsonc::parse::<serde_json::Value>(&content).ok());
+            let pnpm_workspace = ancestor.join("pnpm-workspace.yaml").exists();
+            if pnpm_workspace
+                || root_pkg
+                    .as_ref()
+                    .is_some_and(|pkg| !pkg["workspaces"].is_null())
+            {
+                let found = Self::in_dir(&root_pkg.unwrap_or_default(), ancestor);
+                return found.or_else(|| {
+                    pnpm_workspace.then(|| Self {
+                        manager: PackageManager::Pnpm,
+                        major: None,
+                        root: ancestor.to_path_buf(),
+                    })
+                });
             }
+            // Don't wander out of the repository
+            if ancestor.join(".git").exists() {
+                break;
+            }
+        }
+        None
+    }
+
+    fn in_dir(pkg: &serde_json::Value, dir: &Path) -> Option<Self> {
+        let declared = pkg["packageManager"].as_str().and_then(|spec| {
+            let (name, version) = spec.split_once('@').unwrap_or((spec, ""));
+            let major = version
+                .split('.')
+                .next()
+                .and_then(|major| major.parse().ok());
+            Some((PackageManager::from_name(name)?, major))
+        });
+        let (manager, major) = declared.or_else(|| {
+            LOCKFILES
+                .iter()
+                .find(|(file, _)| dir.join(file).exists())
+                .map(|(_, manager)| (*manager, None))
+        })?;
+        Some(Self {
+            manager,
+            major,
+            root: dir.to_path_buf(),
+        })
+    }
+
+    /// Whether running `name` also runs `prename` and `postname`. pnpm 7 turned
+    /// them off unless `.npmrc` sets `enable-pre-post-scripts`; pnpm 8 turned
+    /// them back on.
+    fn runs_hooks(&self, dir: &Path) -> bool {
+        if self.manager != PackageManager::Pnpm {
+            return true;
+        }
+        let setting = [dir, &self.root].into_iter().find_map(|dir| {
+            npmrc(dir)
+                .get("npm_config_enable_pre_post_scripts")
+                .map(|value| value == "true")
+        });
+        setting.unwrap_or(self.major != Some(7))
+    }
+}
+
+impl PackageManager {
+    /// From the `packageManager` field, then the lockfile, in `dir` or else at
+    /// the root of the workspace it belongs to; npm otherwise
+    pub fn detect(pkg: &serde_json::Value, dir: &Path) -> Self {
+        Detected::find(pkg, dir).map_or(Self::Npm, |detected| detected.manager)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "npm" => Some(Self::Npm),
+            "pnpm" => Some(Self::Pnpm),
+            "yarn" => Some(Self::Yarn),
+            "bun" => Some(Self::Bun),
+            _ => None,
+        }
+    }
+
+    /// The command that runs a script, e.g. `pnpm run`
+    pub fn run_command(self) -> &'static str {
+        match self {
+            Self::Npm => "npm run",
+            Self::Pnpm => "pnpm run",
+            Self::Yarn => "yarn run",
+            Self::Bun => "bun run",
+        }
+    }
+
+    /// Whether arguments for the script only pass through after `--`
+    pub fn needs_separator(self) -> bool {
+        self == Self::Npm
+    }
+}
+
+impl fmt::Display for PackageManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Npm => "npm",
+            Self::Pnpm => "pnpm",
+            Self::Yarn => "yarn",
+            Self::Bun => "bun",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Read package.json scripts as tasks. Without the package manager to run
+/// them, kyle does its part: `pre`/`post` hooks run around each script and
+/// the `npm_*` environment is set.
+pub fn parse(content: &str, dir: &Path) -> Result<Kylefile, Error> {
+    let pkg: serde_json::Value = jsonc::parse(content)?;
+    let name = pkg["name"].as_str().unwrap_or("").to_string();
+    let detected = Detected::find(&pkg, dir);
+    let manager = detected
+        .as_ref()
+        .map_or(PackageManager::Npm, |detected| detected.manager);
+    let runs_hooks = detected.is_none_or(|detected| detected.runs_hooks(dir));
+    let scripts: IndexMap<&str, &str> = pkg["scripts"]
+        .as_object()
+        .map(|scripts| {
+            scripts
+                .iter()
+                .filter_map(|(key, val)| Some((key.as_str(), val.as_str()?)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let is_hook = |key: &str| {
+        runs_hooks
+            && ["pre", "post"].iter().any(|prefix| {
+                key.strip_prefix(prefix)
+                    .is_some_and(|base| scripts.contains_key(base))
+            })
+    };
+    let mut tasks = IndexMap::new();
+    for (&key, &cmd) in &scripts {
+        let hook = |prefix: &str| {
+            let hook = format!("{prefix}{key}");
+            (runs_hooks && scripts.contains_key(hook.as_str())).then_some(hook)
+        };
+        let (pre, post) = (hook("pre"), hook("post"));
+        let run = if pre.is_none() && post.is_none() {
+            cmd.into()
+        } else {
+            let call = |task: String| Step::Task {
+                task,
+                args: Vec::new(),
+            };
+            Run::Steps(
+                pre.map(call)
+                    .into_iter()
+                    .chain(std::iter::once(Step::Command(cmd.to_string())))
+                    .chain(post.map(call))
+                    .collect(),
+            )
+        };
+        tasks.insert(
+            key.to_string(),
+            Task {
+                run,
+                private: is_hook(key),
+                env: IndexMap::from([
+                    ("npm_lifecycle_event".to_string(), key.to_string()),
+                    ("npm_lifecycle_script".to_string(), escape_templates(cmd)),
+                ]),
+                ..Default::default()
+            },
+        );
+    }
+
+    let mut env = IndexMap::from([("npm_command".to_string(), "run-script".to_string())]);
+    for field in ["name", "version"] {
+        if let Some(value) = pkg[field].as_str() {
+            env.insert(format!("npm_package_{field}"), escape_templates(value));
         }
     }
+    env.extend(npmrc(dir));
 
     Ok(Kylefile {
         name,
+        env,
         tasks,
+        package_manager: Some(manager),
         ..Default::default()
     })
 }
 
+/// `.npmrc` settings as the `npm_config_*` variables npm exports to scripts.
+/// Registry credentials (`//host/:_authToken=...`) are left out.
+fn npmrc(dir: &Path) -> IndexMap<String, String> {
+    let Ok(content) = fs::read_to_string(dir.join(".npmrc")) else {
+        return IndexMap::new();
+    };
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with(['#', ';']))
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| !key.trim().starts_with("//") && !key.contains("_auth"))
+        .map(|(key, value)| {
+            let key = key.trim().replace(['-', '.'], "_").to_lowercase();
+            (format!("npm_config_{key}"), escape_templates(value.trim()))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
 
     #[test]
     fn parse_scripts() {
         let content = r#"{"name": "my-app", "scripts": {"build": "tsc", "test": "vitest"}}"#;
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert_eq!(kf.name, "my-app");
         assert_eq!(kf.tasks.len(), 2);
         assert_eq!(kf.tasks["build"].run, "tsc");
@@ -45,21 +274,149 @@ mod tests {
     #[test]
     fn parse_no_scripts() {
         let content = r#"{"name": "my-app", "version": "1.0.0"}"#;
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert!(kf.tasks.is_empty());
     }
 
     #[test]
     fn parse_empty_scripts() {
         let content = r#"{"name": "my-app", "scripts": {}}"#;
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert!(kf.tasks.is_empty());
     }
 
     #[test]
     fn parse_compound_command() {
         let content = r#"{"scripts": {"build": "tsc && vite build"}}"#;
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("")).unwrap();
         assert_eq!(kf.tasks["build"].run, "tsc && vite build");
     }
+
+    #[test]
+    fn detect_package_manager() {
+        let temp = TempDir::new().unwrap();
+        let pkg = |json: &str| serde_json::from_str::<serde_json::Value>(json).unwrap();
+        assert_eq!(
+            PackageManager::detect(&pkg("{}"), temp.path()),
+            PackageManager::Npm
+        );
+        fs::write(temp.path().join("yarn.lock"), "").unwrap();
+        assert_eq!(
+            PackageManager::detect(&pkg("{}"), temp.path()),
+            PackageManager::Yarn
+        );
+        fs::write(temp.path().join("bun.lock"), "").unwrap();
+        assert_eq!(
+            PackageManager::detect(&pkg("{}"), temp.path()),
+            PackageManager::Bun
+        );
+        assert_eq!(
+            PackageManager::detect(
+                &pkg(r#"{"packageManager": "pnpm@9.1.0+sha512.abc"}"#),
+                temp.path()
+            ),
+            PackageManager::Pnpm
+        );
+    }
+
+    #[test]
+    fn hooks_run_around_scripts() {
+        let content = r#"{"scripts": {"prebuild": "rm -rf dist", "build": "tsc", "postbuild": "echo done", "prepare": "husky"}}"#;
+        let kf = parse(content, Path::new("")).unwrap();
+        assert_eq!(kf.package_manager, Some(PackageManager::Npm));
+        assert_eq!(
+            kf.tasks["build"].run,
+            Run::Steps(vec![
+                Step::Task {
+                    task: "prebuild".into(),
+                    args: vec![],
+                },
+                Step::Command("tsc".into()),
+                Step::Task {
+                    task: "postbuild".into(),
+                    args: vec![],
+                },
+            ])
+        );
+        assert!(kf.tasks["prebuild"].private);
+        assert!(kf.tasks["postbuild"].private);
+        assert!(!kf.tasks["prepare"].private);
+    }
+
+    #[test]
+    fn workspace_members_use_the_root_package_manager() {
+        let temp = TempDir::new().unwrap();
+        fs::write(
+            temp.path().join("package.json"),
+            r#"{"workspaces": ["packages/*"]}"#,
+        )
+        .unwrap();
+        fs::write(temp.path().join("yarn.lock"), "").unwrap();
+        let member = temp.path().join("packages/web");
+        fs::create_dir_all(&member).unwrap();
+        let pkg = serde_json::json!({"name": "web"});
+        assert_eq!(PackageManager::detect(&pkg, &member), PackageManager::Yarn);
+
+        fs::remove_file(temp.path().join("yarn.lock")).unwrap();
+        fs::write(
+            temp.path().join("pnpm-workspace.yaml"),
+            "packages: ['packages/*']\n",
+        )
+        .unwrap();
+        assert_eq!(PackageManager::detect(&pkg, &member), PackageManager::Pnpm);
+    }
+
+    #[test]
+    fn pnpm_hooks_follow_version_and_npmrc() {
+        let scripts = r#""scripts": {"prebuild": "rm -rf dist", "build": "tsc"}"#;
+        let runs_hooks = |version: &str, dir: &Path| {
+            let content = format!(r#"{{"packageManager": "pnpm@{version}", {scripts}}}"#);
+            let kf = parse(&content, dir).unwrap();
+            assert_eq!(kf.package_manager, Some(PackageManager::Pnpm));
+            kf.tasks["prebuild"].private
+        };
+        let temp = TempDir::new().unwrap();
+        assert!(runs_hooks("9.0.0", temp.path()));
+        assert!(!runs_hooks("7.33.0", temp.path()));
+
+        fs::write(temp.path().join(".npmrc"), "enable-pre-post-scripts=true\n").unwrap();
+        assert!(runs_hooks("7.33.0", temp.path()));
+        fs::write(
+            temp.path().join(".npmrc"),
+            "enable-pre-post-scripts=false\n",
+        )
+        .unwrap();
+        assert!(!runs_hooks("9.0.0", temp.path()));
+    }
+
+    #[test]
+    fn set_npm_environment() {
+        let temp = TempDir::new().unwrap();
+        fs::write(
+            temp.path().join(".npmrc"),
+            "# settings\nregistry=https://registry.example.com/\nsave-exact = true\n//registry.example.com/:_authToken=secret\n",
+        )
+        .unwrap();
+        let content = r#"{"name": "app", "version": "1.2.0", "scripts": {"show": "echo {{x}} $npm_package_version"}}"#;
+        let kf = parse(content, temp.path()).unwrap();
+        assert_eq!(
+            kf.env,
+            IndexMap::from([
+                ("npm_command".to_string(), "run-script".to_string()),
+                ("npm_package_name".to_string(), "app".to_string()),
+                ("npm_package_version".to_string(), "1.2.0".to_string()),
+                (
+                    "npm_config_registry".to_string(),
+                    "https://registry.example.com/".to_string()
+                ),
+                ("npm_config_save_exact".to_string(), "true".to_string()),
+            ])
+        );
+        let show = &kf.tasks["show"];
+        assert_eq!(show.env["npm_lifecycle_event"], "show");
+        assert_eq!(
+            show.env["npm_lifecycle_script"],
+            "echo \\{{x}} $npm_package_version"
+        );
+    }
 }
@@ -0,0 +1,134 @@
+use super::kylefile::Run;
+use std::collections::BTreeMap;
+use std::env::consts::{ARCH, FAMILY, OS};
+
+/// Key in a per-platform `run` table used when no platform matches
+const DEFAULT_KEY: &str = "default";
+
+/// Check a platform spec against the running system. Accepts an OS
+/// (`linux`, `macos`/`darwin`, `windows`), a family (`unix`), an arch
+/// (`x86_64`/`amd64`, `aarch64`/`arm64`) or an `os/arch` pair.
+pub fn matches(spec: &str) -> bool {
+    specificity(spec).is_some()
+}
+
+/// Pick the `run` for this platform from a per-platform table. The most
+/// specific match wins (`os/arch`, then `os`, then arch, then family),
+/// falling back to `default`.
+pub fn select(runs: &BTreeMap<String, Run>) -> Option<&Run> {
+    runs.iter()
+        .filter(|(spec, _)| spec.as_str() != DEFAULT_KEY)
+        .filter_map(|(spec, run)| Some((specificity(spec)?, run)))
+        .max_by_key(|(rank, _)| *rank)
+        .map(|(_, run)| run)
+        .or_else(|| runs.get(DEFAULT_KEY))
+}
+
+/// How closely a matching spec describes the running system, higher being
+/// closer; `None` if it doesn't match
+fn specificity(spec: &str) -> Option<u8> {
+    let spec = spec.trim().to_ascii_lowercase();
+    let rank = match spec.split_once('/') {
+        Some((os, arch)) if matches_arch(arch) && os_name(os) == OS => 5,
+        Some((os, arch)) if matches_arch(arch) && os == FAMILY => 3,
+        Some(_) => return None,
+        None if os_name(&spec) == OS => 4,
+        None if matches_arch(&spec) => 2,
+        None if spec == FAMILY => 1,
+        None => return None,
+    };
+    Some(rank)
+}
+
+/// The running platform as `os/arch`, for error messages
+pub fn current() -> String {
+    format!("{OS}/{ARCH}")
+}
+
+fn os_name(os: &str) -> &str {
+    match os {
+        "darwin" | "osx" => "macos",
+        other => other,
+    }
+}
+
+fn matches_arch(arch: &str) -> bool {
+    // Go-style names, as used by Taskfile
+    let arch = match arch {
+        "amd64" => "x86_64",
+        "arm64" => "aarch64",
+        "386" => "x86",
+        other => other,
+    };
+    arch == ARCH
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_current_os_and_arch() {
+        assert!(matches(OS));
+        assert!(matches(FAMILY));
+        assert!(matches(ARCH));
+        assert!(matches(&format!("{OS}/{ARCH}")));
+        assert!(matches(&OS.to_ascii_uppercase()));
+    }
+
+    #[test]
+    fn rejects_other_platforms() {
+        assert!(!matches("plan9"));
+        assert!(!matches(&format!("{OS}/sparc")));
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn darwin_is_macos() {
+        assert!(matches("darwin"));
+    }
+
+    #[test]
+    fn select_prefers_platform_over_default() {
+        let runs = BTreeMap::from([
+            ("default".to_string(), Run::from("echo fallback")),
+            (OS.to_string(), Run::from("echo native")),
+            ("plan9".to_string(), Run::from("echo plan9")),
+        ]);
+        assert_eq!(select(&runs), Some(&Run::from("echo native")));
+    }
+
+    #[test]
+    fn select_prefers_the_most_specific_match() {
+        let pair = format!("{OS}/{ARCH}");
+        let mut runs = BTreeMap::from([
+            ("default".to_string(), Run::from("echo fallback")),
+            (FAMILY.to_string(), Run::from("echo family")),
+            (ARCH.to_string(), Run::from("echo arch")),
+            (OS.to_string(), Run::from("echo os")),
+            (pair.clone(), Run::from("echo pair")),
+            (format!("{OS}/sparc"), Run::from("echo sparc")),
+        ]);
+        assert_eq!(select(&runs), Some(&Run::from("echo pair")));
+        runs.remove(&pair);
+        assert_eq!(select(&runs), Some(&Run::from("echo os")));
+        runs.remove(OS);
+        assert_eq!(select(&runs), Some(&Run::from("echo arch")));
+        runs.remove(ARCH);
+        assert_eq!(select(&runs), Some(&Run::from("echo family")));
+        runs.remove(FAMILY);
+        assert_eq!(select(&runs), Some(&Run::from("echo fallback")));
+    }
+
+    #[test]
+    fn select_falls_back_to_default() {
+        let runs = BTreeMap::from([
+            ("default".to_string(), Run::from("echo fallback")),
+            ("plan9".to_string(), Run::from("echo plan9")),
+        ]);
+        assert_eq!(select(&runs), Some(&Run::from("echo fallback")));
+
+        let runs = BTreeMap::from([("plan9".to_string(), Run::from("echo plan9"))]);
+        assert_eq!(select(&runs), None);
+    }
+}
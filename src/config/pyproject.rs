@@ -1,10 +1,10 @@
 use super::Error;
-use super::kylefile::{Kylefile, Task};
-use std::collections::HashMap;
+use super::kylefile::{Kylefile, Run, Task};
+use indexmap::IndexMap;
 
 pub fn parse(content: &str) -> Result<Kylefile, Error> {
     let doc: toml::Value = toml::from_str(content)?;
-    let mut tasks = HashMap::new();
+    let mut tasks = IndexMap::new();
 
     // Try PDM scripts: [tool.pdm.scripts]
     if let Some(scripts) = doc
@@ -104,9 +104,9 @@ pub fn parse(content: &str) -> Result<Kylefile, Error> {
     })
 }
 
-fn extract_script_cmd(val: &toml::Value) -> Option<String> {
+fn extract_script_cmd(val: &toml::Value) -> Option<Run> {
     match val {
-        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::String(s) => Some(Run::from(s.as_str())),
         toml::Value::Array(arr) => {
             let cmds: Vec<String> = arr
                 .iter()
@@ -115,17 +115,17 @@ fn extract_script_cmd(val: &toml::Value) -> Option<String> {
             if cmds.is_empty() {
                 None
             } else {
-                Some(cmds.join(" && "))
+                Some(Run::from_commands(cmds))
             }
         }
         // PDM supports {cmd = "..."} format
-        toml::Value::Table(t) => t.get("cmd").and_then(|c| c.as_str()).map(String::from),
+        toml::Value::Table(t) => t.get("cmd").and_then(|c| c.as_str()).map(Run::from),
         _ => None,
     }
 }
 
-fn standard_python_tasks() -> HashMap<String, Task> {
-    let mut tasks = HashMap::new();
+fn standard_python_tasks() -> IndexMap<String, Task> {
+    let mut tasks = IndexMap::new();
     let standard = [
         ("test", "pytest", "Run tests"),
         ("lint", "ruff check .", "Run linter"),
@@ -138,7 +138,7 @@ fn standard_python_tasks() -> HashMap<String, Task> {
             name.to_string(),
             Task {
                 desc: desc.to_string(),
-                run: cmd.to_string(),
+                run: cmd.into(),
                 ..Default::default()
             },
         );
@@ -183,6 +183,16 @@ mod tests {
         assert!(kf.tasks.contains_key("lint"));
     }
 
+    #[test]
+    fn parse_array_scripts() {
+        let content = "[tool.rye.scripts]\ncheck = [\"ruff check .\", \"mypy .\"]";
+        let kf = parse(content).unwrap();
+        assert_eq!(
+            kf.tasks["check"].run.commands(),
+            vec!["ruff check .", "mypy ."]
+        );
+    }
+
     #[test]
     fn parse_pdm_cmd_format() {
         let content = "[tool.pdm.scripts]\nserve = {cmd = \"python -m http.server\"}";
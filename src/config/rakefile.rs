@@ -1,102 +1,625 @@
 use super::Error;
-use super::kylefile::{Kylefile, Task};
+use super::kylefile::{Kylefile, Run, Step, Task};
 use regex::Regex;
 use std::collections::HashMap;
 use std::sync::LazyLock;
 
-// task :name or task :name => [:dep1, :dep2]
+// namespace :db do / namespace "db" do
+static NAMESPACE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^namespace\s*\(?\s*(?::([\w?!]+)|["']([^"']+)["'])\s*\)?\s+do(?:\s*\|[^|]*\|)?$"#)
+        .unwrap()
+});
+
+// task, multitask, file or directory, followed by its arguments
 static TASK_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"^task\s+:(\w+)(?:\s+=>\s+\[([^\]]*)\])?"#).unwrap());
+    LazyLock::new(|| Regex::new(r"^(task|multitask|file|directory)\b\s*([^=.\s].*)$").unwrap());
 
 // desc "description"
-static DESC_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"^desc\s+["'](.+?)["']"#).unwrap());
+static DESC_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^desc\b\s*\(?\s*(.+)$").unwrap());
+
+// VERSION = "1.2.0"
+static CONSTANT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^([A-Z][A-Za-z0-9_]*)\s*=\s*(["'].*)$"#).unwrap());
+
+// `#{...}` interpolation in a double-quoted string
+static INTERPOLATION_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"#\{\s*([^{}]*?)\s*\}").unwrap());
+
+// A trailing `do` or `do |t, args|` opening a block
+static DO_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+do(?:\s*\|[^|]*\|)?$").unwrap());
+
+// A trailing `{` or `{ |x|` opening a block over several lines
+static OPEN_BRACE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{(?:\s*\|[^|]*\|)?$").unwrap());
+
+// A one-line `{ ... }` or `{ |t| ... }` block
+static BRACES_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(.*?)\s*\{\s*(?:\|[^|]*\|)?\s*(.*?)\s*\}$").unwrap());
 
-// sh "command" or system("command") or `command`
-static SH_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"^\s+sh\s+["'](.+?)["']"#).unwrap());
+// RSpec::Core::RakeTask.new(:spec)
+static LIBRARY_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^([A-Z][\w:]*)\.new\b\s*\(?\s*(?::([\w?!]+)|["']([^"']+)["'])?"#).unwrap()
+});
 
-static SYSTEM_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"^\s+system\(["'](.+?)["']\)"#).unwrap());
+// Rake::Task["db:seed"].invoke
+static INVOKE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"^Rake::Task\[\s*(?::([\w?!]+)|["']([^"']+)["'])\s*\]\.(?:invoke|execute)(?:\(\))?$"#,
+    )
+    .unwrap()
+});
 
-static BACKTICK_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s+`(.+?)`").unwrap());
+/// Task library classes that define a task, with the name it gets by default
+const TASK_LIBRARIES: &[(&str, &str)] = &[
+    ("RSpec::Core::RakeTask", "spec"),
+    ("Rake::TestTask", "test"),
+    ("RuboCop::RakeTask", "rubocop"),
+    ("YARD::Rake::YardocTask", "yard"),
+    ("Cucumber::Rake::Task", "cucumber"),
+];
+
+/// Keywords that open a block closed by `end`
+const BLOCK_KEYWORDS: &[&str] = &[
+    "if", "unless", "while", "until", "case", "begin", "def", "class", "module", "for",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Task,
+    Multitask,
+    File,
+    Directory,
+}
+
+/// What a `task` line declares
+struct Header {
+    name: String,
+    args: Vec<String>,
+    deps: Vec<String>,
+}
+
+/// A task as read, before dependencies are resolved against its namespace
+struct Declared {
+    scope: Vec<String>,
+    kind: Kind,
+    deps: Vec<String>,
+    /// Run as `rake <task>`, which runs the dependencies itself
+    via_rake: bool,
+}
 
 pub fn parse(content: &str) -> Result<Kylefile, Error> {
-    let mut tasks: HashMap<String, Task> = HashMap::new();
-    let mut pending_desc: Option<String> = None;
+    let mut kf = Kylefile::default();
+    let mut declared: Vec<(String, Declared)> = Vec::new();
+    let mut scope: Vec<String> = Vec::new();
+    // One entry per open block: the namespace it opened, if any
+    let mut blocks: Vec<Option<String>> = Vec::new();
+    let mut pending_desc: Option<Desc> = None;
+    // String constants, for `#{NAME}` in descriptions
+    let mut constants: HashMap<String, String> = HashMap::new();
 
     let lines: Vec<&str> = content.lines().collect();
     let mut i = 0;
 
     while i < lines.len() {
-        let line = lines[i];
+        let no = i + 1;
+        let line = strip_comment(lines[i]).trim();
+        i += 1;
 
+        if line.is_empty() {
+            pending_desc = None;
+            continue;
+        }
+        if closes_block(line) {
+            if let Some(Some(_)) = blocks.pop() {
+                scope.pop();
+            }
+            continue;
+        }
         if let Some(caps) = DESC_RE.captures(line) {
-            pending_desc = Some(caps[1].to_string());
-            i += 1;
+            pending_desc = quoted(&caps[1], &constants);
+            continue;
+        }
+        if let Some(caps) = CONSTANT_RE.captures(line)
+            && let Some(desc) = quoted(&caps[2], &constants)
+            && desc.unexpanded.is_empty()
+        {
+            constants.insert(caps[1].to_string(), desc.text);
+            continue;
+        }
+        if let Some(caps) = NAMESPACE_RE.captures(line) {
+            let name = caps.get(1).or(caps.get(2)).map_or("", |m| m.as_str());
+            scope.push(name.to_string());
+            blocks.push(Some(name.to_string()));
             continue;
         }
 
+        let desc = pending_desc.take();
         if let Some(caps) = TASK_RE.captures(line) {
-            let name = caps[1].to_string();
-            let deps: Vec<String> = caps
-                .get(2)
-                .map(|m| {
-                    m.as_str()
-                        .split(',')
-                        .filter_map(|d| {
-                            let d = d.trim().trim_start_matches(':');
-                            if d.is_empty() {
-                                None
-                            } else {
-                                Some(d.to_string())
-                            }
-                        })
-                        .collect()
-                })
-                .unwrap_or_default();
-
-            let mut commands: Vec<String> = Vec::new();
-            i += 1;
-            while i < lines.len() {
-                let cmd_line = lines[i];
-                if cmd_line.trim() == "end" {
-                    i += 1;
-                    break;
+            let kind = match &caps[1] {
+                "multitask" => Kind::Multitask,
+                "file" => Kind::File,
+                "directory" => Kind::Directory,
+                _ => Kind::Task,
+            };
+            let spec = &caps[2];
+            let (spec, body) = if let Some(m) = DO_RE.find(spec) {
+                let (body, end) = block_body(&lines, i);
+                i = end;
+                (&spec[..m.start()], Some(body))
+            } else if let Some(braces) = BRACES_RE.captures(spec) {
+                let inner = braces.get(2).map_or("", |m| m.as_str());
+                (braces.get(1).map_or("", |m| m.as_str()), Some(vec![inner]))
+            } else {
+                (spec, None)
+            };
+            let Some(header) = parse_header(spec) else {
+                kf.warnings
+                    .push(format!("Rakefile:{no}: not understood: {line}"));
+                continue;
+            };
+            let name = qualified(&scope, &header.name);
+            let index = match declared.iter().position(|(n, _)| *n == name) {
+                Some(index) => index,
+                None => {
+                    declared.push((
+                        name.clone(),
+                        Declared {
+                            scope: scope.clone(),
+                            kind,
+                            deps: Vec::new(),
+                            via_rake: false,
+                        },
+                    ));
+                    declared.len() - 1
                 }
-                if cmd_line.trim().is_empty() || cmd_line.starts_with("task") {
-                    break;
+            };
+            let declared = &mut declared[index].1;
+            declared.deps.extend(header.deps);
+
+            let task = kf.tasks.entry(name.clone()).or_default();
+            if let Some(desc) = desc {
+                desc.apply(task);
+            }
+            if !header.args.is_empty() {
+                task.notes.push(format!(
+                    "task arguments not imported: {}",
+                    header.args.join(", ")
+                ));
+            }
+            if declared.via_rake {
+                continue;
+            }
+            let mut steps = task.run.steps();
+            match body {
+                Some(body) => match body_steps(&body) {
+                    Some(more) => steps.extend(more),
+                    None => {
+                        // Ruby kyle can't follow: let rake run the whole task
+                        steps = vec![Step::Command(format!("rake {name}"))];
+                        task.notes
+                            .push("task body is Ruby; it runs through rake".into());
+                        declared.via_rake = true;
+                    }
+                },
+                None if kind == Kind::Directory => {
+                    steps.push(Step::Command(format!("mkdir -p {}", header.name)));
                 }
+                None => {}
+            }
+            task.run = Run::from_steps(steps);
+            continue;
+        }
 
-                if let Some(caps) = SH_RE.captures(cmd_line) {
-                    commands.push(caps[1].to_string());
-                } else if let Some(caps) = SYSTEM_RE.captures(cmd_line) {
-                    commands.push(caps[1].to_string());
-                } else if let Some(caps) = BACKTICK_RE.captures(cmd_line) {
-                    commands.push(caps[1].to_string());
+        if let Some(caps) = LIBRARY_RE.captures(line) {
+            let class = &caps[1];
+            let named = caps.get(2).or(caps.get(3)).map(|m| m.as_str());
+            let default = TASK_LIBRARIES
+                .iter()
+                .find(|(library, _)| *library == class)
+                .map(|(_, name)| *name);
+            let named = named.filter(|_| class.ends_with("Task") || default.is_some());
+            if let Some(name) = named.or(default) {
+                let name = qualified(&scope, name);
+                let task = kf.tasks.entry(name.clone()).or_default();
+                if let Some(desc) = desc {
+                    desc.apply(task);
                 }
-                i += 1;
+                task.run = format!("rake {name}").into();
+                task.notes
+                    .push(format!("defined by {class}; it runs through rake"));
             }
+        } else if line.starts_with("rule ") || line.starts_with("rule(") {
+            kf.warnings
+                .push(format!("Rakefile:{no}: rules not imported: {line}"));
+        }
+        if opens_block(line) {
+            blocks.push(None);
+        }
+    }
 
-            tasks.insert(
-                name,
-                Task {
-                    desc: pending_desc.take().unwrap_or_default(),
-                    run: commands.join(" && "),
-                    deps,
-                },
+    resolve_deps(&mut kf, declared);
+    Ok(kf)
+}
+
+/// Point each task's prerequisites at the tasks rake would find for them
+fn resolve_deps(kf: &mut Kylefile, declared: Vec<(String, Declared)>) {
+    for (name, declared) in declared {
+        let mut deps = Vec::new();
+        let mut files = Vec::new();
+        for dep in &declared.deps {
+            match lookup(kf, &declared.scope, dep) {
+                Some(dep) => deps.push(dep),
+                None if matches!(declared.kind, Kind::File | Kind::Directory) => {
+                    files.push(dep.as_str())
+                }
+                None => deps.push(dep.trim_start_matches("rake:").to_string()),
+            }
+        }
+        let task = &mut kf.tasks[&name];
+        if declared.kind == Kind::Multitask && deps.len() > 1 {
+            task.notes
+                .push("multitask dependencies run one at a time".into());
+        }
+        if matches!(declared.kind, Kind::File | Kind::Directory) {
+            task.private |= task.desc.is_empty();
+            task.notes.push(
+                "file task always runs; rake only runs it when the file is out of date".into(),
             );
-            continue;
+            if !files.is_empty() {
+                task.notes.push(format!(
+                    "file prerequisites not imported: {}",
+                    files.join(", ")
+                ));
+            }
         }
-
-        if line.trim().is_empty() {
-            pending_desc = None;
+        if !declared.via_rake {
+            task.deps = deps;
         }
+    }
+}
+
+/// The task a prerequisite names from within `scope`: the innermost
+/// namespace that has it wins, `^` climbs out one namespace and `rake:`
+/// starts at the top
+fn lookup(kf: &Kylefile, scope: &[String], dep: &str) -> Option<String> {
+    if let Some(top) = dep.strip_prefix("rake:") {
+        return kf.tasks.contains_key(top).then(|| top.to_string());
+    }
+    let up = dep.chars().take_while(|c| *c == '^').count();
+    let dep = &dep[up..];
+    let scope = &scope[..scope.len().saturating_sub(up)];
+    (0..=scope.len())
+        .rev()
+        .map(|depth| qualified(&scope[..depth], dep))
+        .find(|name| kf.tasks.contains_key(name))
+}
+
+fn qualified(scope: &[String], name: &str) -> String {
+    scope
+        .iter()
+        .map(String::as_str)
+        .chain(std::iter::once(name))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Lines of the block starting at `start`, up to its `end`, and the line after it
+fn block_body<'a>(lines: &[&'a str], start: usize) -> (Vec<&'a str>, usize) {
+    let mut body = Vec::new();
+    let mut depth = 1;
+    let mut i = start;
+    while i < lines.len() {
+        let line = strip_comment(lines[i]).trim();
         i += 1;
+        if closes_block(line) {
+            depth -= 1;
+            if depth == 0 {
+                break;
+            }
+        } else if opens_block(line) {
+            depth += 1;
+        }
+        body.push(line);
+    }
+    (body, i)
+}
+
+/// Whether `line` opens a block that a later `end` or `}` closes
+fn opens_block(line: &str) -> bool {
+    let first = line.split(|c: char| !c.is_alphanumeric()).next();
+    DO_RE.is_match(line)
+        || OPEN_BRACE_RE.is_match(line)
+        || line == "do"
+        || first.is_some_and(|w| BLOCK_KEYWORDS.contains(&w))
+}
+
+fn closes_block(line: &str) -> bool {
+    line.starts_with('}')
+        || line
+            .strip_prefix("end")
+            .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+}
+
+/// Steps for a task body, or `None` when it does more than run commands
+fn body_steps(body: &[&str]) -> Option<Vec<Step>> {
+    body.iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| statement(line))
+        .collect()
+}
+
+/// Map one statement: `sh`, `system`, backticks, `ruby`, `puts` or
+/// `Rake::Task[...].invoke`
+fn statement(line: &str) -> Option<Step> {
+    if let Some(caps) = INVOKE_RE.captures(line) {
+        let task = caps.get(1).or(caps.get(2))?.as_str();
+        return Some(Step::Task {
+            task: task.to_string(),
+            args: Vec::new(),
+        });
+    }
+    if let Some(cmd) = line.strip_prefix('`').and_then(|l| l.strip_suffix('`')) {
+        return (!cmd.contains("#{")).then(|| Step::Command(cmd.to_string()));
+    }
+    let (method, rest) = line.split_at(line.find([' ', '('])?);
+    let args = call_args(rest)?;
+    match method {
+        "sh" | "system" if !args.is_empty() => Some(Step::Command(join_words(&args))),
+        "ruby" if !args.is_empty() => Some(Step::Command(format!("ruby {}", join_words(&args)))),
+        "puts" if args.len() == 1 => Some(Step::Command(format!("echo {}", quote(&args[0])))),
+        _ => None,
+    }
+}
+
+/// String arguments of a method call, with or without parentheses; `None`
+/// if any of them isn't a plain string
+fn call_args(text: &str) -> Option<Vec<String>> {
+    let text = text.trim();
+    let text = match text.strip_prefix('(') {
+        Some(inner) => inner.strip_suffix(')')?,
+        None => text,
+    };
+    let mut args = Vec::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let (arg, after) = string(rest)?;
+        if arg.contains("#{") {
+            return None;
+        }
+        args.push(arg);
+        rest = after.trim_start();
+        match rest.strip_prefix(',') {
+            Some(after) => rest = after.trim_start(),
+            None if rest.is_empty() => {}
+            None => return None,
+        }
+    }
+    Some(args)
+}
+
+/// A single string is a shell command; several are one argument each
+fn join_words(args: &[String]) -> String {
+    if let [cmd] = args {
+        return cmd.clone();
+    }
+    args.iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                quote(arg)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Name, arguments and prerequisites from what follows `task`: `:name`,
+/// `"name"`, `:name => deps`, `name: deps`, `:name, [:arg] => deps`
+fn parse_header(spec: &str) -> Option<Header> {
+    let spec = spec.trim();
+    let spec = match spec.strip_prefix('(') {
+        Some(inner) => inner.strip_suffix(')')?.trim(),
+        None => spec,
+    };
+    let (name, rest, hash_key) = if let Some(rest) = spec.strip_prefix(':') {
+        let (name, rest) = symbol(rest)?;
+        (name, rest, false)
+    } else if spec.starts_with(['"', '\'']) {
+        let (name, rest) = string(spec)?;
+        match rest.strip_prefix(':').filter(|r| !r.starts_with(':')) {
+            Some(rest) => (name, rest, true),
+            None => (name, rest, false),
+        }
+    } else {
+        let (name, rest) = symbol(spec)?;
+        let rest = rest.strip_prefix(':').filter(|r| !r.starts_with(':'))?;
+        (name, rest, true)
+    };
+
+    let mut header = Header {
+        name,
+        args: Vec::new(),
+        deps: Vec::new(),
+    };
+    let mut rest = rest.trim_start();
+    if hash_key {
+        header.deps = list(rest)?.0;
+        return Some(header);
+    }
+    if let Some(after) = rest.strip_prefix(',') {
+        let (args, after) = list(after.trim_start())?;
+        header.args = args;
+        rest = after.trim_start();
+    }
+    if let Some(after) = rest.strip_prefix("=>") {
+        let (deps, after) = list(after.trim_start())?;
+        header.deps = deps;
+        rest = after.trim_start();
+    }
+    rest.is_empty().then_some(header)
+}
+
+/// A symbol, string, `[...]` array of them or `%w[...]` word list
+fn list(text: &str) -> Option<(Vec<String>, &str)> {
+    if let Some(rest) = text.strip_prefix("%w").or_else(|| text.strip_prefix("%i")) {
+        let close = match rest.chars().next()? {
+            '[' => ']',
+            '(' => ')',
+            '{' => '}',
+            '<' => '>',
+            _ => return None,
+        };
+        let end = rest.find(close)?;
+        let words = rest[1..end].split_whitespace().map(String::from).collect();
+        return Some((words, &rest[end + 1..]));
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Some((items, after));
+            }
+            let (item, after) = value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest);
+        }
+    }
+    let (item, rest) = value(text)?;
+    Some((vec![item], rest))
+}
+
+/// A `:symbol` or string
+fn value(text: &str) -> Option<(String, &str)> {
+    match text.strip_prefix(':') {
+        Some(rest) if rest.starts_with(['"', '\'']) => string(rest),
+        Some(rest) => symbol(rest),
+        None => string(text),
+    }
+}
+
+/// A bare symbol name, e.g. `build` or `ready?`
+fn symbol(text: &str) -> Option<(String, &str)> {
+    let end = text
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(text.len());
+    if end == 0 {
+        return None;
+    }
+    let end = if text[end..].starts_with(['?', '!']) {
+        end + 1
+    } else {
+        end
+    };
+    Some((text[..end].to_string(), &text[end..]))
+}
+
+/// A `desc` string, with the interpolation kyle couldn't expand
+struct Desc {
+    text: String,
+    unexpanded: Vec<String>,
+}
+
+impl Desc {
+    fn apply(self, task: &mut Task) {
+        task.desc = self.text;
+        if !self.unexpanded.is_empty() {
+            task.notes.push(format!(
+                "description interpolation not expanded: {}",
+                self.unexpanded.join(" ")
+            ));
+        }
+    }
+}
+
+/// A string literal with `#{NAME}` filled in from the constants seen so far
+fn quoted(text: &str, constants: &HashMap<String, String>) -> Option<Desc> {
+    let (raw, _) = string(text)?;
+    if !text.starts_with('"') {
+        return Some(Desc {
+            text: raw,
+            unexpanded: Vec::new(),
+        });
+    }
+    let mut unexpanded = Vec::new();
+    let text = INTERPOLATION_RE
+        .replace_all(&raw, |caps: &regex::Captures| {
+            match constants.get(&caps[1]) {
+                Some(value) => value.clone(),
+                None => {
+                    unexpanded.push(caps[0].to_string());
+                    caps[0].to_string()
+                }
+            }
+        })
+        .into_owned();
+    Some(Desc { text, unexpanded })
+}
+
+/// A quoted string and what follows it. `#{...}` interpolation in double
+/// quotes is kept as written, since kyle can't evaluate Ruby.
+fn string(text: &str) -> Option<(String, &str)> {
+    let quote = text.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let mut out = String::new();
+    let mut chars = text.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                let (_, next) = chars.next()?;
+                match (quote, next) {
+                    ('"', 'n') => out.push('\n'),
+                    ('"', 't') => out.push('\t'),
+                    (_, c) if c == quote || c == '\\' => out.push(c),
+                    ('\'', c) => {
+                        out.push('\\');
+                        out.push(c);
+                    }
+                    (_, c) => out.push(c),
+                }
+            }
+            '#' if quote == '"' && chars.peek().is_some_and(|(_, c)| *c == '{') => {
+                out.push('#');
+                let mut depth = 0;
+                for (_, c) in chars.by_ref() {
+                    out.push(c);
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            c if c == quote => return Some((out, &text[i + 1..])),
+            c => out.push(c),
+        }
     }
+    None
+}
 
-    Ok(Kylefile {
-        tasks,
-        ..Default::default()
-    })
+/// Drop a trailing `# comment`, leaving `#` inside strings alone
+fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (_, _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'' | '`') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
 }
 
 #[cfg(test)]
@@ -127,6 +650,28 @@ end
         assert_eq!(kf.tasks["test"].desc, "Run tests");
     }
 
+    #[test]
+    fn desc_interpolates_constants() {
+        let content = r##"
+APP = "shop"
+VERSION = "#{APP}-1.2"
+
+desc "Release #{VERSION} of #{ APP }"
+task :release do
+  sh "echo release"
+end
+
+desc 'Literal #{VERSION}'
+task :literal do
+  sh "echo literal"
+end
+"##;
+        let kf = parse(content).unwrap();
+        assert_eq!(kf.tasks["release"].desc, "Release shop-1.2 of shop");
+        assert!(kf.tasks["release"].notes.is_empty());
+        assert_eq!(kf.tasks["literal"].desc, "Literal #{VERSION}");
+    }
+
     #[test]
     fn parse_task_with_deps() {
         let content = r#"
@@ -159,8 +704,156 @@ end
 "#;
         let kf = parse(content).unwrap();
         assert_eq!(
-            kf.tasks["deploy"].run,
-            "make build && rsync -a dist/ server:/app/"
+            kf.tasks["deploy"].run.commands(),
+            vec!["make build", "rsync -a dist/ server:/app/"]
+        );
+    }
+    #[test]
+    fn parse_hash_syntax_and_namespaces() {
+        let content = r#"
+task default: :test
+
+desc "Run the specs"
+task test: %w[db:prepare lint]
+
+task :lint do
+  sh "rubocop", "--format", "progress"
+end
+
+namespace :db do
+  desc 'Create and migrate'
+  task prepare: [:migrate, "rake:lint"]
+
+  task :migrate => :environment do
+    ruby "db/migrate.rb"
+  end
+
+  namespace "seed" do
+    task :dev => "^migrate"
+  end
+end
+
+task :environment
+"#;
+        let kf = parse(content).unwrap();
+        assert_eq!(
+            kf.tasks.keys().collect::<Vec<_>>(),
+            vec![
+                "default",
+                "test",
+                "lint",
+                "db:prepare",
+                "db:migrate",
+                "db:seed:dev",
+                "environment",
+            ]
+        );
+        assert_eq!(kf.tasks["default"].deps, vec!["test"]);
+        assert_eq!(kf.tasks["test"].desc, "Run the specs");
+        assert_eq!(kf.tasks["test"].deps, vec!["db:prepare", "lint"]);
+        assert_eq!(kf.tasks["lint"].run, "rubocop --format progress");
+        assert_eq!(kf.tasks["db:prepare"].desc, "Create and migrate");
+        assert_eq!(kf.tasks["db:prepare"].deps, vec!["db:migrate", "lint"]);
+        assert_eq!(kf.tasks["db:migrate"].run, "ruby db/migrate.rb");
+        assert_eq!(kf.tasks["db:migrate"].deps, vec!["environment"]);
+        assert_eq!(kf.tasks["db:seed:dev"].deps, vec!["db:migrate"]);
+    }
+
+    #[test]
+    fn ruby_bodies_run_through_rake() {
+        let content = r#"
+desc "Deploy to #{ENV.fetch("STAGE", "dev")}"
+task :deploy, [:version] => :build do |t, args|
+  version = args[:version] || "latest"
+  sh "cap deploy VERSION=#{version}"
+end
+
+task :build do
+  %w[a b].each do |dir|
+    sh "make -C #{dir}"
+  end
+end
+"#;
+        let kf = parse(content).unwrap();
+        let deploy = &kf.tasks["deploy"];
+        assert_eq!(deploy.desc, r#"Deploy to #{ENV.fetch("STAGE", "dev")}"#);
+        assert_eq!(deploy.run, "rake deploy");
+        assert!(deploy.deps.is_empty());
+        assert_eq!(
+            deploy.notes,
+            vec![
+                r#"description interpolation not expanded: #{ENV.fetch("STAGE", "dev")}"#,
+                "task arguments not imported: version",
+                "task body is Ruby; it runs through rake",
+            ]
+        );
+        assert_eq!(kf.tasks["build"].run, "rake build");
+    }
+
+    #[test]
+    fn parse_multitask_file_and_library_tasks() {
+        let content = r#"
+require "rspec/core/rake_task"
+
+RSpec::Core::RakeTask.new(:spec)
+Rake::TestTask.new do |t|
+  t.pattern = "test/**/*_test.rb"
+end
+
+multitask :assets => [:css, :js]
+task(:css) { sh "sass in.scss out.css" }
+task :js
+
+directory "pkg"
+file "pkg/app.tar" => ["pkg", "app.rb"] do
+  sh "tar -cf pkg/app.tar app.rb"
+end
+
+rule ".o" => ".c" do |t|
+  sh "cc -c #{t.source}"
+end
+"#;
+        let kf = parse(content).unwrap();
+        assert_eq!(kf.tasks["spec"].run, "rake spec");
+        assert_eq!(kf.tasks["test"].run, "rake test");
+        assert_eq!(kf.tasks["assets"].deps, vec!["css", "js"]);
+        assert_eq!(
+            kf.tasks["assets"].notes,
+            vec!["multitask dependencies run one at a time"]
+        );
+        assert_eq!(kf.tasks["css"].run, "sass in.scss out.css");
+        assert_eq!(kf.tasks["pkg"].run, "mkdir -p pkg");
+        let tar = &kf.tasks["pkg/app.tar"];
+        assert!(tar.private);
+        assert_eq!(tar.deps, vec!["pkg"]);
+        assert_eq!(tar.run, "tar -cf pkg/app.tar app.rb");
+        assert_eq!(tar.notes[1], "file prerequisites not imported: app.rb");
+        assert_eq!(
+            kf.warnings,
+            vec![r#"Rakefile:18: rules not imported: rule ".o" => ".c" do |t|"#]
+        );
+    }
+
+    #[test]
+    fn parse_invoke_puts_and_comments() {
+        let content = r#"
+task :release do # cut a release
+  puts "Releasing"
+  Rake::Task["db:migrate"].invoke
+  `git push --tags`
+end
+"#;
+        let kf = parse(content).unwrap();
+        assert_eq!(
+            kf.tasks["release"].run,
+            Run::Steps(vec![
+                Step::Command("echo 'Releasing'".into()),
+                Step::Task {
+                    task: "db:migrate".into(),
+                    args: vec![],
+                },
+                Step::Command("git push --tags".into()),
+            ])
         );
     }
 }
@@ -0,0 +1,11 @@
+use super::kylefile::Kylefile;
+
+/// Where the published schema lives, referenced from generated Kylefiles
+pub const URL: &str = "https://kylefile.dev/schema.json";
+
+/// JSON Schema for Kylefiles, derived from the config types
+pub fn generate() -> String {
+    let mut schema = schemars::schema_for!(Kylefile);
+    schema.insert("$id".into(), URL.into());
+    serde_json::to_string_pretty(&schema).expect("schema serializes to JSON")
+}
@@ -1,16 +1,16 @@
 use super::kylefile::{Kylefile, Task};
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
 fn make_task(cmd: &str, desc: &str) -> Task {
     Task {
         desc: desc.to_string(),
-        run: cmd.to_string(),
+        run: cmd.into(),
         ..Default::default()
     }
 }
 
 fn build_kylefile(name: &str, entries: &[(&str, &str, &str)]) -> Kylefile {
-    let mut tasks = HashMap::new();
+    let mut tasks = IndexMap::new();
     for (task_name, cmd, desc) in entries {
         tasks.insert(task_name.to_string(), make_task(cmd, desc));
     }
@@ -28,9 +28,9 @@ pub fn cargo() -> Kylefile {
             ("build", "cargo build", "Build the project"),
             ("test", "cargo test", "Run tests"),
             ("run", "cargo run", "Run the project"),
-            ("check", "cargo check", "Check for errors"),
+            ("typecheck", "cargo check", "Check for errors"),
             ("clippy", "cargo clippy", "Run linter"),
-            ("fmt", "cargo fmt", "Format code"),
+            ("format", "cargo fmt", "Format code"),
         ],
     )
 }
@@ -43,7 +43,7 @@ pub fn go_mod() -> Kylefile {
             ("test", "go test ./...", "Run tests"),
             ("run", "go run .", "Run the project"),
             ("vet", "go vet ./...", "Run vet"),
-            ("fmt", "gofmt -w .", "Format code"),
+            ("format", "gofmt -w .", "Format code"),
         ],
     )
 }
@@ -122,7 +122,7 @@ mod tests {
         assert!(kf.tasks.contains_key("test"));
         assert!(kf.tasks.contains_key("run"));
         assert!(kf.tasks.contains_key("clippy"));
-        assert!(kf.tasks.contains_key("fmt"));
+        assert!(kf.tasks.contains_key("format"));
         assert_eq!(kf.tasks["build"].run, "cargo build");
     }
 
@@ -144,6 +144,26 @@ mod tests {
         assert_eq!(kf.tasks["build"].run, "dotnet build");
     }
 
+    #[test]
+    fn no_task_is_a_reserved_command() {
+        for kf in [
+            cargo(),
+            go_mod(),
+            pubspec(),
+            dotnet(),
+            gradle(),
+            maven(),
+            cmake(),
+        ] {
+            for name in kf.tasks.keys() {
+                assert!(
+                    !crate::cli::RESERVED_COMMANDS.contains(&name.as_str()),
+                    "standard task '{name}' shadows a built-in command"
+                );
+            }
+        }
+    }
+
     #[test]
     fn all_generators_return_non_empty() {
         assert!(!cargo().tasks.is_empty());
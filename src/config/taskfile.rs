@@ -1,63 +1,688 @@
 use super::Error;
-use super::kylefile::{Kylefile, Task};
-use std::collections::HashMap;
-
-pub fn parse(content: &str) -> Result<Kylefile, Error> {
-    let tf: serde_json::Value = serde_yml::from_str(content)?;
-    let mut tasks = HashMap::new();
-
-    if let Some(task_map) = tf["tasks"].as_object() {
-        for (name, def) in task_map {
-            // Support both object format and simple string format
-            if let Some(cmd) = def.as_str() {
-                tasks.insert(
-                    name.clone(),
-                    Task {
-                        run: cmd.to_string(),
-                        ..Default::default()
-                    },
-                );
+use super::kylefile::{Confirm, Kylefile, Run, Step, TEMPLATE_RE, Task, Var};
+use super::platform;
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use serde_yml::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Names `task` looks for, in order; also used for included directories
+pub const TASKFILE_NAMES: &[&str] = &[
+    "Taskfile.yml",
+    "taskfile.yml",
+    "Taskfile.yaml",
+    "taskfile.yaml",
+    "Taskfile.dist.yml",
+    "taskfile.dist.yml",
+    "Taskfile.dist.yaml",
+    "taskfile.dist.yaml",
+];
+
+/// Any `{{ ... }}` action in a Go template
+static ACTION_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{.*?\}\}").unwrap());
+
+/// Go template actions that look like a variable to kyle
+const GO_KEYWORDS: &[&str] = &["else", "end", "break", "continue"];
+
+/// How deep `includes` may nest before kyle assumes a cycle
+const MAX_DEPTH: usize = 16;
+
+/// Top-level keys that only change how `task` prints or schedules
+const IGNORED_KEYS: &[&str] = &["version", "output", "silent", "run", "method", "interval"];
+
+/// Task keys that only change how `task` prints or schedules
+const IGNORED_TASK_KEYS: &[&str] = &[
+    "label",
+    "silent",
+    "interactive",
+    "prefix",
+    "run",
+    "watch",
+    "method",
+];
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Taskfile {
+    vars: IndexMap<String, VarDef>,
+    env: IndexMap<String, VarDef>,
+    includes: IndexMap<String, Include>,
+    tasks: IndexMap<String, TaskDef>,
+    #[serde(deserialize_with = "one_or_many")]
+    dotenv: Vec<String>,
+    #[serde(flatten)]
+    other: IndexMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VarDef {
+    Sh {
+        sh: String,
+    },
+    Ref {
+        #[serde(rename = "ref")]
+        reference: String,
+    },
+    Value(Value),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Include {
+    Path(String),
+    Full(IncludeDef),
+}
+
+#[derive(Deserialize, Default)]
+struct IncludeDef {
+    taskfile: String,
+    #[serde(default)]
+    dir: Option<String>,
+    #[serde(default)]
+    optional: bool,
+    #[serde(default)]
+    internal: bool,
+    #[serde(default)]
+    flatten: bool,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    excludes: Vec<String>,
+    #[serde(default)]
+    vars: IndexMap<String, VarDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TaskDef {
+    Command(String),
+    Commands(Vec<Cmd>),
+    Full(Box<TaskBody>),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct TaskBody {
+    desc: String,
+    summary: String,
+    #[serde(deserialize_with = "one_or_many")]
+    prompt: Vec<String>,
+    cmds: Vec<Cmd>,
+    cmd: Option<Cmd>,
+    deps: Vec<Dep>,
+    vars: IndexMap<String, VarDef>,
+    env: IndexMap<String, VarDef>,
+    dir: Option<String>,
+    internal: bool,
+    aliases: Vec<String>,
+    preconditions: Vec<Precondition>,
+    status: Vec<String>,
+    sources: Vec<Value>,
+    generates: Vec<Value>,
+    platforms: Vec<String>,
+    ignore_error: bool,
+    #[serde(deserialize_with = "one_or_many")]
+    dotenv: Vec<String>,
+    requires: Option<Value>,
+    #[serde(flatten)]
+    other: IndexMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Cmd {
+    Shell(String),
+    Detailed(Box<CmdDef>),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct CmdDef {
+    cmd: Option<String>,
+    task: Option<String>,
+    defer: Option<Cmd>,
+    vars: IndexMap<String, VarDef>,
+    ignore_error: bool,
+    platforms: Vec<String>,
+    #[serde(rename = "for")]
+    each: Option<Value>,
+    #[serde(rename = "as")]
+    item: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Dep {
+    Name(String),
+    Call {
+        task: String,
+        #[serde(default)]
+        vars: IndexMap<String, VarDef>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Precondition {
+    Shell(String),
+    Check { sh: String, msg: Option<String> },
+}
+
+/// A string or a list of strings
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+/// Read a Taskfile, following `includes` relative to it. Included tasks
+/// become `namespace:task` tasks, as `task` names them.
+pub fn parse(content: &str, path: &Path) -> Result<Kylefile, Error> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let label = path.file_name().map_or_else(
+        || "Taskfile.yml".into(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    read(content, dir, dir, &label, 0)
+}
+
+fn read(
+    content: &str,
+    dir: &Path,
+    root: &Path,
+    label: &str,
+    depth: usize,
+) -> Result<Kylefile, Error> {
+    let tf: Taskfile = serde_yml::from_str(content)?;
+    let mut kf = Kylefile::default();
+    let mut warnings = Vec::new();
+
+    for (name, def) in tf.vars {
+        match var(&name, def) {
+            Ok(var) => {
+                kf.vars.insert(name, var);
+            }
+            Err(message) => warnings.push(message),
+        }
+    }
+    for (name, def) in tf.env {
+        match env(&name, def) {
+            Ok((value, var)) => {
+                if let Some(var) = var {
+                    kf.vars.entry(name.clone()).or_insert(var);
+                }
+                kf.env.insert(name, value);
+            }
+            Err(message) => warnings.push(message),
+        }
+    }
+    if !tf.dotenv.is_empty() {
+        warnings.push(format!("dotenv files not loaded: {}", tf.dotenv.join(", ")));
+    }
+    let unknown: Vec<&str> = tf
+        .other
+        .keys()
+        .map(String::as_str)
+        .filter(|key| !IGNORED_KEYS.contains(key))
+        .collect();
+    if !unknown.is_empty() {
+        warnings.push(format!("keys not imported: {}", unknown.join(", ")));
+    }
+
+    let mut aliases = Vec::new();
+    for (name, def) in tf.tasks {
+        let (task, names) = task(&name, def);
+        aliases.extend(names.into_iter().map(|alias| (alias, name.clone())));
+        kf.tasks.insert(name, task);
+    }
+    add_aliases(&mut kf, aliases);
+
+    kf.warnings = warnings
+        .into_iter()
+        .map(|message| format!("{label}: {message}"))
+        .collect();
+
+    for (namespace, include) in tf.includes {
+        let include = match include {
+            Include::Path(taskfile) => IncludeDef {
+                taskfile,
+                ..Default::default()
+            },
+            Include::Full(def) => def,
+        };
+        include_taskfile(&mut kf, &namespace, include, dir, root, label, depth);
+    }
+
+    Ok(kf)
+}
+
+/// Add an included Taskfile's tasks under `namespace`
+fn include_taskfile(
+    kf: &mut Kylefile,
+    namespace: &str,
+    include: IncludeDef,
+    dir: &Path,
+    root: &Path,
+    label: &str,
+    depth: usize,
+) {
+    let mut warn = |message: String| kf.warnings.push(format!("{label}: {message}"));
+    let path = dir.join(&include.taskfile);
+    let file = if path.is_dir() {
+        TASKFILE_NAMES
+            .iter()
+            .map(|name| path.join(name))
+            .find(|file| file.is_file())
+    } else {
+        Some(path).filter(|file| file.is_file())
+    };
+    let Some(file) = file else {
+        if !include.optional {
+            warn(format!("included Taskfile not found: {}", include.taskfile));
+        }
+        return;
+    };
+    if depth >= MAX_DEPTH {
+        warn("includes nest too deeply; stopped".into());
+        return;
+    }
+    let file_label = file
+        .strip_prefix(root)
+        .unwrap_or(&file)
+        .to_string_lossy()
+        .into_owned();
+    let included = fs::read_to_string(&file)
+        .map_err(Error::from)
+        .and_then(|content| {
+            read(
+                &content,
+                file.parent().unwrap_or(Path::new("")),
+                root,
+                &file_label,
+                depth + 1,
+            )
+        });
+    let included = match included {
+        Ok(included) => included,
+        Err(e) => {
+            warn(format!("can't read included Taskfile {file_label}: {e}"));
+            return;
+        }
+    };
+
+    let mut include_vars = IndexMap::new();
+    for (name, def) in include.vars {
+        match var(&name, def) {
+            Ok(var) => {
+                include_vars.insert(name, var);
+            }
+            Err(message) => warn(message),
+        }
+    }
+    // Included tasks run where the including Taskfile does, unless `dir:` moves them
+    let base = include
+        .dir
+        .as_deref()
+        .map(|sub| relative(&dir.join(sub), root));
+    let prefixes: Vec<String> = if include.flatten {
+        vec![String::new()]
+    } else {
+        std::iter::once(namespace)
+            .chain(include.aliases.iter().map(String::as_str))
+            .map(|prefix| format!("{prefix}:"))
+            .collect()
+    };
+    let rename = |prefix: &str, name: &str| match name.strip_prefix(':') {
+        Some(root_task) => root_task.to_string(),
+        None if included.tasks.contains_key(name) => format!("{prefix}{name}"),
+        None => name.to_string(),
+    };
+
+    for (index, prefix) in prefixes.iter().enumerate() {
+        for (name, task) in &included.tasks {
+            if include.excludes.contains(name) {
                 continue;
             }
+            let mut task = task.clone();
+            task.deps = task.deps.iter().map(|dep| rename(prefix, dep)).collect();
+            if let Run::Steps(steps) = &mut task.run {
+                for step in steps {
+                    let step = match step {
+                        Step::Defer { defer } => defer.as_mut(),
+                        step => step,
+                    };
+                    if let Step::Task { task: call, .. } = step {
+                        *call = rename(prefix, call);
+                    }
+                }
+            }
+            for (key, var) in &include_vars {
+                task.vars.insert(key.clone(), var.clone());
+            }
+            for (key, var) in &included.vars {
+                task.vars.entry(key.clone()).or_insert_with(|| var.clone());
+            }
+            for (key, value) in &included.env {
+                task.env.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+            if let Some(base) = &base {
+                task.dir = Some(match &task.dir {
+                    Some(sub) => base.join(sub).to_string_lossy().into_owned(),
+                    None => base.to_string_lossy().into_owned(),
+                });
+            }
+            // Alias prefixes only add other names to run the same tasks by
+            task.private |= include.internal || index > 0;
+            kf.tasks.entry(format!("{prefix}{name}")).or_insert(task);
+        }
+    }
+    kf.warnings.extend(included.warnings);
+}
 
-            let desc = def["desc"].as_str().unwrap_or("").to_string();
-            let cmds: Vec<String> = def["cmds"]
-                .as_array()
-                .map(|arr| {
-                    arr.iter()
-                        .filter_map(|v| v.as_str().map(String::from))
-                        .collect()
-                })
-                .unwrap_or_default();
-            let deps: Vec<String> = def["deps"]
-                .as_array()
-                .map(|arr| {
-                    arr.iter()
-                        .filter_map(|v| v.as_str().map(String::from))
-                        .collect()
-                })
-                .unwrap_or_default();
-
-            tasks.insert(
-                name.clone(),
-                Task {
-                    desc,
-                    run: cmds.join(" && "),
-                    deps,
-                },
-            );
+/// `path` relative to `root`, for a task's `dir`
+fn relative(path: &Path, root: &Path) -> PathBuf {
+    let path = path.strip_prefix(root).unwrap_or(path);
+    path.strip_prefix(".").unwrap_or(path).to_path_buf()
+}
+
+/// Register task `aliases` as hidden copies under the alias name
+fn add_aliases(kf: &mut Kylefile, aliases: Vec<(String, String)>) {
+    for (alias, name) in aliases {
+        if kf.tasks.contains_key(&alias) {
+            continue;
         }
+        let mut task = kf.tasks[&name].clone();
+        task.private = true;
+        kf.tasks.insert(alias, task);
     }
+}
 
-    Ok(Kylefile {
-        tasks,
+/// Map one task, returning it with its aliases
+fn task(name: &str, def: TaskDef) -> (Task, Vec<String>) {
+    let body = match def {
+        TaskDef::Command(cmd) => TaskBody {
+            cmds: vec![Cmd::Shell(cmd)],
+            ..Default::default()
+        },
+        TaskDef::Commands(cmds) => TaskBody {
+            cmds,
+            ..Default::default()
+        },
+        TaskDef::Full(body) => *body,
+    };
+
+    let mut notes = Vec::new();
+    let mut task = Task {
+        desc: if body.desc.is_empty() {
+            body.summary.lines().next().unwrap_or("").trim().to_string()
+        } else {
+            body.desc
+        },
+        private: body.internal,
+        dir: body.dir,
+        platforms: body.platforms,
         ..Default::default()
-    })
+    };
+    if !body.prompt.is_empty() {
+        task.confirm = Some(Confirm::Prompt(body.prompt.join(" ")));
+    }
+
+    for dep in body.deps {
+        match dep {
+            Dep::Name(dep) => task.deps.push(dep),
+            Dep::Call { task: dep, vars } => {
+                if !vars.is_empty() {
+                    notes.push(format!("variables passed to '{dep}' not imported"));
+                }
+                task.deps.push(dep);
+            }
+        }
+    }
+    for (key, def) in body.vars {
+        match var(&key, def) {
+            Ok(var) => {
+                task.vars.insert(key, var);
+            }
+            Err(message) => notes.push(message),
+        }
+    }
+    for (key, def) in body.env {
+        match env(&key, def) {
+            Ok((value, var)) => {
+                if let Some(var) = var {
+                    task.vars.entry(key.clone()).or_insert(var);
+                }
+                task.env.insert(key, value);
+            }
+            Err(message) => notes.push(message),
+        }
+    }
+
+    // Preconditions become checks that stop the task before its commands
+    let mut steps: Vec<Step> = body
+        .preconditions
+        .into_iter()
+        .map(|check| {
+            let (sh, msg) = match check {
+                Precondition::Shell(sh) => (sh, None),
+                Precondition::Check { sh, msg } => (sh, msg),
+            };
+            let msg = msg.unwrap_or_else(|| format!("precondition not met: {sh}"));
+            Step::Command(format!("{sh} || {{ echo {} >&2; exit 1; }}", quote(&msg)))
+        })
+        .collect();
+    for cmd in body.cmd.into_iter().chain(body.cmds) {
+        add_cmd(cmd, body.ignore_error, &mut steps, &mut notes);
+    }
+    task.run = Run::from_steps(steps);
+
+    let mut checks = Vec::new();
+    if !body.status.is_empty() {
+        checks.push("status");
+    }
+    if !body.sources.is_empty() {
+        checks.push("sources");
+    }
+    if !body.generates.is_empty() {
+        checks.push("generates");
+    }
+    if !checks.is_empty() {
+        notes.push(format!(
+            "up-to-date checks not imported ({}); the task always runs",
+            checks.join(", ")
+        ));
+    }
+    if body.requires.is_some() {
+        notes.push("required variables are not checked".into());
+    }
+    if !body.dotenv.is_empty() {
+        notes.push(format!(
+            "dotenv files not loaded: {}",
+            body.dotenv.join(", ")
+        ));
+    }
+    let unknown: Vec<&str> = body
+        .other
+        .keys()
+        .map(String::as_str)
+        .filter(|key| !IGNORED_TASK_KEYS.contains(key))
+        .collect();
+    if !unknown.is_empty() {
+        notes.push(format!("keys not imported: {}", unknown.join(", ")));
+    }
+
+    template_vars(name, &mut task, &mut notes);
+    task.notes = notes;
+    (task, body.aliases)
+}
+
+/// Map a `cmds` entry onto `steps`. A `defer:` entry stays where it was
+/// written, so it only runs if the commands before it did. Commands for
+/// other platforms are dropped at load.
+fn add_cmd(cmd: Cmd, ignore_error: bool, steps: &mut Vec<Step>, notes: &mut Vec<String>) {
+    let def = match cmd {
+        Cmd::Shell(cmd) => {
+            steps.push(Step::cmd(cmd, false, ignore_error));
+            return;
+        }
+        Cmd::Detailed(def) => *def,
+    };
+    if !def.platforms.is_empty() && !def.platforms.iter().any(|p| platform::matches(p)) {
+        return;
+    }
+    if let Some(inner) = def.defer {
+        let mut deferred = Vec::new();
+        add_cmd(inner, false, &mut deferred, notes);
+        steps.extend(deferred.into_iter().map(Step::defer));
+        return;
+    }
+
+    let step = if let Some(task) = def.task {
+        if !def.vars.is_empty() {
+            notes.push(format!("variables passed to '{task}' not imported"));
+        }
+        Step::Task {
+            task,
+            args: Vec::new(),
+        }
+    } else if let Some(cmd) = def.cmd {
+        Step::cmd(cmd, false, ignore_error || def.ignore_error)
+    } else {
+        return;
+    };
+
+    match def.each {
+        None => steps.push(step),
+        // A literal list unrolls into one step per item
+        Some(Value::Sequence(items)) if items.iter().all(|item| scalar(item).is_some()) => {
+            let name = def.item.as_deref().unwrap_or("ITEM");
+            for item in items.iter().filter_map(scalar) {
+                let mut step = step.clone();
+                if let Some(text) = match &mut step {
+                    Step::Command(cmd) | Step::Cmd { cmd, .. } => Some(cmd),
+                    _ => None,
+                } {
+                    *text = substitute(text, name, &item);
+                }
+                steps.push(step);
+            }
+        }
+        Some(_) => {
+            let text = step.command().unwrap_or_default();
+            notes.push(format!("loop not imported: {text}"));
+        }
+    }
 }
 
+/// Replace `{{.name}}` in `text` with `value`
+fn substitute(text: &str, name: &str, value: &str) -> String {
+    TEMPLATE_RE
+        .replace_all(text, |caps: &regex::Captures| {
+            if caps.get(1).is_none() && &caps[2] == name {
+                value.to_string()
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .into_owned()
+}
+
+/// Map the special variables `task` provides that kyle can, and note
+/// template actions kyle can't evaluate
+fn template_vars(name: &str, task: &mut Task, notes: &mut Vec<String>) {
+    let texts: Vec<String> = task
+        .run
+        .steps()
+        .iter()
+        .map(|step| match step {
+            Step::Defer { defer } => defer.as_ref(),
+            step => step,
+        })
+        .filter_map(|step| step.command().map(String::from))
+        .collect();
+    for text in &texts {
+        for action in ACTION_RE.find_iter(text) {
+            let action = action.as_str();
+            let Some(caps) = TEMPLATE_RE
+                .captures(action)
+                .filter(|c| &c[0] == action && !GO_KEYWORDS.contains(&&c[2]))
+            else {
+                notes.push(format!("template not evaluated: {action}"));
+                continue;
+            };
+            match &caps[2] {
+                "CLI_ARGS" if task.params.is_empty() => {
+                    task.params.push("*CLI_ARGS".into());
+                }
+                "TASK" => {
+                    task.vars
+                        .entry("TASK".into())
+                        .or_insert_with(|| Var::Static(name.to_string()));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Map a `vars:` entry. Scalars become literals and `{sh: ...}` stays dynamic.
+fn var(name: &str, def: VarDef) -> Result<Var, String> {
+    match def {
+        VarDef::Sh { sh } => Ok(Var::Dynamic { sh }),
+        VarDef::Ref { reference } => {
+            let var = reference.trim().trim_start_matches('.');
+            if var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !var.is_empty() {
+                Ok(Var::Static(format!("{{{{.{var}}}}}")))
+            } else {
+                Err(format!("variable '{name}' not imported: ref {reference}"))
+            }
+        }
+        VarDef::Value(value) => scalar(&value)
+            .map(Var::Static)
+            .ok_or_else(|| format!("variable '{name}' not imported: not a string")),
+    }
+}
+
+/// Map an `env:` entry. A `{sh: ...}` value is read through a dynamic var
+/// of the same name, returned alongside.
+fn env(name: &str, def: VarDef) -> Result<(String, Option<Var>), String> {
+    match def {
+        VarDef::Sh { sh } => Ok((format!("{{{{.{name}}}}}"), Some(Var::Dynamic { sh }))),
+        def => match var(name, def)? {
+            Var::Static(value) => Ok((value, None)),
+            Var::Dynamic { sh } => Ok((format!("{{{{.{name}}}}}"), Some(Var::Dynamic { sh }))),
+        },
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null => Some(String::new()),
+        _ => None,
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
 
     #[test]
     fn parse_taskfile() {
@@ -74,7 +699,7 @@ tasks:
     cmds:
       - go generate ./...
 "#;
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("Taskfile.yml")).unwrap();
         assert_eq!(kf.tasks.len(), 2);
         assert_eq!(kf.tasks["build"].desc, "Build the project");
         assert_eq!(kf.tasks["build"].run, "go build -o main .");
@@ -93,11 +718,91 @@ tasks:
       - echo step2
       - echo step3
 "#;
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("Taskfile.yml")).unwrap();
+        assert_eq!(
+            kf.tasks["build"].run.commands(),
+            vec!["echo step1", "echo step2", "echo step3"]
+        );
+    }
+
+    #[test]
+    fn parse_object_cmds() {
+        let content = r#"
+version: '3'
+tasks:
+  ci:
+    cmds:
+      - echo start
+      - cmd: golangci-lint run
+        ignore_error: true
+      - task: test
+      - defer: echo cleanup
+      - defer:
+          task: stop
+"#;
+        let kf = parse(content, Path::new("Taskfile.yml")).unwrap();
         assert_eq!(
-            kf.tasks["build"].run,
-            "echo step1 && echo step2 && echo step3"
+            kf.tasks["ci"].run,
+            Run::Steps(vec![
+                Step::Command("echo start".into()),
+                Step::cmd("golangci-lint run", false, true),
+                Step::Task {
+                    task: "test".into(),
+                    args: vec![],
+                },
+                Step::defer(Step::Command("echo cleanup".into())),
+                Step::defer(Step::Task {
+                    task: "stop".into(),
+                    args: vec![],
+                }),
+            ])
         );
+        assert!(kf.tasks["ci"].defer.is_empty());
+    }
+
+    #[test]
+    fn parse_vars() {
+        let content = r#"
+version: '3'
+vars:
+  IMAGE: acme/app
+  PORT: 8080
+  SHA:
+    sh: git rev-parse --short HEAD
+tasks:
+  build:
+    vars:
+      TAG: latest
+    cmds:
+      - docker build -t {{.IMAGE}}:{{.TAG}} .
+"#;
+        let kf = parse(content, Path::new("Taskfile.yml")).unwrap();
+        assert_eq!(kf.vars["IMAGE"], Var::Static("acme/app".into()));
+        assert_eq!(kf.vars["PORT"], Var::Static("8080".into()));
+        assert_eq!(
+            kf.vars["SHA"],
+            Var::Dynamic {
+                sh: "git rev-parse --short HEAD".into()
+            }
+        );
+        assert_eq!(kf.tasks["build"].vars["TAG"], Var::Static("latest".into()));
+    }
+
+    #[test]
+    fn parse_platforms() {
+        let content = r#"
+version: '3'
+tasks:
+  open:
+    platforms: [linux, darwin]
+    cmds:
+      - cmd: echo native
+        platforms: [plan9]
+      - echo everywhere
+"#;
+        let kf = parse(content, Path::new("Taskfile.yml")).unwrap();
+        assert_eq!(kf.tasks["open"].platforms, vec!["linux", "darwin"]);
+        assert_eq!(kf.tasks["open"].run, "echo everywhere");
     }
 
     #[test]
@@ -107,7 +812,202 @@ version: '3'
 tasks:
   hello: echo hello
 "#;
-        let kf = parse(content).unwrap();
+        let kf = parse(content, Path::new("Taskfile.yml")).unwrap();
         assert_eq!(kf.tasks["hello"].run, "echo hello");
     }
+    #[test]
+    fn parse_task_fields() {
+        let content = r#"
+version: '3'
+env:
+  MODE: release
+  HASH:
+    sh: git rev-parse HEAD
+tasks:
+  deploy:
+    summary: |
+      Deploy the app.
+
+      Pushes the image first.
+    prompt: Deploy to production?
+    dir: infra
+    internal: true
+    aliases: [ship, d]
+    env:
+      REGION: eu
+    preconditions:
+      - test -f .env
+      - sh: which docker
+        msg: docker is required
+    status:
+      - test -f out/deployed
+    sources: [src/**]
+    requires:
+      vars: [TARGET]
+    label: deploy-{{.TARGET}}
+    cmds:
+      - ./deploy.sh
+"#;
+        let kf = parse(content, Path::new("Taskfile.yml")).unwrap();
+        assert_eq!(kf.env["MODE"], "release");
+        assert_eq!(kf.env["HASH"], "{{.HASH}}");
+        assert_eq!(
+            kf.vars["HASH"],
+            Var::Dynamic {
+                sh: "git rev-parse HEAD".into()
+            }
+        );
+
+        let deploy = &kf.tasks["deploy"];
+        assert_eq!(deploy.desc, "Deploy the app.");
+        assert_eq!(
+            deploy.confirm,
+            Some(Confirm::Prompt("Deploy to production?".into()))
+        );
+        assert_eq!(deploy.dir.as_deref(), Some("infra"));
+        assert!(deploy.private);
+        assert_eq!(deploy.env["REGION"], "eu");
+        assert_eq!(
+            deploy.run.commands(),
+            vec![
+                "test -f .env || { echo 'precondition not met: test -f .env' >&2; exit 1; }",
+                "which docker || { echo 'docker is required' >&2; exit 1; }",
+                "./deploy.sh",
+            ]
+        );
+        assert_eq!(
+            deploy.notes,
+            vec![
+                "up-to-date checks not imported (status, sources); the task always runs",
+                "required variables are not checked",
+            ]
+        );
+        assert!(kf.tasks["ship"].private);
+        assert_eq!(kf.tasks["d"].run, deploy.run);
+    }
+
+    #[test]
+    fn parse_loops_and_templates() {
+        let content = r#"
+version: '3'
+tasks:
+  lint:
+    cmds:
+      - for: [api, web]
+        cmd: golangci-lint run ./{{.ITEM}}
+      - for: { var: DIRS }
+        cmd: echo {{.ITEM}}
+  test:
+    cmds:
+      - go test {{.CLI_ARGS}}
+      - echo {{.TASK}}
+      - echo {{if .CI}}ci{{end}}
+"#;
+        let kf = parse(content, Path::new("Taskfile.yml")).unwrap();
+        assert_eq!(
+            kf.tasks["lint"].run.commands(),
+            vec!["golangci-lint run ./api", "golangci-lint run ./web"]
+        );
+        assert_eq!(
+            kf.tasks["lint"].notes,
+            vec!["loop not imported: echo {{.ITEM}}"]
+        );
+        let test = &kf.tasks["test"];
+        assert_eq!(test.params, vec!["*CLI_ARGS"]);
+        assert_eq!(test.vars["TASK"], Var::Static("test".into()));
+        assert_eq!(
+            test.notes,
+            vec![
+                "template not evaluated: {{if .CI}}",
+                "template not evaluated: {{end}}",
+            ]
+        );
+    }
+
+    #[test]
+    fn warn_about_unmodelled_top_level_keys() {
+        let content =
+            "version: '3'\nset: [pipefail]\ndotenv: ['.env']\noutput: prefixed\ntasks: {}\n";
+        let kf = parse(content, Path::new("taskfile.dist.yml")).unwrap();
+        assert_eq!(
+            kf.warnings,
+            vec![
+                "taskfile.dist.yml: dotenv files not loaded: .env",
+                "taskfile.dist.yml: keys not imported: set",
+            ]
+        );
+    }
+
+    #[test]
+    fn includes_become_namespaces() {
+        let temp = TempDir::new().unwrap();
+        fs::create_dir_all(temp.path().join("docs")).unwrap();
+        fs::write(
+            temp.path().join("docs/Taskfile.yml"),
+            r#"
+version: '3'
+vars:
+  OUT: public
+tasks:
+  build:
+    deps: [clean]
+    cmds:
+      - hugo -d {{.OUT}}
+      - task: :notify
+  clean: rm -rf {{.OUT}}
+"#,
+        )
+        .unwrap();
+        fs::write(
+            temp.path().join("tools.yml"),
+            "version: '3'\ntasks:\n  fmt: gofmt -w .\n  secret: echo hidden\n",
+        )
+        .unwrap();
+        let content = r#"
+version: '3'
+includes:
+  docs:
+    taskfile: ./docs
+    dir: ./docs
+    aliases: [d]
+    vars:
+      OUT: site
+  tools:
+    taskfile: tools.yml
+    flatten: true
+    excludes: [secret]
+  extra:
+    taskfile: ./missing
+    optional: true
+  gone: ./gone.yml
+tasks:
+  notify: echo done
+"#;
+        let path = temp.path().join("Taskfile.yml");
+        let kf = parse(content, &path).unwrap();
+
+        let build = &kf.tasks["docs:build"];
+        assert_eq!(build.deps, vec!["docs:clean"]);
+        assert_eq!(
+            build.run,
+            Run::Steps(vec![
+                Step::Command("hugo -d {{.OUT}}".into()),
+                Step::Task {
+                    task: "notify".into(),
+                    args: vec![],
+                },
+            ])
+        );
+        assert_eq!(build.dir.as_deref(), Some("docs"));
+        assert_eq!(build.vars["OUT"], Var::Static("site".into()));
+        assert!(!build.private);
+        assert!(kf.tasks["d:build"].private);
+        assert_eq!(kf.tasks["d:build"].deps, vec!["d:clean"]);
+        assert_eq!(kf.tasks["fmt"].run, "gofmt -w .");
+        assert!(!kf.tasks.contains_key("secret"));
+        assert_eq!(
+            kf.warnings,
+            vec!["Taskfile.yml: included Taskfile not found: ./gone.yml"]
+        );
+    }
 }
@@ -0,0 +1,168 @@
+use super::Error;
+use serde_json::Value;
+
+/// The running kyle version
+pub const CURRENT: &str = env!("CARGO_PKG_VERSION");
+
+/// A Kylefile key whose meaning changed, or that is on its way out. Files that
+/// use it get a warning until they declare a `version` of at least `since`,
+/// which is how authors confirm they've moved to the new meaning.
+pub(super) struct Deprecation {
+    /// Dotted key path; `*` matches any name, e.g. `tasks.*.run`
+    pub key: &'static str,
+    /// Release that changed or deprecated the key
+    pub since: &'static str,
+    /// What changed and what to do about it
+    pub note: &'static str,
+}
+
+/// Add an entry here whenever a release changes what an existing field means
+pub(super) const DEPRECATIONS: &[Deprecation] = &[];
+
+/// A deprecated key found in a Kylefile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deprecated {
+    pub path: Vec<String>,
+    pub message: String,
+}
+
+/// Fail when a Kylefile needs a newer kyle than the one running
+pub fn require(required: &str) -> Result<(), Error> {
+    let wanted = parse(required).ok_or_else(|| Error::InvalidVersion(required.into()))?;
+    let current = parse(CURRENT).expect("crate version is valid");
+    if wanted > current {
+        return Err(Error::VersionTooNew {
+            required: required.trim().trim_start_matches('v').into(),
+            current: CURRENT.into(),
+        });
+    }
+    Ok(())
+}
+
+/// Find uses of deprecated keys that the file's declared `version` doesn't account for
+pub(super) fn deprecated_keys(
+    value: &Value,
+    declared: Option<&str>,
+    table: &[Deprecation],
+) -> Vec<Deprecated> {
+    let declared = declared.and_then(parse);
+    let mut found = Vec::new();
+    for deprecation in table {
+        let since = parse(deprecation.since).expect("deprecation versions are valid");
+        if declared.is_some_and(|v| v >= since) {
+            continue;
+        }
+        let pattern: Vec<&str> = deprecation.key.split('.').collect();
+        for path in matching_paths(value, &pattern) {
+            found.push(Deprecated {
+                message: format!(
+                    "`{}` is deprecated since kyle {}: {}",
+                    path.join("."),
+                    deprecation.since,
+                    deprecation.note
+                ),
+                path,
+            });
+        }
+    }
+    found
+}
+
+fn matching_paths(value: &Value, pattern: &[&str]) -> Vec<Vec<String>> {
+    let Some((head, rest)) = pattern.split_first() else {
+        return vec![Vec::new()];
+    };
+    let Some(fields) = value.as_object() else {
+        return Vec::new();
+    };
+
+    fields
+        .iter()
+        .filter(|(key, _)| *head == "*" || key == head)
+        .flat_map(|(key, child)| {
+            matching_paths(child, rest).into_iter().map(|mut path| {
+                path.insert(0, key.clone());
+                path
+            })
+        })
+        .collect()
+}
+
+/// `MAJOR.MINOR.PATCH`, with an optional `v` prefix. Missing parts count as 0
+/// and pre-release suffixes are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Version(u64, u64, u64);
+
+fn parse(text: &str) -> Option<Version> {
+    let text = text.trim().trim_start_matches('v');
+    let core = text.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(Version(major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(parse("0.1.9"), Some(Version(0, 1, 9)));
+        assert_eq!(parse("v1.2"), Some(Version(1, 2, 0)));
+        assert_eq!(parse("2"), Some(Version(2, 0, 0)));
+        assert_eq!(parse("1.0.0-beta.1"), Some(Version(1, 0, 0)));
+        assert_eq!(parse("1.x"), None);
+        assert_eq!(parse("1.2.3.4"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn current_version_is_accepted() {
+        assert!(require(CURRENT).is_ok());
+        assert!(require("0.0.1").is_ok());
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let err = require("999.0.0").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("this Kylefile needs kyle >= 999.0.0 (running {CURRENT}); run `kyle upgrade`")
+        );
+        assert!(matches!(require("soon"), Err(Error::InvalidVersion(_))));
+    }
+
+    const TABLE: &[Deprecation] = &[Deprecation {
+        key: "tasks.*.shell",
+        since: "0.3.0",
+        note: "use `run` instead",
+    }];
+
+    #[test]
+    fn deprecated_keys_warn_until_version_is_bumped() {
+        let value = serde_json::json!({
+            "tasks": {
+                "build": { "shell": "make" },
+                "test": { "run": "make test" }
+            }
+        });
+
+        let found = deprecated_keys(&value, None, TABLE);
+        assert_eq!(
+            found,
+            vec![Deprecated {
+                path: vec!["tasks".into(), "build".into(), "shell".into()],
+                message: "`tasks.build.shell` is deprecated since kyle 0.3.0: use `run` instead"
+                    .into(),
+            }]
+        );
+
+        assert_eq!(deprecated_keys(&value, Some("0.2.9"), TABLE).len(), 1);
+        assert!(deprecated_keys(&value, Some("0.3.0"), TABLE).is_empty());
+    }
+}
@@ -34,15 +34,24 @@ impl KyleMcp {
 #[tool_router]
 impl KyleMcp {
     #[tool(
-        description = "List all available tasks in the current project. Returns task names, descriptions, dependencies, and source file type. Also discovers tasks from subdirectory namespaces."
+        description = "List all available tasks in the current project. Returns task names, descriptions, dependencies, source file type and, for package.json, the package manager. Also discovers tasks from subdirectory namespaces."
     )]
     async fn list_tasks(&self) -> Result<CallToolResult, McpError> {
         let mut output = String::new();
 
         match load_from_dir(&self.root) {
             Ok((kf, source)) => {
-                output.push_str(&format!("Source: {source}\n\nTasks:\n"));
-                let mut names: Vec<_> = kf.tasks.keys().collect();
+                output.push_str(&format!("Source: {source}"));
+                if let Some(manager) = kf.package_manager {
+                    output.push_str(&format!(" (package manager: {manager})"));
+                }
+                output.push_str("\n\nTasks:\n");
+                let mut names: Vec<_> = kf
+                    .tasks
+                    .iter()
+                    .filter(|(_, task)| !task.private)
+                    .map(|(name, _)| name)
+                    .collect();
                 names.sort();
                 for name in names {
                     let task = &kf.tasks[name];
@@ -50,6 +59,9 @@ impl KyleMcp {
                     if !task.desc.is_empty() {
                         output.push_str(&format!(" — {}", task.desc));
                     }
+                    if !task.group.is_empty() {
+                        output.push_str(&format!(" [group: {}]", task.group));
+                    }
                     if !task.deps.is_empty() {
                         output.push_str(&format!(" [deps: {}]", task.deps.join(", ")));
                     }
@@ -67,7 +79,12 @@ impl KyleMcp {
             for ns in &discovered {
                 output.push_str(&format!("  {} ({})\n", ns.alias, ns.file_type));
                 if let Ok((kf, _)) = load_from_dir(&ns.path) {
-                    let mut names: Vec<_> = kf.tasks.keys().collect();
+                    let mut names: Vec<_> = kf
+                        .tasks
+                        .iter()
+                        .filter(|(_, task)| !task.private)
+                        .map(|(name, _)| name)
+                        .collect();
                     names.sort();
                     for name in names {
                         let task = &kf.tasks[name];
@@ -1,3 +1,6 @@
+use crate::config::parse_jsonc;
+use regex::Regex;
+use std::fs;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
@@ -65,13 +68,20 @@ const PROJECT_FILES: &[(&str, FileType)] = &[
     ("Kylefile.yaml", FileType::Kylefile),
     ("Kylefile.yml", FileType::Kylefile),
     ("Kylefile.toml", FileType::Kylefile),
+    ("Kylefile.json", FileType::Kylefile),
     ("Makefile", FileType::Makefile),
     ("makefile", FileType::Makefile),
     ("GNUmakefile", FileType::Makefile),
     ("justfile", FileType::Justfile),
     ("Justfile", FileType::Justfile),
     ("Taskfile.yml", FileType::Taskfile),
+    ("taskfile.yml", FileType::Taskfile),
     ("Taskfile.yaml", FileType::Taskfile),
+    ("taskfile.yaml", FileType::Taskfile),
+    ("Taskfile.dist.yml", FileType::Taskfile),
+    ("taskfile.dist.yml", FileType::Taskfile),
+    ("Taskfile.dist.yaml", FileType::Taskfile),
+    ("taskfile.dist.yaml", FileType::Taskfile),
     ("Rakefile", FileType::Rakefile),
     ("rakefile", FileType::Rakefile),
     ("package.json", FileType::PackageJson),
@@ -90,17 +100,138 @@ const PROJECT_FILES: &[(&str, FileType)] = &[
 
 const EXTENSION_FILES: &[(&str, FileType)] = &[(".csproj", FileType::CSharpProject)];
 
+/// Files that name a JS workspace package, and may declare the workspace
+const MANIFESTS: &[&str] = &["package.json", "deno.json", "deno.jsonc"];
+
 #[derive(Debug, Clone)]
 pub struct DiscoveredNamespace {
     pub alias: String,
     pub path: PathBuf,
     pub file_type: FileType,
+    /// The workspace pattern (e.g. `packages/*`) this package was declared by
+    pub workspace: Option<String>,
+}
+
+/// The packages a JS monorepo declares, from `pnpm-workspace.yaml`, the
+/// `workspaces` field of its root package.json or the `workspace` field of
+/// its deno.json
+#[derive(Debug)]
+struct Workspace {
+    include: Vec<(String, Regex)>,
+    exclude: Vec<Regex>,
+}
+
+impl Workspace {
+    fn read(root: &Path) -> Option<Self> {
+        // pnpm ignores package.json's `workspaces` once it has its own file
+        let patterns: Vec<String> =
+            if let Ok(content) = fs::read_to_string(root.join("pnpm-workspace.yaml")) {
+                let yaml: serde_yml::Value = serde_yml::from_str(&content).ok()?;
+                strings(
+                    yaml.get("packages")?
+                        .as_sequence()?
+                        .iter()
+                        .map(|v| v.as_str()),
+                )
+            } else {
+                MANIFESTS.iter().find_map(|file| {
+                    let json = manifest(&root.join(file))?;
+                    let workspaces = json.get("workspaces").or_else(|| json.get("workspace"))?;
+                    // Yarn also accepts `{ "packages": [...] }`, deno `{ "members": [...] }`
+                    let list = workspaces.as_array().or_else(|| {
+                        workspaces
+                            .get("packages")
+                            .or_else(|| workspaces.get("members"))?
+                            .as_array()
+                    })?;
+                    Some(strings(list.iter().map(|v| v.as_str())))
+                })?
+            };
+
+        let mut workspace = Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+        };
+        for pattern in patterns {
+            match pattern.strip_prefix('!') {
+                Some(negated) => workspace.exclude.push(glob_regex(negated)),
+                None => {
+                    let regex = glob_regex(&pattern);
+                    workspace.include.push((pattern, regex));
+                }
+            }
+        }
+        Some(workspace)
+    }
+
+    /// The pattern declaring the package at `relative`, if it is a member
+    fn member(&self, relative: &str) -> Option<&str> {
+        if self.exclude.iter().any(|re| re.is_match(relative)) {
+            return None;
+        }
+        self.include
+            .iter()
+            .find(|(_, re)| re.is_match(relative))
+            .map(|(pattern, _)| pattern.as_str())
+    }
+}
+
+fn strings<'a>(values: impl Iterator<Item = Option<&'a str>>) -> Vec<String> {
+    values.flatten().map(String::from).collect()
+}
+
+/// Translate a workspace glob (`packages/*`, `apps/**`, `!**/test/**`) into a
+/// regex over `/`-separated paths relative to the workspace root
+fn glob_regex(pattern: &str) -> Regex {
+    let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+    let mut regex = String::from("^");
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**/") {
+            regex.push_str("(?:.*/)?");
+            rest = after;
+        } else if rest == "/**" {
+            regex.push_str("(?:/.*)?");
+            rest = "";
+        } else if let Some(after) = rest.strip_prefix("**") {
+            regex.push_str(".*");
+            rest = after;
+        } else {
+            match c {
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                _ => regex.push_str(&regex::escape(&c.to_string())),
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).unwrap_or_else(|_| Regex::new("^$").unwrap())
+}
+
+fn manifest(path: &Path) -> Option<serde_json::Value> {
+    parse_jsonc(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn package_name(dir: &Path) -> Option<String> {
+    MANIFESTS.iter().find_map(|file| {
+        let json = manifest(&dir.join(file))?;
+        Some(json.get("name")?.as_str()?.to_string()).filter(|name| !name.is_empty())
+    })
 }
 
 /// Recursively scan for project files starting from root
-/// Returns a list of discovered namespaces with their aliases and paths
+/// Returns a list of discovered namespaces with their aliases and paths.
+/// When the root declares JS workspaces, member packages are aliased by their
+/// package name and other nested package.json and deno.json files are
+/// skipped, unless the `workspace_members_only` setting is off.
 pub fn discover_namespaces(root: &Path) -> Vec<DiscoveredNamespace> {
+    discover(root, crate::settings::get().workspace_members_only)
+}
+
+fn discover(root: &Path, members_only: bool) -> Vec<DiscoveredNamespace> {
     let mut namespaces = Vec::new();
+    let workspace = Workspace::read(root);
 
     let walker = WalkDir::new(root)
         .min_depth(1) // Skip the root directory itself
@@ -126,14 +257,37 @@ pub fn discover_namespaces(root: &Path) -> Vec<DiscoveredNamespace> {
 
         // Create alias from relative path
         if let Ok(relative) = dir.strip_prefix(root) {
-            let alias = relative.to_string_lossy().replace('\\', "/");
-            if !alias.is_empty() {
-                namespaces.push(DiscoveredNamespace {
-                    alias,
-                    path: dir.to_path_buf(),
-                    file_type,
-                });
+            let mut alias = relative.to_string_lossy().replace('\\', "/");
+            if alias.is_empty() {
+                continue;
             }
+
+            let member = workspace
+                .as_ref()
+                .filter(|_| MANIFESTS.iter().any(|file| dir.join(file).is_file()))
+                .and_then(|ws| ws.member(&alias));
+            match member {
+                Some(_) => {
+                    if let Some(name) = package_name(dir) {
+                        alias = name;
+                    }
+                }
+                // Fixtures and examples that happen to carry a package.json
+                None if workspace.is_some()
+                    && members_only
+                    && matches!(file_type, FileType::PackageJson | FileType::DenoJson) =>
+                {
+                    continue;
+                }
+                None => {}
+            }
+
+            namespaces.push(DiscoveredNamespace {
+                alias,
+                path: dir.to_path_buf(),
+                file_type,
+                workspace: member.map(String::from),
+            });
         }
     }
 
@@ -143,6 +297,39 @@ pub fn discover_namespaces(root: &Path) -> Vec<DiscoveredNamespace> {
     namespaces
 }
 
+/// The directory of the workspace package called `name`, if `root` is a JS
+/// workspace that declares one
+pub fn workspace_package(root: &Path, name: &str) -> Option<PathBuf> {
+    Workspace::read(root)?;
+    discover(root, true)
+        .into_iter()
+        .find(|ns| ns.workspace.is_some() && ns.alias == name)
+        .map(|ns| ns.path)
+}
+
+/// Project files directly in `dir`, one per type, in detection order
+pub fn project_files(dir: &Path) -> Vec<(PathBuf, FileType)> {
+    let mut found: Vec<(PathBuf, FileType)> = Vec::new();
+    for (name, file_type) in PROJECT_FILES {
+        let path = dir.join(name);
+        if path.is_file() && !found.iter().any(|(_, ft)| ft == file_type) {
+            found.push((path, file_type.clone()));
+        }
+    }
+
+    let entries = fs::read_dir(dir).into_iter().flatten().flatten();
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_str().unwrap_or("");
+        if let Some((_, file_type)) = EXTENSION_FILES.iter().find(|(ext, _)| name.ends_with(ext))
+            && !found.iter().any(|(_, ft)| ft == file_type)
+        {
+            found.push((entry.path(), file_type.clone()));
+        }
+    }
+    found
+}
+
 fn detect_file_type(filename: &str) -> Option<FileType> {
     if let Some((_, ft)) = PROJECT_FILES.iter().find(|(name, _)| *name == filename) {
         return Some(ft.clone());
@@ -226,6 +413,29 @@ mod tests {
         assert_eq!(namespaces[0].file_type, FileType::Justfile);
     }
 
+    #[test]
+    fn test_project_files_in_detection_order() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("Cargo.toml"), "").unwrap();
+        fs::write(temp.path().join("package.json"), "{}").unwrap();
+        fs::write(temp.path().join("App.csproj"), "").unwrap();
+        fs::create_dir(temp.path().join("backend")).unwrap();
+        fs::write(temp.path().join("backend").join("Makefile"), "").unwrap();
+
+        let types: Vec<FileType> = project_files(temp.path())
+            .into_iter()
+            .map(|(_, file_type)| file_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                FileType::PackageJson,
+                FileType::CargoToml,
+                FileType::CSharpProject
+            ]
+        );
+    }
+
     #[test]
     fn test_kylefile_takes_precedence() {
         let temp = TempDir::new().unwrap();
@@ -238,4 +448,108 @@ mod tests {
         assert_eq!(namespaces.len(), 1);
         assert_eq!(namespaces[0].file_type, FileType::Kylefile);
     }
+
+    fn write_package(dir: &Path, name: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("package.json"), format!(r#"{{"name": "{name}"}}"#)).unwrap();
+    }
+
+    #[test]
+    fn test_workspace_packages_use_package_names() {
+        let temp = TempDir::new().unwrap();
+        fs::write(
+            temp.path().join("package.json"),
+            r#"{"workspaces": ["packages/*", "!packages/legacy"]}"#,
+        )
+        .unwrap();
+        write_package(&temp.path().join("packages/web"), "@acme/web");
+        fs::write(temp.path().join("packages/web/Makefile"), "").unwrap();
+        write_package(&temp.path().join("packages/legacy"), "legacy");
+        write_package(&temp.path().join("examples/demo"), "demo");
+        fs::create_dir_all(temp.path().join("tools")).unwrap();
+        fs::write(temp.path().join("tools/Makefile"), "").unwrap();
+
+        let namespaces = discover(temp.path(), true);
+        let aliases: Vec<&str> = namespaces.iter().map(|ns| ns.alias.as_str()).collect();
+        assert_eq!(aliases, vec!["@acme/web", "tools"]);
+        assert_eq!(namespaces[0].workspace.as_deref(), Some("packages/*"));
+        assert_eq!(namespaces[0].file_type, FileType::Makefile);
+        assert_eq!(namespaces[1].workspace, None);
+
+        let all = discover(temp.path(), false);
+        let aliases: Vec<&str> = all.iter().map(|ns| ns.alias.as_str()).collect();
+        assert_eq!(
+            aliases,
+            vec!["@acme/web", "examples/demo", "packages/legacy", "tools"]
+        );
+    }
+
+    #[test]
+    fn test_pnpm_workspace_file() {
+        let temp = TempDir::new().unwrap();
+        fs::write(
+            temp.path().join("package.json"),
+            r#"{"workspaces": ["other/*"]}"#,
+        )
+        .unwrap();
+        fs::write(
+            temp.path().join("pnpm-workspace.yaml"),
+            "packages:\n  - 'apps/**'\n  - '!**/test/**'\n",
+        )
+        .unwrap();
+        write_package(&temp.path().join("apps/site/docs"), "docs");
+        write_package(&temp.path().join("apps/site/test/app"), "fixture");
+        write_package(&temp.path().join("other/pkg"), "other");
+
+        let namespaces = discover(temp.path(), true);
+        let aliases: Vec<&str> = namespaces.iter().map(|ns| ns.alias.as_str()).collect();
+        assert_eq!(aliases, vec!["docs"]);
+        assert_eq!(
+            workspace_package(temp.path(), "docs"),
+            Some(temp.path().join("apps/site/docs"))
+        );
+        assert_eq!(workspace_package(temp.path(), "other"), None);
+    }
+
+    #[test]
+    fn test_yarn_workspaces_object() {
+        let temp = TempDir::new().unwrap();
+        fs::write(
+            temp.path().join("package.json"),
+            r#"{"workspaces": {"packages": ["./libs/*/"], "nohoist": ["**/x"]}}"#,
+        )
+        .unwrap();
+        write_package(&temp.path().join("libs/core"), "@acme/core");
+
+        let namespaces = discover(temp.path(), true);
+        assert_eq!(namespaces.len(), 1);
+        assert_eq!(namespaces[0].alias, "@acme/core");
+        assert_eq!(namespaces[0].workspace.as_deref(), Some("./libs/*/"));
+    }
+
+    #[test]
+    fn test_deno_workspace_members() {
+        let temp = TempDir::new().unwrap();
+        fs::write(
+            temp.path().join("deno.jsonc"),
+            "{\n  // members\n  \"workspace\": [\"./add\", \"./subtract\",],\n}",
+        )
+        .unwrap();
+        for (dir, name) in [("add", "@math/add"), ("subtract", "@math/subtract")] {
+            fs::create_dir(temp.path().join(dir)).unwrap();
+            fs::write(
+                temp.path().join(dir).join("deno.json"),
+                format!(r#"{{"name": "{name}"}}"#),
+            )
+            .unwrap();
+        }
+        fs::create_dir(temp.path().join("scratch")).unwrap();
+        fs::write(temp.path().join("scratch").join("deno.json"), "{}").unwrap();
+
+        let namespaces = discover(temp.path(), true);
+        let aliases: Vec<&str> = namespaces.iter().map(|ns| ns.alias.as_str()).collect();
+        assert_eq!(aliases, vec!["@math/add", "@math/subtract"]);
+        assert_eq!(namespaces[0].file_type, FileType::DenoJson);
+        assert_eq!(namespaces[0].workspace.as_deref(), Some("./add"));
+    }
 }
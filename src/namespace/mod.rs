@@ -53,9 +53,14 @@ pub fn parse_task_ref(input: &str) -> TaskRef {
 }
 
 /// Resolve a namespace path relative to a base directory
-/// Returns the absolute path to the namespace directory
+/// Returns the absolute path to the namespace directory. A namespace that
+/// isn't a directory may name a JS workspace package, like `@acme/web`.
 pub fn resolve_namespace(base: &Path, namespace: &str) -> PathBuf {
-    base.join(namespace)
+    let dir = base.join(namespace);
+    if dir.is_dir() {
+        return dir;
+    }
+    discovery::workspace_package(base, namespace).unwrap_or(dir)
 }
 
 #[cfg(test)]
@@ -1,20 +1,38 @@
-use crate::config::{Kylefile, Source, load_from_dir};
+mod vars;
+
+use crate::config::{
+    GLOBAL_NAMESPACE, Kylefile, PackageManager, Source, Step, Task, Var, current_platform,
+    escape_templates, load_from_dir, load_global,
+};
 use crate::namespace::{parse_task_ref, resolve_namespace};
+use crate::output;
 use std::collections::HashSet;
-use std::io;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Once;
+use std::sync::atomic::{AtomicBool, Ordering};
 use thiserror::Error;
 
 const SHELL: &str = "sh";
 const SHELL_FLAG: &str = "-c";
 
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INTERRUPT_HANDLER: Once = Once::new();
+
 pub struct Runner {
     kylefile: Kylefile,
     working_dir: PathBuf,
     root_dir: PathBuf,
     executed: HashSet<String>,
     in_progress: Vec<String>,
+    vars: vars::Vars,
+    source: Option<Source>,
+    delegate: bool,
+    /// Running deferred cleanup, which must go ahead even after Ctrl-C
+    cleanup: bool,
+    /// Set on Ctrl-C; shared with the runners of namespaces loaded from here
+    interrupt: &'static AtomicBool,
 }
 
 #[derive(Debug, Error)]
@@ -22,6 +40,13 @@ pub enum Error {
     #[error("task not found: {0}")]
     TaskNotFound(String),
 
+    #[error("task '{task}' is not available on {current} (platforms: {platforms})")]
+    UnsupportedPlatform {
+        task: String,
+        platforms: String,
+        current: String,
+    },
+
     #[error("namespace not found: {0}")]
     NamespaceNotFound(String),
 
@@ -48,6 +73,55 @@ pub enum Error {
         #[source]
         source: io::Error,
     },
+
+    #[error("task '{task}' failed at step {step} ({cmd}): {source}")]
+    StepFailed {
+        task: String,
+        step: usize,
+        cmd: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("task '{task}': {source}")]
+    Vars {
+        task: String,
+        #[source]
+        source: vars::Error,
+    },
+
+    #[error("task '{0}' interrupted")]
+    Interrupted(String),
+
+    #[error("task '{task}' needs a value for '{param}'")]
+    MissingArgument { task: String, param: String },
+
+    #[error("task '{task}' takes {max} argument(s), got {got}")]
+    TooManyArguments {
+        task: String,
+        max: usize,
+        got: usize,
+    },
+
+    #[error("task '{0}' not confirmed")]
+    NotConfirmed(String),
+
+    #[error("cleanup '{cleanup}' for task '{task}' failed: {source}")]
+    DeferFailed {
+        task: String,
+        cleanup: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("task '{task}' failed at step {step} (task: {call}): {source}")]
+    CallFailed {
+        task: String,
+        step: usize,
+        call: String,
+        #[source]
+        source: Box<Error>,
+    },
 }
 
 impl Runner {
@@ -59,6 +133,11 @@ impl Runner {
             root_dir: cwd,
             executed: HashSet::new(),
             in_progress: Vec::new(),
+            vars: vars::Vars::default(),
+            source: None,
+            delegate: false,
+            cleanup: false,
+            interrupt: &INTERRUPTED,
         }
     }
 
@@ -69,14 +148,33 @@ impl Runner {
             root_dir,
             executed: HashSet::new(),
             in_progress: Vec::new(),
+            vars: vars::Vars::default(),
+            source: None,
+            delegate: false,
+            cleanup: false,
+            interrupt: &INTERRUPTED,
         }
     }
 
+    /// Note where the tasks came from. With `delegate` set, tasks from another
+    /// tool's file run through that tool, e.g. `make build`, when it's on PATH;
+    /// namespaces loaded from here follow the same choice.
+    pub fn delegating(mut self, source: Source, delegate: bool) -> Self {
+        self.source = Some(source);
+        self.delegate = delegate;
+        self
+    }
+
     pub fn run(&mut self, task_name: &str, args: &[String]) -> Result<(), Error> {
         if self.executed.contains(task_name) {
             return Ok(());
         }
+        self.invoke(task_name, args)
+    }
 
+    /// Run a task regardless of whether it already ran in this invocation.
+    /// Used for `task:` steps, which call a task explicitly rather than depend on it.
+    fn invoke(&mut self, task_name: &str, args: &[String]) -> Result<(), Error> {
         // Cycle detection
         if self.in_progress.contains(&task_name.to_string()) {
             let cycle_start = self
@@ -94,20 +192,163 @@ impl Runner {
             });
         }
 
-        let task = self
+        let mut task = self
             .kylefile
             .tasks
             .get(task_name)
-            .ok_or_else(|| Error::TaskNotFound(task_name.into()))?
+            .ok_or_else(|| self.missing_task(task_name))?
             .clone();
 
+        // The tool applies the task's prerequisites, dir, env and params itself
+        if let Some(cmd) = self.delegated(task_name, &task, args) {
+            println!("→ {task_name}");
+            watch_interrupts();
+            self.run_command(task_name, &cmd, &Task::default())?;
+            self.executed.insert(task_name.into());
+            return Ok(());
+        }
+
+        // Arguments fill the task's params rather than being appended to its command
+        let args = if task.params.is_empty() {
+            args
+        } else {
+            self.bind_params(task_name, &mut task, args)?;
+            &[]
+        };
+        let task = self.expand_task(task_name, &task)?;
+        confirm(task_name, &task)?;
+
         self.in_progress.push(task_name.into());
+        let result = self.run_deps(&task).and_then(|()| {
+            println!("→ {task_name}");
+            self.execute(task_name, &task, args)
+        });
+        self.in_progress.pop();
+        result?;
+
+        self.executed.insert(task_name.into());
+        Ok(())
+    }
+
+    /// The command that hands `task_name` to its own tool, if it should
+    fn delegated(&self, task_name: &str, task: &Task, args: &[String]) -> Option<String> {
+        if !self.delegate || task.global {
+            return None;
+        }
+        let source = self.source?;
+        let manager = self.kylefile.package_manager;
+        let cmd = source.delegate(task_name, manager)?;
+        let tool = cmd.split_whitespace().next()?;
+        if !self.on_path(tool) {
+            return None;
+        }
+        if args.is_empty() {
+            return Some(cmd);
+        }
+        let cmd = match source {
+            // make would take each argument as another target, so kyle runs
+            // the recipe itself with the arguments appended
+            Source::Makefile => return None,
+            // rake takes a task's arguments as `task[a,b]`, commas escaped
+            Source::Rakefile => {
+                let args: Vec<String> = args.iter().map(|arg| arg.replace(',', "\\,")).collect();
+                let call = format!("{task_name}[{}]", args.join(","));
+                return Some(format!("rake '{}'", call.replace('\'', r"'\''")));
+            }
+            // These tools only pass arguments on after `--`
+            Source::PackageJson if manager.is_none_or(PackageManager::needs_separator) => {
+                format!("{cmd} --")
+            }
+            Source::ComposerJson | Source::Taskfile => format!("{cmd} --"),
+            _ => cmd,
+        };
+        Some(with_args(&cmd, args))
+    }
+
+    /// Set each param as a task var from `args`, falling back to its default
+    fn bind_params(
+        &mut self,
+        task_name: &str,
+        task: &mut Task,
+        args: &[String],
+    ) -> Result<(), Error> {
+        let params = task.params();
+        let mut remaining = args.iter();
+        for param in &params {
+            let given: Vec<String> = if param.rest {
+                remaining
+                    .by_ref()
+                    .map(|arg| escape_templates(arg))
+                    .collect()
+            } else {
+                remaining
+                    .next()
+                    .map(|arg| escape_templates(arg))
+                    .into_iter()
+                    .collect()
+            };
+            let value = match (given.is_empty(), &param.default) {
+                (false, _) => given.join(" "),
+                (true, Some(default)) => default.clone(),
+                (true, None) if !param.required => String::new(),
+                (true, None) => {
+                    return Err(Error::MissingArgument {
+                        task: task_name.into(),
+                        param: param.name.clone(),
+                    });
+                }
+            };
+            task.vars.insert(param.name.clone(), Var::Static(value));
+        }
+        if remaining.next().is_some() {
+            return Err(Error::TooManyArguments {
+                task: task_name.into(),
+                max: params.len(),
+                got: args.len(),
+            });
+        }
+        // A task called again with other arguments must not see the old values
+        self.vars.forget(task_name);
+        Ok(())
+    }
+
+    /// Whether `tool` can be launched from this task's PATH. When it can't,
+    /// delegation falls back to the commands kyle read from the file.
+    fn on_path(&self, tool: &str) -> bool {
+        std::env::split_paths(&self.build_path()).any(|dir| is_executable(&dir.join(tool)))
+    }
+
+    fn missing_task(&self, task_name: &str) -> Error {
+        match self.kylefile.unavailable.get(task_name) {
+            Some(platforms) => Error::UnsupportedPlatform {
+                task: task_name.into(),
+                platforms: platforms.join(", "),
+                current: current_platform(),
+            },
+            None => Error::TaskNotFound(task_name.into()),
+        }
+    }
 
-        // Run dependencies first (without extra args, args only apply to main task)
+    fn expand_task(&mut self, task_name: &str, task: &Task) -> Result<Task, Error> {
+        let scope = vars::Scope {
+            global: &self.kylefile.vars,
+            task: Some((task_name, &task.vars)),
+            dir: &self.working_dir,
+        };
+        self.vars
+            .expand_task(task, &self.kylefile.env, scope)
+            .map_err(|e| Error::Vars {
+                task: task_name.into(),
+                source: e,
+            })
+    }
+
+    /// Run dependencies first (without extra args, args only apply to main task)
+    fn run_deps(&mut self, task: &Task) -> Result<(), Error> {
         for dep in &task.deps {
             let dep_ref = parse_task_ref(dep);
 
-            if dep_ref.is_namespaced() {
+            if dep_ref.is_namespaced() && !self.kylefile.tasks.contains_key(dep) {
                 if self.executed.contains(dep) {
                     continue;
                 }
@@ -116,6 +357,7 @@ impl Runner {
                         .namespace
                         .expect("invariant: is_namespaced() guarantees namespace is Some"),
                     &dep_ref.task_name,
+                    &[],
                 )
                 .map_err(|e| Error::DependencyFailed {
                     dep: dep.clone(),
@@ -128,71 +370,233 @@ impl Runner {
                 })?;
             }
         }
+        Ok(())
+    }
 
-        println!("→ {task_name}");
+    fn execute(&mut self, task_name: &str, task: &Task, args: &[String]) -> Result<(), Error> {
+        watch_interrupts();
+
+        let mut deferred: Vec<(usize, Step)> = Vec::new();
+        let mut result = self.run_steps(task_name, task, args, &mut deferred);
+
+        // Deferred steps run last-registered first, whatever happened above
+        for (n, step) in deferred.into_iter().rev() {
+            if let Err(e) = self.run_cleanup(&step, task) {
+                let err = Error::StepFailed {
+                    task: task_name.into(),
+                    step: n,
+                    cmd: describe_step(&step),
+                    source: e,
+                };
+                keep_first_error(&mut result, err);
+            }
+        }
 
-        // Append extra args to command if provided
-        let cmd = if args.is_empty() {
-            task.run.clone()
-        } else {
-            format!("{} {}", task.run, args.join(" "))
-        };
+        // Task-level cleanup runs in the order it was written, like a `finally` block
+        for cleanup in &task.defer {
+            if let Err(e) = self.run_cleanup(cleanup, task) {
+                let err = Error::DeferFailed {
+                    task: task_name.into(),
+                    cleanup: describe_step(cleanup),
+                    source: e,
+                };
+                keep_first_error(&mut result, err);
+            }
+        }
 
-        let path_env = self.build_path();
+        result
+    }
+
+    fn run_steps(
+        &mut self,
+        task_name: &str,
+        task: &Task,
+        args: &[String],
+        deferred: &mut Vec<(usize, Step)>,
+    ) -> Result<(), Error> {
+        let steps = task.run.steps();
+
+        // A single command keeps the plain error shape: there is no step to point at
+        if let [Step::Command(cmd)] = steps.as_slice() {
+            return self.run_command(task_name, &with_args(cmd, args), task);
+        }
+
+        // Extra args go to the last command, as they did when steps were joined with `&&`
+        let last_cmd = steps.iter().rposition(|s| s.command().is_some());
+
+        for (i, step) in steps.iter().enumerate() {
+            if self.interrupted() {
+                return Err(Error::Interrupted(task_name.into()));
+            }
+
+            let n = i + 1;
+            match step {
+                Step::Defer { defer } => deferred.push((n, (**defer).clone())),
+                Step::Task { task: call, args } => {
+                    self.call(call, args).map_err(|e| Error::CallFailed {
+                        task: task_name.into(),
+                        step: n,
+                        call: call.clone(),
+                        source: Box::new(e),
+                    })?;
+                }
+                Step::Command(cmd) | Step::Cmd { cmd, .. } => {
+                    let (echo, ignore_error) = match step {
+                        Step::Cmd {
+                            echo, ignore_error, ..
+                        } => (*echo, *ignore_error),
+                        _ => (false, false),
+                    };
+                    let cmd = if Some(i) == last_cmd {
+                        with_args(cmd, args)
+                    } else {
+                        cmd.clone()
+                    };
+                    if echo {
+                        println!("$ {cmd}");
+                    }
+                    match self.shell(&cmd, task).and_then(check_status) {
+                        Ok(()) => {}
+                        Err(_) if self.interrupted() => {
+                            return Err(Error::Interrupted(task_name.into()));
+                        }
+                        Err(e) if ignore_error => {
+                            output::warn(&format!(
+                                "step {n} of task '{task_name}' failed (ignored): {e}"
+                            ));
+                        }
+                        Err(e) => {
+                            return Err(Error::StepFailed {
+                                task: task_name.into(),
+                                step: n,
+                                cmd,
+                                source: e,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
 
-        let status = Command::new(SHELL)
+    /// Whether Ctrl-C should stop the next step; never during cleanup
+    fn interrupted(&self) -> bool {
+        !self.cleanup && self.interrupt.load(Ordering::SeqCst)
+    }
+
+    fn run_command(&self, task_name: &str, cmd: &str, task: &Task) -> Result<(), Error> {
+        let status = self.shell(cmd, task).map_err(|e| Error::ExecutionFailed {
+            task: task_name.into(),
+            source: e,
+        })?;
+        if self.interrupted() {
+            return Err(Error::Interrupted(task_name.into()));
+        }
+        check_status(status).map_err(|e| Error::ExecutionFailed {
+            task: task_name.into(),
+            source: e,
+        })
+    }
+
+    /// Call a task from a `task:` step, falling back to namespaces for `ns:task` names
+    fn call(&mut self, call: &str, args: &[String]) -> Result<(), Error> {
+        let call_ref = parse_task_ref(call);
+        match call_ref.namespace {
+            Some(ns) if !self.kylefile.tasks.contains_key(call) => {
+                self.run_namespaced(&ns, &call_ref.task_name, args)
+            }
+            _ => self.invoke(call, args),
+        }
+    }
+
+    fn run_cleanup(&mut self, step: &Step, task: &Task) -> io::Result<()> {
+        match step {
+            Step::Task { task, args } => {
+                let cleanup = std::mem::replace(&mut self.cleanup, true);
+                let result = self.call(task, args);
+                self.cleanup = cleanup;
+                result.map_err(|e| io::Error::other(e.to_string()))
+            }
+            Step::Command(cmd) | Step::Cmd { cmd, .. } => {
+                self.shell(cmd, task).and_then(check_status)
+            }
+            Step::Defer { defer } => self.run_cleanup(defer, task),
+        }
+    }
+
+    fn shell(&self, cmd: &str, task: &Task) -> io::Result<ExitStatus> {
+        let dir = match &task.dir {
+            Some(dir) => self.working_dir.join(dir),
+            None => self.working_dir.clone(),
+        };
+        Command::new(SHELL)
             .arg(SHELL_FLAG)
-            .arg(&cmd)
-            .current_dir(&self.working_dir)
-            .env("PATH", &path_env)
+            .arg(cmd)
+            .current_dir(dir)
+            .envs(&task.env)
+            .env("PATH", self.build_path())
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
             .status()
-            .map_err(|e| Error::ExecutionFailed {
-                task: task_name.into(),
-                source: e,
-            })?;
-
-        if !status.success() {
-            self.in_progress.pop();
-            return Err(Error::ExecutionFailed {
-                task: task_name.into(),
-                source: io::Error::other(format!("exit code: {}", status.code().unwrap_or(-1))),
-            });
-        }
-
-        self.in_progress.pop();
-        self.executed.insert(task_name.into());
-        Ok(())
     }
 
     /// Run a task in a different namespace
-    fn run_namespaced(&mut self, namespace: &str, task_name: &str) -> Result<(), Error> {
+    fn run_namespaced(
+        &mut self,
+        namespace: &str,
+        task_name: &str,
+        args: &[String],
+    ) -> Result<(), Error> {
         let ns_key = format!("{namespace}:{task_name}");
-        if self.executed.contains(&ns_key) {
+        if args.is_empty() && self.executed.contains(&ns_key) {
             return Ok(());
         }
 
-        let ns_dir = resolve_namespace(&self.root_dir, namespace);
+        let mut ns_runner = self.namespace_runner(namespace)?;
 
-        if !ns_dir.exists() {
-            return Err(Error::NamespaceNotFound(namespace.into()));
-        }
+        println!("→ [{namespace}]");
+        ns_runner.run(task_name, args)?;
 
-        let (kf, _source) = load_from_dir(&ns_dir).map_err(|e| Error::NamespaceLoadFailed {
+        self.executed.insert(ns_key);
+
+        Ok(())
+    }
+
+    fn namespace_runner(&self, namespace: &str) -> Result<Runner, Error> {
+        let load_failed = |e| Error::NamespaceLoadFailed {
             namespace: namespace.into(),
             source: e,
-        })?;
+        };
 
-        let mut ns_runner = Runner::with_working_dir(kf, ns_dir, self.root_dir.clone());
+        // Global tasks run where kyle was invoked, like any other task
+        if namespace == GLOBAL_NAMESPACE {
+            let kf = load_global()
+                .map_err(load_failed)?
+                .ok_or_else(|| Error::NamespaceNotFound(namespace.into()))?;
+            let mut runner =
+                Runner::with_working_dir(kf, self.working_dir.clone(), self.root_dir.clone())
+                    .delegating(Source::Global, self.delegate);
+            runner.cleanup = self.cleanup;
+            runner.interrupt = self.interrupt;
+            return Ok(runner);
+        }
 
-        println!("→ [{namespace}]");
-        ns_runner.run(task_name, &[])?;
+        let ns_dir = resolve_namespace(&self.root_dir, namespace);
 
-        self.executed.insert(ns_key);
+        if !ns_dir.exists() {
+            return Err(Error::NamespaceNotFound(namespace.into()));
+        }
 
-        Ok(())
+        let (kf, source) = load_from_dir(&ns_dir).map_err(load_failed)?;
+
+        let mut runner = Runner::with_working_dir(kf, ns_dir, self.root_dir.clone())
+            .delegating(source, self.delegate);
+        runner.cleanup = self.cleanup;
+        runner.interrupt = self.interrupt;
+        Ok(runner)
     }
 
     pub fn kylefile(&self) -> &Kylefile {
@@ -200,7 +604,7 @@ impl Runner {
     }
 
     pub fn source(&self) -> Option<Source> {
-        None // Will be set by CLI when needed
+        self.source
     }
 
     fn build_path(&self) -> String {
@@ -221,13 +625,177 @@ impl Runner {
         }
     }
 
-    pub fn list_tasks(&self) {
-        for (name, task) in &self.kylefile.tasks {
-            if task.desc.is_empty() {
-                println!("  {name}");
+    /// List the project's own tasks
+    pub fn list_tasks(&self, hide_undocumented: bool) {
+        self.print_tasks(false, hide_undocumented);
+    }
+
+    /// List tasks merged in from the user-global Kylefile
+    pub fn list_global_tasks(&self, hide_undocumented: bool) {
+        self.print_tasks(true, hide_undocumented);
+    }
+
+    /// Ungrouped tasks first, then each group under its own heading in the
+    /// order the groups first appear
+    fn print_tasks(&self, global: bool, hide_undocumented: bool) {
+        let tasks: Vec<(&String, &Task)> = self
+            .kylefile
+            .tasks
+            .iter()
+            .filter(|(_, t)| t.global == global && !t.private)
+            .filter(|(_, t)| !hide_undocumented || !t.desc.is_empty())
+            .collect();
+
+        let mut groups: Vec<&str> = Vec::new();
+        for (_, task) in &tasks {
+            if !groups.contains(&task.group.as_str()) {
+                groups.push(&task.group);
+            }
+        }
+        groups.sort_by_key(|group| !group.is_empty());
+
+        for group in groups {
+            let indent = if group.is_empty() {
+                "  "
             } else {
-                println!("  {name} - {}", task.desc);
+                println!("\n  {group}:");
+                "    "
+            };
+            for (name, task) in tasks.iter().filter(|(_, t)| t.group == group) {
+                let origin = task
+                    .origin
+                    .as_deref()
+                    .map(|path| format!(" (from {})", display_path(path)))
+                    .unwrap_or_default();
+                if task.desc.is_empty() {
+                    println!("{indent}{name}{origin}");
+                } else {
+                    println!("{indent}{name} - {}{origin}", task.desc);
+                }
             }
         }
     }
 }
+
+/// Ask the task's `confirm` question, if it has one
+fn confirm(task_name: &str, task: &Task) -> Result<(), Error> {
+    let Some(question) = task.confirm.as_ref().and_then(|c| c.question(task_name)) else {
+        return Ok(());
+    };
+    eprint!("{question} [y/N] ");
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    let _ = io::stdin().read_line(&mut answer);
+    let answer = answer.trim();
+    if answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes") {
+        Ok(())
+    } else {
+        Err(Error::NotConfirmed(task_name.into()))
+    }
+}
+
+/// Show paths under the home directory as `~/...`
+fn display_path(path: &Path) -> String {
+    match dirs::home_dir().and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    // npm, yarn and friends install as .cmd shims
+    ["exe", "cmd", "bat"]
+        .iter()
+        .any(|ext| path.with_extension(ext).is_file())
+}
+
+fn with_args(cmd: &str, args: &[String]) -> String {
+    if args.is_empty() {
+        cmd.to_string()
+    } else {
+        format!("{cmd} {}", args.join(" "))
+    }
+}
+
+fn check_status(status: ExitStatus) -> io::Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "exit code: {}",
+            status.code().unwrap_or(-1)
+        )))
+    }
+}
+
+/// Catch Ctrl-C so kyle outlives the interrupted child and can still run
+/// deferred cleanup. The child gets the signal from the terminal as usual.
+fn watch_interrupts() {
+    INTERRUPT_HANDLER.call_once(|| {
+        let _ = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst));
+    });
+}
+
+/// Record a cleanup failure without masking the error that came before it
+fn keep_first_error(result: &mut Result<(), Error>, err: Error) {
+    if result.is_ok() {
+        *result = Err(err);
+    } else {
+        output::warn(&err.to_string());
+    }
+}
+
+fn describe_step(step: &Step) -> String {
+    match step {
+        Step::Task { task, .. } => format!("task: {task}"),
+        Step::Command(cmd) | Step::Cmd { cmd, .. } => cmd.clone(),
+        Step::Defer { defer } => describe_step(defer),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Run;
+    use indexmap::IndexMap;
+    use tempfile::TempDir;
+
+    #[test]
+    fn task_cleanup_runs_after_interrupt() {
+        let temp = TempDir::new().unwrap();
+        let marker = temp.path().join("stopped");
+        let serve = Task {
+            run: Run::from("true"),
+            defer: vec![Step::Task {
+                task: "stop".into(),
+                args: Vec::new(),
+            }],
+            ..Default::default()
+        };
+        let stop = Task {
+            run: Run::from_commands(vec!["true".into(), format!("touch {}", marker.display())]),
+            ..Default::default()
+        };
+        let kylefile = Kylefile {
+            tasks: IndexMap::from([("serve".into(), serve), ("stop".into(), stop)]),
+            ..Default::default()
+        };
+        // A flag of its own, so tests running alongside never see it set
+        static INTERRUPT: AtomicBool = AtomicBool::new(true);
+        let mut runner = Runner::with_working_dir(kylefile, temp.path().into(), temp.path().into());
+        runner.interrupt = &INTERRUPT;
+
+        let result = runner.run("serve", &[]);
+
+        assert!(matches!(result, Err(Error::Interrupted(task)) if task == "serve"));
+        assert!(marker.exists());
+    }
+}
@@ -0,0 +1,365 @@
+use crate::config::{Run, Step, TEMPLATE_RE, Task, Var};
+use indexmap::IndexMap;
+use regex::Captures;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("undefined variable '{name}' in `{text}` (write \\{{{{ for literal braces)")]
+    Undefined { name: String, text: String },
+
+    #[error("circular variable reference: {0}")]
+    Cycle(String),
+
+    #[error("variable '{name}' failed to evaluate `{cmd}`: {source}")]
+    Shell {
+        name: String,
+        cmd: String,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// Where a template is expanded: Kylefile vars, plus the vars of the task
+/// being run. Task vars shadow Kylefile vars of the same name. Dynamic vars
+/// run in `dir`, the task's working directory.
+#[derive(Clone, Copy)]
+pub struct Scope<'a> {
+    pub global: &'a IndexMap<String, Var>,
+    pub task: Option<(&'a str, &'a IndexMap<String, Var>)>,
+    pub dir: &'a Path,
+}
+
+/// Resolves `{{ .NAME }}` templates. Dynamic `sh` vars only run when first
+/// referenced from a directory and are cached for the rest of the run.
+#[derive(Default)]
+pub struct Vars {
+    cache: HashMap<(Option<String>, String, PathBuf), String>,
+    resolving: Vec<String>,
+}
+
+impl Vars {
+    pub fn expand(&mut self, text: &str, scope: Scope) -> Result<String, Error> {
+        if !text.contains("{{") {
+            return Ok(text.to_string());
+        }
+
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for caps in TEMPLATE_RE.captures_iter(text) {
+            let whole = caps.get(0).expect("group 0 always matches");
+            out.push_str(&text[last..whole.start()]);
+            last = whole.end();
+            out.push_str(&self.substitute(&caps, text, scope)?);
+        }
+        out.push_str(&text[last..]);
+        Ok(out)
+    }
+
+    /// Drop cached values of `task`'s own vars, e.g. after its params change
+    pub fn forget(&mut self, task: &str) {
+        self.cache
+            .retain(|(owner, _, _), _| owner.as_deref() != Some(task));
+    }
+
+    /// Expand every templated field of a task, merging Kylefile-level `env` beneath the task's own
+    pub fn expand_task(
+        &mut self,
+        task: &Task,
+        env: &IndexMap<String, String>,
+        scope: Scope,
+    ) -> Result<Task, Error> {
+        let mut merged_env = env.clone();
+        merged_env.extend(task.env.clone());
+
+        // The rest of the task sees dynamic vars from the directory it runs in
+        let dir = task
+            .dir
+            .as_deref()
+            .map(|dir| self.expand(dir, scope))
+            .transpose()?;
+        let task_dir = dir.as_deref().map(|dir| scope.dir.join(dir));
+        let scope = Scope {
+            dir: task_dir.as_deref().unwrap_or(scope.dir),
+            ..scope
+        };
+
+        Ok(Task {
+            run: self.expand_run(&task.run, scope)?,
+            deps: self.expand_all(&task.deps, scope)?,
+            defer: task
+                .defer
+                .iter()
+                .map(|step| self.expand_step(step, scope))
+                .collect::<Result<_, _>>()?,
+            dir,
+            env: merged_env
+                .into_iter()
+                .map(|(k, v)| Ok((k, self.expand(&v, scope)?)))
+                .collect::<Result<_, _>>()?,
+            ..task.clone()
+        })
+    }
+
+    fn expand_run(&mut self, run: &Run, scope: Scope) -> Result<Run, Error> {
+        Ok(match run {
+            Run::Command(cmd) => Run::Command(self.expand(cmd, scope)?),
+            Run::Steps(steps) => Run::Steps(
+                steps
+                    .iter()
+                    .map(|step| self.expand_step(step, scope))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Run::Platform(runs) => Run::Platform(
+                runs.iter()
+                    .map(|(spec, run)| Ok((spec.clone(), self.expand_run(run, scope)?)))
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+
+    fn expand_step(&mut self, step: &Step, scope: Scope) -> Result<Step, Error> {
+        Ok(match step {
+            Step::Command(cmd) => Step::Command(self.expand(cmd, scope)?),
+            Step::Cmd {
+                cmd,
+                echo,
+                ignore_error,
+            } => Step::Cmd {
+                cmd: self.expand(cmd, scope)?,
+                echo: *echo,
+                ignore_error: *ignore_error,
+            },
+            Step::Task { task, args } => Step::Task {
+                task: self.expand(task, scope)?,
+                args: self.expand_all(args, scope)?,
+            },
+            Step::Defer { defer } => Step::defer(self.expand_step(defer, scope)?),
+        })
+    }
+
+    fn expand_all(&mut self, items: &[String], scope: Scope) -> Result<Vec<String>, Error> {
+        items.iter().map(|item| self.expand(item, scope)).collect()
+    }
+
+    fn substitute(&mut self, caps: &Captures, text: &str, scope: Scope) -> Result<String, Error> {
+        let whole = &caps[0];
+        if caps.get(1).is_some() {
+            return Ok(whole[1..].to_string());
+        }
+        self.lookup(&caps[2], text, scope)
+    }
+
+    fn lookup(&mut self, name: &str, text: &str, scope: Scope) -> Result<String, Error> {
+        // Kylefile vars can't see task vars, so they resolve in a narrower scope
+        let (key, var, inner) = match scope.task {
+            Some((task, vars)) if vars.contains_key(name) => (
+                (Some(task.to_string()), name.to_string(), scope.dir.into()),
+                &vars[name],
+                scope,
+            ),
+            _ => match scope.global.get(name) {
+                Some(var) => (
+                    (None, name.to_string(), scope.dir.into()),
+                    var,
+                    Scope {
+                        task: None,
+                        ..scope
+                    },
+                ),
+                None => {
+                    return Err(Error::Undefined {
+                        name: name.into(),
+                        text: text.into(),
+                    });
+                }
+            },
+        };
+
+        if let Some(value) = self.cache.get(&key) {
+            return Ok(value.clone());
+        }
+
+        if self.resolving.iter().any(|n| n == name) {
+            let mut chain = self.resolving.clone();
+            chain.push(name.into());
+            return Err(Error::Cycle(chain.join(" → ")));
+        }
+
+        self.resolving.push(name.into());
+        let result = match var {
+            Var::Static(value) => self.expand(value, inner),
+            Var::Dynamic { sh } => self
+                .expand(sh, inner)
+                .and_then(|cmd| evaluate(name, &cmd, scope.dir)),
+        };
+        self.resolving.pop();
+
+        let value = result?;
+        self.cache.insert(key, value.clone());
+        Ok(value)
+    }
+}
+
+fn evaluate(name: &str, cmd: &str, dir: &Path) -> Result<String, Error> {
+    let shell_error = |source| Error::Shell {
+        name: name.into(),
+        cmd: cmd.into(),
+        source,
+    };
+
+    let output = Command::new(super::SHELL)
+        .arg(super::SHELL_FLAG)
+        .arg(cmd)
+        .current_dir(dir)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(shell_error)?;
+
+    if !output.status.success() {
+        return Err(shell_error(io::Error::other(format!(
+            "exit code: {}",
+            output.status.code().unwrap_or(-1)
+        ))));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches(['\n', '\r'])
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(entries: &[(&str, Var)]) -> IndexMap<String, Var> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    fn scope<'a>(
+        global: &'a IndexMap<String, Var>,
+        task: Option<(&'a str, &'a IndexMap<String, Var>)>,
+    ) -> Scope<'a> {
+        Scope {
+            global,
+            task,
+            dir: Path::new("."),
+        }
+    }
+
+    #[test]
+    fn expand_dotted_and_bare_names() {
+        let global = vars(&[("IMAGE", Var::Static("acme/app".into()))]);
+        let mut v = Vars::default();
+        let out = v
+            .expand("docker push {{ .IMAGE }} {{IMAGE}}", scope(&global, None))
+            .unwrap();
+        assert_eq!(out, "docker push acme/app acme/app");
+    }
+
+    #[test]
+    fn task_vars_shadow_global() {
+        let global = vars(&[("TAG", Var::Static("latest".into()))]);
+        let task = vars(&[("TAG", Var::Static("dev".into()))]);
+        let mut v = Vars::default();
+        let out = v
+            .expand("{{.TAG}}", scope(&global, Some(("build", &task))))
+            .unwrap();
+        assert_eq!(out, "dev");
+    }
+
+    #[test]
+    fn vars_reference_other_vars() {
+        let global = vars(&[
+            ("IMAGE", Var::Static("acme/app:{{.VERSION}}".into())),
+            ("VERSION", Var::Static("1.2".into())),
+        ]);
+        let mut v = Vars::default();
+        let out = v.expand("{{.IMAGE}}", scope(&global, None)).unwrap();
+        assert_eq!(out, "acme/app:1.2");
+    }
+
+    #[test]
+    fn undefined_var_is_an_error() {
+        let global = IndexMap::new();
+        let mut v = Vars::default();
+        let err = v
+            .expand("echo {{.MISSING}}", scope(&global, None))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "undefined variable 'MISSING' in `echo {{.MISSING}}` (write \\{{ for literal braces)"
+        );
+    }
+
+    #[test]
+    fn escaped_braces_stay_literal() {
+        let global = IndexMap::new();
+        let mut v = Vars::default();
+        let out = v
+            .expand(r"docker ps --format '\{{.ID}}'", scope(&global, None))
+            .unwrap();
+        assert_eq!(out, "docker ps --format '{{.ID}}'");
+    }
+
+    #[test]
+    fn cycle_is_detected() {
+        let global = vars(&[
+            ("A", Var::Static("{{.B}}".into())),
+            ("B", Var::Static("{{.A}}".into())),
+        ]);
+        let mut v = Vars::default();
+        let err = v.expand("{{.A}}", scope(&global, None)).unwrap_err();
+        assert!(matches!(err, Error::Cycle(_)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dynamic_vars_are_cached() {
+        let global = vars(&[(
+            "N",
+            Var::Dynamic {
+                sh: "echo $$".into(),
+            },
+        )]);
+        let mut v = Vars::default();
+        let first = v.expand("{{.N}}", scope(&global, None)).unwrap();
+        let second = v.expand("{{.N}}", scope(&global, None)).unwrap();
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dynamic_vars_run_in_the_task_dir() {
+        let temp = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(temp.path().join("web")).unwrap();
+        let global = vars(&[("HERE", Var::Dynamic { sh: "pwd".into() })]);
+        let task = Task {
+            run: "echo {{.HERE}}".into(),
+            dir: Some("web".into()),
+            ..Default::default()
+        };
+        let mut v = Vars::default();
+        let scope = Scope {
+            global: &global,
+            task: Some(("build", &task.vars)),
+            dir: temp.path(),
+        };
+        let expanded = v.expand_task(&task, &IndexMap::new(), scope).unwrap();
+        let web = temp.path().join("web").canonicalize().unwrap();
+        assert_eq!(expanded.run, format!("echo {}", web.display()).as_str());
+
+        // Run from elsewhere, the same var gives that directory instead
+        let root = v.expand("{{.HERE}}", scope).unwrap();
+        assert_eq!(Path::new(&root), temp.path().canonicalize().unwrap());
+    }
+}
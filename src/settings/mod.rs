@@ -9,7 +9,7 @@ use thiserror::Error;
 const CONFIG_DIR: &str = "kyle";
 const CONFIG_FILE: &str = "config.toml";
 const DEFAULT_FORMAT: &str = "toml";
-const ALLOWED_FORMATS: &[&str] = &["yaml", "toml"];
+const ALLOWED_FORMATS: &[&str] = &["yaml", "toml", "json"];
 const ALLOWED_BOOLS: &[&str] = &["true", "false"];
 
 static CONFIG_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
@@ -28,6 +28,14 @@ pub struct Settings {
     pub auto_upgrade: bool,
     #[serde(default = "default_true")]
     pub verify_updates: bool,
+    #[serde(default)]
+    pub sort_tasks: bool,
+    #[serde(default = "default_true")]
+    pub delegate: bool,
+    #[serde(default)]
+    pub hide_undocumented: bool,
+    #[serde(default = "default_true")]
+    pub workspace_members_only: bool,
 }
 
 fn default_true() -> bool {
@@ -44,6 +52,10 @@ impl Default for Settings {
             default_format: default_format(),
             auto_upgrade: false,
             verify_updates: true,
+            sort_tasks: false,
+            delegate: true,
+            hide_undocumented: false,
+            workspace_members_only: true,
         }
     }
 }
@@ -111,6 +123,46 @@ pub fn set(key: &str, value: &str) -> Result<(), Error> {
             }
             settings.verify_updates = value == "true";
         }
+        "sort_tasks" => {
+            if !ALLOWED_BOOLS.contains(&value) {
+                return Err(Error::InvalidValue {
+                    key: key.into(),
+                    value: value.into(),
+                    allowed: ALLOWED_BOOLS.join(", "),
+                });
+            }
+            settings.sort_tasks = value == "true";
+        }
+        "delegate" => {
+            if !ALLOWED_BOOLS.contains(&value) {
+                return Err(Error::InvalidValue {
+                    key: key.into(),
+                    value: value.into(),
+                    allowed: ALLOWED_BOOLS.join(", "),
+                });
+            }
+            settings.delegate = value == "true";
+        }
+        "hide_undocumented" => {
+            if !ALLOWED_BOOLS.contains(&value) {
+                return Err(Error::InvalidValue {
+                    key: key.into(),
+                    value: value.into(),
+                    allowed: ALLOWED_BOOLS.join(", "),
+                });
+            }
+            settings.hide_undocumented = value == "true";
+        }
+        "workspace_members_only" => {
+            if !ALLOWED_BOOLS.contains(&value) {
+                return Err(Error::InvalidValue {
+                    key: key.into(),
+                    value: value.into(),
+                    allowed: ALLOWED_BOOLS.join(", "),
+                });
+            }
+            settings.workspace_members_only = value == "true";
+        }
         _ => return Err(Error::UnknownKey(key.into())),
     }
 
@@ -124,6 +176,10 @@ pub fn get_value(key: &str) -> Result<String, Error> {
         "default_format" => Ok(settings.default_format),
         "auto_upgrade" => Ok(settings.auto_upgrade.to_string()),
         "verify_updates" => Ok(settings.verify_updates.to_string()),
+        "sort_tasks" => Ok(settings.sort_tasks.to_string()),
+        "delegate" => Ok(settings.delegate.to_string()),
+        "hide_undocumented" => Ok(settings.hide_undocumented.to_string()),
+        "workspace_members_only" => Ok(settings.workspace_members_only.to_string()),
         _ => Err(Error::UnknownKey(key.into())),
     }
 }
@@ -145,11 +201,23 @@ pub fn path() -> &'static Path {
     CONFIG_PATH.as_path()
 }
 
+/// Directory holding the settings file, also home to the global Kylefile
+pub fn dir() -> &'static Path {
+    CONFIG_PATH.parent().unwrap_or(Path::new("."))
+}
+
 pub fn list() -> HashMap<&'static str, String> {
     let settings = get();
     HashMap::from([
         ("default_format", settings.default_format),
         ("auto_upgrade", settings.auto_upgrade.to_string()),
         ("verify_updates", settings.verify_updates.to_string()),
+        ("sort_tasks", settings.sort_tasks.to_string()),
+        ("delegate", settings.delegate.to_string()),
+        ("hide_undocumented", settings.hide_undocumented.to_string()),
+        (
+            "workspace_members_only",
+            settings.workspace_members_only.to_string(),
+        ),
     ])
 }
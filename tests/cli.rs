@@ -104,6 +104,78 @@ tasks:
         .stdout(predicate::str::contains("test"));
 }
 
+#[test]
+fn list_tasks_by_group_and_hide_undocumented() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Makefile"),
+        "help: ## Show this help\n\t@echo help\n\n##@ Development\n\nbuild: ## Build it\n\techo build\n\ninternal:\n\techo internal\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "  help - Show this help\n\n  Development:\n    build - Build it\n    internal\n",
+        ));
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .args(["config", "set", "hide_undocumented", "true"])
+        .assert()
+        .success();
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("build - Build it"))
+        .stdout(predicate::str::contains("internal").not());
+}
+
+#[test]
+fn list_and_run_js_workspace_packages() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("package.json"),
+        r#"{"name": "acme", "workspaces": ["packages/*"], "scripts": {"lint": "echo lint"}}"#,
+    )
+    .unwrap();
+    let web = temp.path().join("packages").join("web");
+    fs::create_dir_all(&web).unwrap();
+    fs::write(
+        web.join("package.json"),
+        r#"{"name": "@acme/web", "scripts": {"build": "echo web-built"}}"#,
+    )
+    .unwrap();
+    let demo = temp.path().join("examples").join("demo");
+    fs::create_dir_all(&demo).unwrap();
+    fs::write(demo.join("package.json"), r#"{"name": "demo"}"#).unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Workspace packages:\n  packages/*\n    @acme/web: (package.json)\n",
+        ))
+        .stdout(predicate::str::contains("demo").not());
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .args(["--no-delegate", "@acme/web:build"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("web-built"));
+}
+
 #[test]
 fn no_kylefile_error() {
     let temp = TempDir::new().unwrap();
@@ -144,6 +216,79 @@ tasks:
         .stdout(predicate::str::contains("hello world"));
 }
 
+#[test]
+fn run_task_with_params_confirm_and_private() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Kylefile"),
+        r#"# kyle: yaml
+name: test
+
+tasks:
+  greet:
+    params: [name, greeting=hello, "*rest"]
+    run: echo "{{greeting}} {{name}} [{{rest}}]"
+  wipe:
+    confirm: Really wipe?
+    run: echo wiped
+  helper:
+    private: true
+    run: echo helped
+"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["greet", "world"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hello world []"));
+    kyle()
+        .current_dir(temp.path())
+        .args(["greet", "world", "hi", "a", "b"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hi world [a b]"));
+    kyle()
+        .current_dir(temp.path())
+        .arg("greet")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "task 'greet' needs a value for 'name'",
+        ));
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("wipe")
+        .write_stdin("n\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Really wipe? [y/N]"))
+        .stderr(predicate::str::contains("task 'wipe' not confirmed"));
+    kyle()
+        .current_dir(temp.path())
+        .arg("wipe")
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("wiped"));
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("helper")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("helped"));
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .assert()
+        .stdout(predicate::str::contains("greet"))
+        .stdout(predicate::str::contains("helper").not());
+}
+
 #[test]
 fn run_task_with_deps() {
     let temp = TempDir::new().unwrap();
@@ -250,160 +395,182 @@ run = "echo args:"
         .stdout(predicate::str::contains("args: --release -v"));
 }
 
-// =============================================================================
-// Config Commands
-// =============================================================================
-
 #[test]
-fn config_list() {
-    kyle()
-        .arg("config")
-        .arg("list")
-        .assert()
-        .success()
-        .stdout(predicate::str::contains("default_format"));
-}
+fn run_step_list() {
+    let temp = TempDir::new().unwrap();
+    let kylefile = temp.path().join("Kylefile");
+    fs::write(
+        &kylefile,
+        r#"# kyle: toml
+name = "test"
 
-#[test]
-fn config_get() {
-    kyle()
-        .arg("config")
-        .arg("get")
-        .arg("default_format")
-        .assert()
-        .success();
-}
+[tasks.greet]
+run = "echo greet-$1"
 
-#[test]
-fn config_get_unknown_key() {
-    kyle()
-        .arg("config")
-        .arg("get")
-        .arg("unknown_key")
-        .assert()
-        .failure()
-        .stderr(predicate::str::contains("unknown config key"));
-}
+[tasks.ci]
+run = [
+    "echo step-one",
+    { task = "greet" },
+    { cmd = "exit 3", ignore_error = true },
+    "echo step-four",
+]
+"#,
+    )
+    .unwrap();
 
-#[test]
-fn config_path() {
     kyle()
-        .arg("config")
-        .arg("path")
+        .current_dir(temp.path())
+        .arg("ci")
         .assert()
         .success()
-        .stdout(predicate::str::contains("config.toml"));
+        .stdout(predicate::str::contains("step-one"))
+        .stdout(predicate::str::contains("greet-"))
+        .stdout(predicate::str::contains("step-four"))
+        .stderr(predicate::str::contains("failed (ignored)"));
 }
 
-// =============================================================================
-// Init Command
-// =============================================================================
-
 #[test]
-fn init_with_name_toml() {
+fn step_failure_reports_step() {
     let temp = TempDir::new().unwrap();
+    let kylefile = temp.path().join("Kylefile");
+    fs::write(
+        &kylefile,
+        r#"# kyle: yaml
+name: test
+
+tasks:
+  ci:
+    run:
+      - defer: echo cleaned-up
+      - echo first
+      - exit 7
+      - echo never
+"#,
+    )
+    .unwrap();
 
     kyle()
         .current_dir(temp.path())
-        .arg("init")
-        .arg("myproject")
-        .arg("--toml")
+        .arg("ci")
         .assert()
-        .success()
-        .stdout(predicate::str::contains("Created Kylefile"));
-
-    let kylefile = temp.path().join("Kylefile");
-    assert!(kylefile.exists());
-
-    let content = fs::read_to_string(&kylefile).unwrap();
-    assert!(content.contains("# kyle: toml"));
-    assert!(content.contains("version = "));
-    assert!(content.contains("name = \"myproject\""));
+        .failure()
+        .stdout(predicate::str::contains("first"))
+        .stdout(predicate::str::contains("never").not())
+        .stdout(predicate::str::contains("cleaned-up"))
+        .stderr(predicate::str::contains(
+            "task 'ci' failed at step 3 (exit 7): exit code: 7",
+        ));
 }
 
 #[test]
-fn init_with_name_yaml() {
+fn defer_runs_after_failure() {
     let temp = TempDir::new().unwrap();
-
-    kyle()
-        .current_dir(temp.path())
-        .arg("init")
-        .arg("myproject")
-        .arg("--yaml")
-        .assert()
-        .success()
-        .stdout(predicate::str::contains("Created Kylefile"));
-
     let kylefile = temp.path().join("Kylefile");
-    assert!(kylefile.exists());
-
-    let content = fs::read_to_string(&kylefile).unwrap();
-    assert!(content.contains("# kyle: yaml"));
-    assert!(content.contains("version:"));
-    assert!(content.contains("name: myproject"));
-}
+    fs::write(
+        &kylefile,
+        r#"# kyle: toml
+name = "test"
 
-// =============================================================================
-// MCP
-// =============================================================================
+[tasks.integration]
+run = "echo testing && exit 4"
+defer = ["echo containers-down"]
+"#,
+    )
+    .unwrap();
 
-#[test]
-fn mcp_config_output() {
     kyle()
-        .arg("mcp")
-        .arg("--config")
+        .current_dir(temp.path())
+        .arg("integration")
         .assert()
-        .success()
-        .stdout(predicate::str::contains("mcpServers"))
-        .stdout(predicate::str::contains("\"kyle\""))
-        .stdout(predicate::str::contains("\"mcp\""));
+        .failure()
+        .stdout(predicate::str::contains("containers-down"))
+        .stderr(predicate::str::contains(
+            "task 'integration' failed: exit code: 4",
+        ));
 }
 
-// =============================================================================
-// Non-Kylefile Sources (no spurious warning)
-// =============================================================================
-
 #[test]
-fn package_json_no_kylefile_warning() {
+fn finally_alias_runs_after_success() {
     let temp = TempDir::new().unwrap();
+    let kylefile = temp.path().join("Kylefile");
     fs::write(
-        temp.path().join("package.json"),
-        r#"{"name":"test","scripts":{"build":"echo building"}}"#,
+        &kylefile,
+        r#"# kyle: yaml
+name: test
+
+tasks:
+  stop:
+    run: echo stopped
+  integration:
+    run: echo testing
+    finally:
+      - echo removing-tmp
+      - task: stop
+"#,
     )
     .unwrap();
 
     kyle()
         .current_dir(temp.path())
-        .arg("build")
+        .arg("integration")
         .assert()
         .success()
-        .stdout(predicate::str::contains("building"))
-        .stderr(predicate::str::contains("no Kylefile").not());
+        .stdout(predicate::str::contains("testing"))
+        .stdout(predicate::str::contains("removing-tmp"))
+        .stdout(predicate::str::contains("stopped"));
 }
 
+#[cfg(unix)]
 #[test]
-fn package_json_list_no_warning() {
+fn defer_runs_after_interrupt() {
     let temp = TempDir::new().unwrap();
+    let kylefile = temp.path().join("Kylefile");
     fs::write(
-        temp.path().join("package.json"),
-        r#"{"name":"test","scripts":{"build":"echo b","test":"echo t"}}"#,
+        &kylefile,
+        r#"# kyle: toml
+name = "test"
+
+[tasks.serve]
+run = "kill -INT $PPID; sleep 1"
+defer = ["echo server-stopped"]
+"#,
     )
     .unwrap();
 
     kyle()
         .current_dir(temp.path())
+        .arg("serve")
         .assert()
-        .success()
-        .stdout(predicate::str::contains("build"))
-        .stderr(predicate::str::contains("no Kylefile").not());
+        .failure()
+        .stdout(predicate::str::contains("server-stopped"))
+        .stderr(predicate::str::contains("task 'serve' interrupted"));
 }
 
 #[test]
-fn makefile_no_kylefile_warning() {
+fn vars_are_interpolated() {
     let temp = TempDir::new().unwrap();
+    fs::create_dir(temp.path().join("web")).unwrap();
+    let kylefile = temp.path().join("Kylefile");
     fs::write(
-        temp.path().join("Makefile"),
-        "build:\n\techo building-from-makefile\n",
+        &kylefile,
+        r#"# kyle: toml
+name = "test"
+
+[vars]
+IMAGE = "acme/app"
+SHA = { sh = "echo abc123" }
+TARGET = "web"
+
+[tasks.prepare-web]
+run = "echo preparing"
+
+[tasks.build]
+deps = ["prepare-{{ .TARGET }}"]
+dir = "{{ .TARGET }}"
+env = { TAG = "{{ .IMAGE }}:{{ .SHA }}" }
+run = "echo image=$TAG in=$(basename $PWD) channel={{ .CHANNEL }}"
+vars = { CHANNEL = "beta" }
+"#,
     )
     .unwrap();
 
@@ -412,27 +579,1475 @@ fn makefile_no_kylefile_warning() {
         .arg("build")
         .assert()
         .success()
-        .stdout(predicate::str::contains("building-from-makefile"))
-        .stderr(predicate::str::contains("no Kylefile").not());
+        .stdout(predicate::str::contains("preparing"))
+        .stdout(predicate::str::contains(
+            "image=acme/app:abc123 in=web channel=beta",
+        ));
 }
 
 #[test]
-fn cargo_toml_no_kylefile_warning() {
+fn undefined_var_error() {
     let temp = TempDir::new().unwrap();
+    let kylefile = temp.path().join("Kylefile");
     fs::write(
-        temp.path().join("Cargo.toml"),
-        "[package]\nname = \"test\"\nversion = \"0.1.0\"\n",
+        &kylefile,
+        r#"# kyle: yaml
+name: test
+
+tasks:
+  push:
+    run: docker push {{ .IMAGE }}
+"#,
     )
     .unwrap();
 
     kyle()
         .current_dir(temp.path())
+        .arg("push")
         .assert()
-        .success()
+        .failure()
+        .stderr(predicate::str::contains(
+            "task 'push': undefined variable 'IMAGE' in `docker push {{ .IMAGE }}`",
+        ));
+}
+
+#[test]
+fn makefile_braces_are_not_templates() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("Makefile"), "ps:\n\t@echo '{{.Names}}'\n").unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("ps")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("{{.Names}}"));
+}
+
+#[test]
+fn platform_specific_tasks() {
+    let temp = TempDir::new().unwrap();
+    let kylefile = temp.path().join("Kylefile");
+    fs::write(
+        &kylefile,
+        format!(
+            r#"# kyle: toml
+name = "test"
+
+[tasks.mainframe]
+desc = "Only on plan9"
+platforms = ["plan9"]
+run = "echo plan9"
+
+[tasks.open]
+run.plan9 = "echo plan9"
+run.{os} = "echo native-open"
+run.default = "echo fallback-open"
+
+[tasks.fallback]
+run.plan9 = "echo plan9"
+run.default = "echo fallback-run"
+"#,
+            os = std::env::consts::OS
+        ),
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("open"))
+        .stdout(predicate::str::contains("mainframe").not());
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("open")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("native-open"));
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("fallback")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("fallback-run"));
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("mainframe")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "task 'mainframe' is not available on",
+        ))
+        .stderr(predicate::str::contains("(platforms: plan9)"));
+}
+
+#[test]
+fn extends_shared_kylefile() {
+    let temp = TempDir::new().unwrap();
+    let project = temp.path().join("project");
+    let shared = temp.path().join("shared");
+    fs::create_dir_all(&project).unwrap();
+    fs::create_dir_all(&shared).unwrap();
+    fs::write(
+        shared.join("Kylefile.toml"),
+        r#"[vars]
+LINTER = "shared-linter"
+
+[tasks.lint]
+desc = "Lint everything"
+run = "echo running {{ .LINTER }}"
+
+[tasks.build]
+run = "echo shared-build"
+"#,
+    )
+    .unwrap();
+    fs::write(
+        project.join("Kylefile"),
+        r#"# kyle: toml
+name = "test"
+extends = ["../shared/Kylefile.toml"]
+
+[tasks.build]
+run = "echo local-build"
+"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(&project)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lint - Lint everything (from "))
+        .stdout(predicate::str::contains("shared/Kylefile.toml)"));
+
+    kyle()
+        .current_dir(&project)
+        .arg("lint")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("running shared-linter"));
+
+    kyle()
+        .current_dir(&project)
+        .arg("build")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("local-build"));
+}
+
+#[test]
+fn kylefile_requiring_newer_kyle() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Kylefile"),
+        "# kyle: toml\nversion = \"999.0.0\"\n\n[tasks.build]\nrun = \"echo build\"\nshiny = { new = true }\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "this Kylefile needs kyle >= 999.0.0",
+        ))
+        .stderr(predicate::str::contains("run `kyle upgrade`"));
+}
+
+#[test]
+fn json_kylefile() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Kylefile.json"),
+        r#"{
+  "name": "test",
+  "tasks": {
+    "build": { "desc": "Build it", "run": "echo json-build" },
+    "test": { "run": "echo json-test", "deps": ["build"] }
+  }
+}"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("test")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("json-build"))
+        .stdout(predicate::str::contains("json-test"));
+}
+
+#[test]
+fn global_tasks_merge_beneath_project() {
+    let home = TempDir::new().unwrap();
+    let config = home.path().join(".config/kyle");
+    fs::create_dir_all(&config).unwrap();
+    fs::write(
+        config.join("Kylefile.toml"),
+        r#"[tasks.prune]
+desc = "Prune docker"
+run = "echo global-prune"
+
+[tasks.build]
+run = "echo global-build"
+"#,
+    )
+    .unwrap();
+
+    let project = TempDir::new().unwrap();
+    fs::write(
+        project.path().join("Kylefile"),
+        "# kyle: toml\nname = \"test\"\n\n[tasks.build]\nrun = \"echo local-build\"\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(project.path())
+        .env("HOME", home.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Available tasks:\n  build\n"))
+        .stdout(predicate::str::contains(
+            "Global tasks:\n  prune - Prune docker",
+        ));
+
+    kyle()
+        .current_dir(project.path())
+        .env("HOME", home.path())
+        .arg("build")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("local-build"));
+
+    kyle()
+        .current_dir(project.path())
+        .env("HOME", home.path())
+        .arg("~:build")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("global-build"));
+
+    kyle()
+        .current_dir(project.path())
+        .env("HOME", home.path())
+        .arg("prune")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("global-prune"));
+}
+
+#[test]
+fn global_task_deps_stay_global() {
+    let home = TempDir::new().unwrap();
+    let config = home.path().join(".config/kyle");
+    fs::create_dir_all(&config).unwrap();
+    fs::write(
+        config.join("Kylefile.toml"),
+        r#"[tasks.clean]
+run = "echo global-clean"
+
+[tasks.prune]
+deps = ["clean"]
+run = ["echo global-prune", { task = "clean" }]
+"#,
+    )
+    .unwrap();
+
+    let project = TempDir::new().unwrap();
+    fs::write(
+        project.path().join("Kylefile"),
+        "# kyle: toml\nname = \"test\"\n\n[tasks.clean]\nrun = \"echo project-clean\"\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(project.path())
+        .env("HOME", home.path())
+        .arg("prune")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("global-clean"))
+        .stdout(predicate::str::contains("global-prune"))
+        .stdout(predicate::str::contains("project-clean").not());
+}
+
+#[test]
+fn global_tasks_without_project_file() {
+    let home = TempDir::new().unwrap();
+    let config = home.path().join(".config/kyle");
+    fs::create_dir_all(&config).unwrap();
+    fs::write(
+        config.join("Kylefile.yaml"),
+        "tasks:\n  where:\n    run: pwd\n",
+    )
+    .unwrap();
+
+    let empty = TempDir::new().unwrap();
+    kyle()
+        .current_dir(empty.path())
+        .env("HOME", home.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Global tasks:\n  where"))
+        .stdout(predicate::str::contains("Available tasks:").not());
+
+    let dir_name = empty.path().file_name().unwrap().to_str().unwrap();
+    kyle()
+        .current_dir(empty.path())
+        .env("HOME", home.path())
+        .arg("where")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(dir_name));
+}
+
+// =============================================================================
+// Config Commands
+// =============================================================================
+
+#[test]
+fn config_list() {
+    kyle()
+        .arg("config")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("default_format"));
+}
+
+#[test]
+fn config_get() {
+    kyle()
+        .arg("config")
+        .arg("get")
+        .arg("default_format")
+        .assert()
+        .success();
+}
+
+#[test]
+fn config_get_unknown_key() {
+    kyle()
+        .arg("config")
+        .arg("get")
+        .arg("unknown_key")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown config key"));
+}
+
+#[test]
+fn config_path() {
+    kyle()
+        .arg("config")
+        .arg("path")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("config.toml"));
+}
+
+// =============================================================================
+// Init Command
+// =============================================================================
+
+#[test]
+fn init_with_name_toml() {
+    let temp = TempDir::new().unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("init")
+        .arg("myproject")
+        .arg("--toml")
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created Kylefile"));
+
+    let kylefile = temp.path().join("Kylefile");
+    assert!(kylefile.exists());
+
+    let content = fs::read_to_string(&kylefile).unwrap();
+    assert!(content.contains("# kyle: toml"));
+    assert!(content.starts_with("#:schema https://kylefile.dev/schema.json\n"));
+    assert!(content.contains("version = "));
+    assert!(content.contains("name = \"myproject\""));
+
+    // The format header is still found after the schema directive
+    kyle().current_dir(temp.path()).assert().success();
+}
+
+#[test]
+fn init_with_name_yaml() {
+    let temp = TempDir::new().unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("init")
+        .arg("myproject")
+        .arg("--yaml")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created Kylefile"));
+
+    let kylefile = temp.path().join("Kylefile");
+    assert!(kylefile.exists());
+
+    let content = fs::read_to_string(&kylefile).unwrap();
+    assert!(content.contains("# kyle: yaml"));
+    assert!(content.contains("# yaml-language-server: $schema=https://kylefile.dev/schema.json"));
+    assert!(content.contains("version:"));
+    assert!(content.contains("name: myproject"));
+}
+
+#[test]
+fn init_with_name_json() {
+    let temp = TempDir::new().unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("init")
+        .arg("myproject")
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created Kylefile"));
+
+    let content = fs::read_to_string(temp.path().join("Kylefile")).unwrap();
+    assert!(content.starts_with("# kyle: json\n"));
+    assert!(content.contains("\"name\": \"myproject\""));
+
+    kyle().current_dir(temp.path()).assert().success();
+}
+
+#[test]
+fn init_yes_prefills_detected_tasks_and_subprojects() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Cargo.toml"),
+        "[package]\nname = \"demo\"\n",
+    )
+    .unwrap();
+    fs::write(
+        temp.path().join("package.json"),
+        r#"{"scripts": {"build": "tsc", "lint": "eslint ."}}"#,
+    )
+    .unwrap();
+    fs::create_dir_all(temp.path().join("services/api")).unwrap();
+    fs::write(
+        temp.path().join("services/api/Makefile"),
+        "build:\n\tgo build\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["init", "demo", "--toml", "--yes"])
+        .env("HOME", temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created Kylefile"));
+
+    let content = fs::read_to_string(temp.path().join("Kylefile")).unwrap();
+    // package.json comes first in detection order, so its build wins
+    assert!(content.contains("[tasks.build]\nrun = \"npm run build\"\n"));
+    assert!(content.contains("[tasks.lint]\nrun = \"npm run lint\"\n"));
+    assert!(content.contains("[tasks.clippy]\ndesc = \"Run linter\"\nrun = \"cargo clippy\"\n"));
+    assert!(content.contains("[includes]\n\"services/api\" = \"services/api\"\n"));
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["init", "--yes"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Kylefile already exists"));
+}
+
+#[test]
+fn init_yes_skips_reserved_task_names() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Cargo.toml"),
+        "[package]\nname = \"demo\"\n",
+    )
+    .unwrap();
+    fs::write(
+        temp.path().join("package.json"),
+        r#"{"scripts": {"check": "tsc --noEmit", "fmt": "prettier -w ."}}"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["init", "demo", "--toml", "--yes"])
+        .env("HOME", temp.path())
+        .assert()
+        .success();
+
+    let content = fs::read_to_string(temp.path().join("Kylefile")).unwrap();
+    assert!(content.contains("[tasks.format]\n"));
+    assert!(!content.contains("[tasks.check]"));
+    assert!(!content.contains("[tasks.fmt]"));
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("check")
+        .env("HOME", temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("no problems found"));
+}
+
+#[test]
+fn init_prompts_for_each_suggestion() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("go.mod"), "module demo\n").unwrap();
+    fs::write(
+        temp.path().join("justfile"),
+        "# Serve it\nserve:\n    go run .\n",
+    )
+    .unwrap();
+
+    // Decline the justfile, accept go.mod, then add no tasks of our own
+    kyle()
+        .current_dir(temp.path())
+        .args(["init", "demo", "--yaml"])
+        .env("HOME", temp.path())
+        .write_stdin("n\ny\nn\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Add 1 task(s) from justfile (serve)?",
+        ))
+        .stdout(predicate::str::contains(
+            "Add 5 task(s) from go.mod (build, test, run, vet, format)?",
+        ));
+
+    let content = fs::read_to_string(temp.path().join("Kylefile")).unwrap();
+    assert!(content.contains("  vet:\n    desc: Run vet\n    run: go vet ./...\n"));
+    assert!(!content.contains("just serve"));
+}
+
+// =============================================================================
+// Import Command
+// =============================================================================
+
+#[test]
+fn import_makefile_keeps_order_and_notes_losses() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Makefile"),
+        "include rules.mk\n\n# Run the tests\ntest: lint\n\techo import-test\n\nlint:\n\techo import-lint\n\n# Build it\nbuild: $(OBJS)\n\t@$(CC) -o app $(wildcard *.c)\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["import", "--format", "toml"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Imported 3 tasks from Makefile into Kylefile",
+        ))
+        .stderr(predicate::str::contains(
+            "Makefile:1: included file not found: rules.mk",
+        ))
+        .stderr(predicate::str::contains(
+            "build: make functions are not expanded: $(wildcard *.c)",
+        ));
+
+    let content = fs::read_to_string(temp.path().join("Kylefile")).unwrap();
+    assert!(content.contains("# kyle: toml"));
+    assert!(content.find("[tasks.test]").unwrap() < content.find("[tasks.build]").unwrap());
+    assert!(content.contains("desc = \"Run the tests\""));
+    assert!(content.contains("deps = [\"lint\"]"));
+    assert!(content.contains(
+        "# kyle import: make functions are not expanded: $(wildcard *.c)\n\
+         [tasks.build]"
+    ));
+    assert!(content.contains("cc -o app $(wildcard *.c)"));
+
+    // The Kylefile now takes priority over the Makefile
+    kyle()
+        .current_dir(temp.path())
+        .arg("test")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("import-lint"))
+        .stdout(predicate::str::contains("import-test"));
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("import")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Kylefile already exists"));
+}
+
+#[test]
+fn import_keeps_an_existing_kylefile_of_any_name() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("Makefile"), "build:\n\techo make-build\n").unwrap();
+    fs::write(
+        temp.path().join("Kylefile.toml"),
+        "name = \"demo\"\n\n[tasks.build]\nrun = \"echo kyle-build\"\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["import", "--from", "Makefile"])
+        .env("HOME", temp.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Kylefile.toml already exists"));
+    assert!(!temp.path().join("Kylefile").exists());
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["import", "--from", "Makefile", "--force"])
+        .env("HOME", temp.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Kylefile now takes priority over Kylefile.toml",
+        ));
+    assert!(temp.path().join("Kylefile").exists());
+}
+
+#[test]
+fn import_justfile_as_yaml() {
+    let temp = TempDir::new().unwrap();
+    fs::create_dir(temp.path().join("tools")).unwrap();
+    fs::write(
+        temp.path().join("tools/justfile"),
+        "greeting := \"hello\"\n\n# Say hello\nhello name:\n    echo {{greeting}}\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["import", "--from", "tools/justfile", "--format", "yaml"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Imported 1 task from tools/justfile into Kylefile",
+        ));
+
+    let content = fs::read_to_string(temp.path().join("Kylefile")).unwrap();
+    assert!(content.contains("vars:\n  greeting: hello\n"));
+    assert!(content.contains("  hello:\n    desc: Say hello\n    params:\n    - name\n"));
+    assert!(!content.contains("kyle import:"));
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["hello", "world"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hello"));
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("hello")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "task 'hello' needs a value for 'name'",
+        ));
+}
+
+// =============================================================================
+// Export Command
+// =============================================================================
+
+#[test]
+fn export_makefile() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Kylefile.toml"),
+        "[tasks.build]\ndesc = \"Build it\"\nrun = \"cargo build\"\ndeps = [\"lint\"]\n\n[tasks.lint]\nrun = \"cargo clippy\"\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["export", "--to", "makefile"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "# Build it\nbuild:\n\t@kyle build\n",
+        ));
+
+    kyle()
+        .current_dir(temp.path())
+        .args([
+            "export", "--to", "makefile", "--inline", "--output", "Makefile",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exported 2 tasks to Makefile"));
+
+    let content = fs::read_to_string(temp.path().join("Makefile")).unwrap();
+    assert!(content.contains("# Build it\nbuild: lint\n\t@cargo build\n"));
+
+    // Regenerating replaces a generated file, but never a hand-written one
+    kyle()
+        .current_dir(temp.path())
+        .args(["export", "--to", "makefile", "--output", "Makefile"])
+        .assert()
+        .success();
+    fs::write(temp.path().join("justfile"), "build:\n    cargo build\n").unwrap();
+    kyle()
+        .current_dir(temp.path())
+        .args(["export", "--to", "justfile", "--output", "justfile"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "justfile already exists and wasn't generated by kyle export",
+        ));
+}
+
+#[test]
+fn export_merges_package_json_scripts() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Kylefile.toml"),
+        "[tasks.build]\nrun = \"tsc\"\n",
+    )
+    .unwrap();
+    fs::write(
+        temp.path().join("package.json"),
+        r#"{"name": "app", "scripts": {"start": "node ."}}"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["export", "--to", "package-json", "--output", "package.json"])
+        .assert()
+        .success();
+
+    let content = fs::read_to_string(temp.path().join("package.json")).unwrap();
+    let package: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert_eq!(package["name"], "app");
+    assert_eq!(package["scripts"]["start"], "node .");
+    assert_eq!(package["scripts"]["build"], "kyle build");
+}
+
+#[test]
+fn export_needs_a_kylefile() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("justfile"), "build:\n    cargo build\n").unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["export", "--to", "makefile"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("run `kyle import` first"));
+}
+
+// =============================================================================
+// Check Command
+// =============================================================================
+
+#[test]
+fn check_valid_kylefile() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Kylefile"),
+        "# kyle: toml\nname = \"test\"\n\n[tasks.build]\nrun = \"echo build\"\n\n[tasks.test]\nrun = \"echo test\"\ndeps = [\"build\"]\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("check")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("no problems found"));
+}
+
+#[test]
+fn check_reports_problems_with_location() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Kylefile.yaml"),
+        "tasks:\n  build:\n    run: echo build\n    depends: [lint]\n  lint:\n    run: \"\"\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("check")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "unknown key 'depends' in task 'build' (did you mean 'deps'?)",
+        ))
+        .stderr(predicate::str::contains("--> Kylefile.yaml:4:5"))
+        .stderr(predicate::str::contains("4 |     depends: [lint]"))
+        .stderr(predicate::str::contains("task 'lint' has an empty `run`"))
+        .stderr(predicate::str::contains(
+            "2 problems found in Kylefile.yaml",
+        ));
+}
+
+// =============================================================================
+// Fmt Command
+// =============================================================================
+
+#[test]
+fn fmt_check_then_rewrite() {
+    let temp = TempDir::new().unwrap();
+    let content =
+        "# kyle: toml\n\n[tasks.build]\n# compile\nrun   =   \"cargo build\"\ndesc = \"Build\"\n";
+    fs::write(temp.path().join("Kylefile"), content).unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["fmt", "--check"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Kylefile is not formatted; run `kyle fmt`",
+        ));
+    assert_eq!(
+        fs::read_to_string(temp.path().join("Kylefile")).unwrap(),
+        content
+    );
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("fmt")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Formatted Kylefile"));
+    assert_eq!(
+        fs::read_to_string(temp.path().join("Kylefile")).unwrap(),
+        "# kyle: toml\n[tasks.build]\ndesc = \"Build\"\n# compile\nrun = \"cargo build\"\n"
+    );
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["fmt", "--check"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("already formatted"));
+}
+
+#[test]
+fn fmt_refuses_other_task_files() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("Makefile"), "build:\n\tmake\n").unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("fmt")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "kyle fmt only formats Kylefiles, not Makefile",
+        ));
+}
+
+// =============================================================================
+// Add & Rm Commands
+// =============================================================================
+
+#[test]
+fn add_and_rm_keep_comments() {
+    let temp = TempDir::new().unwrap();
+    let content = "# kyle: toml\nname = \"demo\"\n\n# Compile everything\n[tasks.build]\nrun = \"cargo build\"  # debug\n";
+    fs::write(temp.path().join("Kylefile"), content).unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["add", "test", "--run", "cargo test", "--desc", "Run tests"])
+        .args(["--dep", "build"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Added 'test' to Kylefile"));
+    let added = fs::read_to_string(temp.path().join("Kylefile")).unwrap();
+    assert_eq!(
+        added,
+        format!(
+            "{content}\n[tasks.test]\ndesc = \"Run tests\"\nrun = \"cargo test\"\ndeps = [\"build\"]\n"
+        )
+    );
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["add", "test", "--run", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("task 'test' already exists"));
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["rm", "build"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 'build' from Kylefile"))
+        .stderr(predicate::str::contains("'test' still depends on 'build'"));
+    assert_eq!(
+        fs::read_to_string(temp.path().join("Kylefile")).unwrap(),
+        "# kyle: toml\nname = \"demo\"\n\n[tasks.test]\ndesc = \"Run tests\"\nrun = \"cargo test\"\ndeps = [\"build\"]\n"
+    );
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["rm", "build"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("task 'build' not found"));
+}
+
+#[test]
+fn add_to_yaml_kylefile() {
+    let temp = TempDir::new().unwrap();
+    let content = "# kyle: yaml\ntasks:\n  # Compile\n  build:\n    run: echo built\n";
+    fs::write(temp.path().join("Kylefile"), content).unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args([
+            "add", "ci", "--dep", "build", "--run", "echo one", "--run", "echo two",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp.path().join("Kylefile")).unwrap(),
+        format!(
+            "{content}\n  ci:\n    run:\n    - echo one\n    - echo two\n    deps:\n    - build\n"
+        )
+    );
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("ci")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("one"));
+}
+
+#[test]
+fn add_and_rm_refuse_other_task_files() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("Makefile"), "build:\n\tmake\n").unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .args(["add", "test", "--run", "make test"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "kyle add only edits Kylefiles, not Makefile",
+        ));
+    kyle()
+        .current_dir(temp.path())
+        .args(["rm", "build"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "kyle rm only edits Kylefiles, not Makefile",
+        ));
+    kyle()
+        .current_dir(temp.path())
+        .args(["add", "check", "--run", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'check' is a kyle command"));
+}
+
+// =============================================================================
+// Schema
+// =============================================================================
+
+#[test]
+fn schema_matches_published_file() {
+    let published = include_str!("../docs/public/schema.json");
+    kyle()
+        .arg("schema")
+        .assert()
+        .success()
+        .stdout(predicate::eq(published));
+}
+
+// =============================================================================
+// MCP
+// =============================================================================
+
+#[test]
+fn mcp_config_output() {
+    kyle()
+        .arg("mcp")
+        .arg("--config")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("mcpServers"))
+        .stdout(predicate::str::contains("\"kyle\""))
+        .stdout(predicate::str::contains("\"mcp\""));
+}
+
+// =============================================================================
+// Non-Kylefile Sources (no spurious warning)
+// =============================================================================
+
+#[test]
+fn package_json_no_kylefile_warning() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("package.json"),
+        r#"{"name":"test","scripts":{"build":"echo building"}}"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("build")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("building"))
+        .stderr(predicate::str::contains("no Kylefile").not());
+}
+
+#[test]
+fn package_json_list_no_warning() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("package.json"),
+        r#"{"name":"test","scripts":{"build":"echo b","test":"echo t"}}"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("build"))
+        .stderr(predicate::str::contains("no Kylefile").not());
+}
+
+#[test]
+fn makefile_no_kylefile_warning() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Makefile"),
+        "build:\n\techo building-from-makefile\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .arg("build")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("building-from-makefile"))
+        .stderr(predicate::str::contains("no Kylefile").not());
+}
+
+#[test]
+fn cargo_toml_no_kylefile_warning() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Cargo.toml"),
+        "[package]\nname = \"test\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .assert()
+        .success()
         .stdout(predicate::str::contains("build"))
         .stderr(predicate::str::contains("no Kylefile").not());
 }
 
+// =============================================================================
+// Native Tool Delegation
+// =============================================================================
+
+#[test]
+fn package_json_delegates_to_npm_when_installed() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("package.json"),
+        r#"{"scripts":{"build":"echo scraped"}}"#,
+    )
+    .unwrap();
+
+    // A stand-in npm, found first because node_modules/.bin leads PATH
+    let bin_dir = temp.path().join("node_modules/.bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let npm = bin_dir.join("npm");
+    fs::write(&npm, "#!/bin/sh\necho \"npm $*\"\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&npm, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .args(["build", "--watch"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("npm run build -- --watch"));
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .args(["--no-delegate", "build"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("scraped"));
+
+    kyle()
+        .env("HOME", temp.path())
+        .args(["config", "set", "delegate", "false"])
+        .assert()
+        .success();
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .arg("build")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("scraped"));
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .args(["--delegate", "build"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("npm run build"));
+}
+
+#[cfg(unix)]
+#[test]
+fn delegated_make_and_rake_take_arguments_their_way() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let bin = temp.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    for tool in ["make", "rake"] {
+        let path = bin.join(tool);
+        fs::write(&path, format!("#!/bin/sh\necho \"{tool}-called $*\"\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = format!("{}:/usr/bin:/bin", bin.display());
+
+    let make = temp.path().join("make");
+    fs::create_dir(&make).unwrap();
+    fs::write(make.join("Makefile"), "greet:\n\techo hello\n").unwrap();
+    kyle()
+        .current_dir(&make)
+        .env("HOME", temp.path())
+        .env("PATH", &path)
+        .arg("greet")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("make-called greet"));
+    // make would read `world` as a second target, so kyle runs the recipe
+    kyle()
+        .current_dir(&make)
+        .env("HOME", temp.path())
+        .env("PATH", &path)
+        .args(["greet", "world"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hello world"))
+        .stdout(predicate::str::contains("make-called").not());
+
+    let rake = temp.path().join("rake");
+    fs::create_dir(&rake).unwrap();
+    fs::write(
+        rake.join("Rakefile"),
+        "task :deploy, [:env, :region] do |t, args|\n  sh \"echo deploying\"\nend\n",
+    )
+    .unwrap();
+    kyle()
+        .current_dir(&rake)
+        .env("HOME", temp.path())
+        .env("PATH", &path)
+        .args(["deploy", "prod", "eu,west"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            r"rake-called deploy[prod,eu\,west]",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn rakefile_namespaces_run_without_rake() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Rakefile"),
+        r#"APP = "shop"
+
+task :environment do
+  sh "echo loading-env"
+end
+
+namespace :db do
+  desc "Migrate #{APP}"
+  task migrate: :environment do
+    sh "echo migrating"
+  end
+
+  task :seed => [:migrate] do
+    puts "seeded"
+  end
+end
+"#,
+    )
+    .unwrap();
+    // Only a shell on PATH, so kyle can't hand the task to rake
+    let bin = temp.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    std::os::unix::fs::symlink("/bin/sh", bin.join("sh")).unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .env("PATH", &bin)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("db:migrate - Migrate shop"));
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .env("PATH", &bin)
+        .arg("db:seed")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("loading-env"))
+        .stdout(predicate::str::contains("migrating"))
+        .stdout(predicate::str::contains("seeded"));
+}
+
+#[test]
+fn taskfile_defer_only_runs_once_reached() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("Taskfile.yml"),
+        r#"version: '3'
+tasks:
+  stop:
+    cmds:
+      - echo stopped
+  serve:
+    cmds:
+      - defer: { task: stop }
+      - echo serving
+      - exit 3
+      - defer: echo never-registered
+"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .args(["--no-delegate", "serve"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("serving"))
+        .stdout(predicate::str::contains("stopped"))
+        .stdout(predicate::str::contains("never-registered").not());
+}
+
+#[test]
+fn taskfile_includes_run_as_namespaced_tasks() {
+    let temp = TempDir::new().unwrap();
+    fs::create_dir(temp.path().join("docs")).unwrap();
+    fs::write(
+        temp.path().join("docs/Taskfile.yml"),
+        "version: '3'\ntasks:\n  build:\n    cmds:\n      - echo building in $(basename $PWD)\n",
+    )
+    .unwrap();
+    fs::write(
+        temp.path().join("taskfile.dist.yml"),
+        r#"version: '3'
+includes:
+  docs:
+    taskfile: ./docs
+    dir: ./docs
+tasks:
+  all:
+    desc: Build everything
+    deps: [docs:build]
+    cmds:
+      - echo args {{.CLI_ARGS}}
+"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .args(["--no-delegate", "all", "fast"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("building in docs"))
+        .stdout(predicate::str::contains("args fast"));
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Build everything"))
+        .stdout(predicate::str::contains("docs:build"));
+}
+
+#[test]
+fn package_json_scripts_run_with_hooks_and_npm_env() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("package.json"),
+        r#"{"name":"app","version":"1.2.0","scripts":{"prebuild":"echo pre","build":"echo build $npm_package_name@$npm_package_version $npm_lifecycle_event","postbuild":"echo post"}}"#,
+    )
+    .unwrap();
+    fs::write(temp.path().join("yarn.lock"), "").unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Available tasks (yarn):"))
+        .stdout(predicate::str::contains("prebuild").not());
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .args(["--no-delegate", "build"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("pre\nbuild app@1.2.0 build\n"))
+        .stdout(predicate::str::contains("→ postbuild\npost\n"));
+}
+
+#[test]
+fn composer_script_references_run_as_tasks() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("composer.json"),
+        r#"{"scripts":{"lint":"echo linted","test":["@putenv MODE=ci","echo tested $MODE"],"qa":["@lint","@test"],"post-install-cmd":"@qa"},"scripts-descriptions":{"qa":"Lint and test"}}"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Lint and test"))
+        .stdout(predicate::str::contains("post-install-cmd").not());
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .args(["--no-delegate", "qa"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("linted"))
+        .stdout(predicate::str::contains("tested ci"));
+}
+
+#[test]
+fn deno_jsonc_task_objects_run_with_dependencies() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("deno.jsonc"),
+        r#"{
+  // Editor comments and trailing commas are fine
+  "tasks": {
+    "gen": "echo generated",
+    "build": {
+      "description": "Build the app",
+      "command": "echo built",
+      "dependencies": ["gen"],
+    },
+  },
+}"#,
+    )
+    .unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Build the app"));
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .args(["--no-delegate", "build"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("generated"))
+        .stdout(predicate::str::contains("built"));
+}
+
+#[test]
+fn delegation_falls_back_without_the_tool() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("justfile"),
+        "hello:\n    echo from-recipe\n",
+    )
+    .unwrap();
+
+    // No `just` on this PATH, so kyle runs the recipe text itself
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .env("PATH", "/usr/bin:/bin")
+        .arg("hello")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("from-recipe"));
+}
+
+#[cfg(unix)]
+#[test]
+fn delegation_skips_a_tool_that_cannot_run() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("justfile"),
+        "hello:\n    echo from-recipe\n",
+    )
+    .unwrap();
+    // A `just` on PATH without the executable bit can't be delegated to
+    let bin = temp.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(bin.join("just"), "").unwrap();
+
+    kyle()
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .env("PATH", format!("{}:/usr/bin:/bin", bin.display()))
+        .arg("hello")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("from-recipe"));
+}
+
 // =============================================================================
 // Local bin PATH injection (node_modules/.bin, vendor/bin, .venv/bin)
 // =============================================================================
@@ -9,7 +9,7 @@ $KYLE | grep -q "test" && pass "  - has test task" || fail "  - has test task"
 
 echo ""
 echo "=== Task Execution ==="
-$KYLE check > /dev/null 2>&1 && pass "kyle check" || fail "kyle check"
+$KYLE typecheck > /dev/null 2>&1 && pass "kyle typecheck" || fail "kyle typecheck"
 
 echo ""
 echo "=== Error Handling ==="